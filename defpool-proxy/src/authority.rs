@@ -0,0 +1,103 @@
+use crate::config::AuthorityConfig;
+use crate::proxy::Secp256k1PublicKey;
+use anyhow::{Context, Result};
+use secp256k1::{Secp256k1, SecretKey};
+use std::io::Write;
+use std::path::Path;
+use tracing::info;
+
+/// Long-lived SV2 authority keypair the proxy signs its Noise certificates with.
+///
+/// Miners pin the public key; every handshake gets a freshly signed
+/// certificate, so certificates renew themselves as connections come in.
+#[derive(Clone, Copy)]
+pub struct AuthorityKeypair {
+    public: Secp256k1PublicKey,
+    secret: SecretKey,
+}
+
+impl AuthorityKeypair {
+    /// Load the key from config or the key file, generating the file on first run
+    pub fn load_or_generate(config: &AuthorityConfig) -> Result<Self> {
+        if let Some(secret) = &config.secret_key {
+            return Self::from_secret_str(secret).context("Invalid authority secret_key in config");
+        }
+
+        let path = Path::new(&config.key_file);
+        if path.exists() {
+            let secret = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read authority key file {}", config.key_file))?;
+            return Self::from_secret_str(secret.trim())
+                .with_context(|| format!("Invalid authority key file {}", config.key_file));
+        }
+
+        let keypair = Self::from_secret(SecretKey::new(&mut rand::thread_rng()));
+        keypair.write_key_file(path)?;
+        info!("Generated new SV2 authority key at {}", config.key_file);
+        Ok(keypair)
+    }
+
+    fn from_secret(secret: SecretKey) -> Self {
+        let (public, _) = secret.x_only_public_key(&Secp256k1::new());
+        Self {
+            public: Secp256k1PublicKey(public),
+            secret,
+        }
+    }
+
+    /// Parse a base58-check encoded secret key
+    fn from_secret_str(secret: &str) -> Result<Self> {
+        let decoded = bs58::decode(secret).with_check(None).into_vec()?;
+        Ok(Self::from_secret(SecretKey::from_slice(&decoded)?))
+    }
+
+    fn write_key_file(&self, path: &Path) -> Result<()> {
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        let mut file = options
+            .open(path)
+            .with_context(|| format!("Failed to create authority key file {}", path.display()))?;
+        let encoded = bs58::encode(self.secret.secret_bytes()).with_check().into_string();
+        writeln!(file, "{}", encoded)?;
+        Ok(())
+    }
+
+    /// Base58-check public key miners pin (same format `Secp256k1PublicKey` parses)
+    pub fn public_key(&self) -> Secp256k1PublicKey {
+        self.public
+    }
+
+    pub fn public_bytes(&self) -> [u8; 32] {
+        self.public.0.serialize()
+    }
+
+    pub fn secret_bytes(&self) -> [u8; 32] {
+        self.secret.secret_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_file_persists() {
+        let key_file = std::env::temp_dir().join(format!("defpool-authority-{}.key", std::process::id()));
+        let _ = std::fs::remove_file(&key_file);
+        let config = AuthorityConfig {
+            key_file: key_file.display().to_string(),
+            ..Default::default()
+        };
+
+        let generated = AuthorityKeypair::load_or_generate(&config).unwrap();
+        let loaded = AuthorityKeypair::load_or_generate(&config).unwrap();
+        std::fs::remove_file(&key_file).unwrap();
+
+        assert_eq!(generated.public_bytes(), loaded.public_bytes());
+        let parsed: Secp256k1PublicKey = generated.public_key().to_string().parse().unwrap();
+        assert_eq!(parsed.0, generated.public_key().0);
+    }
+}
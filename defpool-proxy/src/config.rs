@@ -6,6 +6,134 @@ pub struct Config {
     pub server_endpoint: String,
     pub listen_address: SocketAddr,
     pub default_wallet: Option<String>,
+    /// How often to poll the server for the target while its event stream is down
+    #[serde(default = "default_target_poll_interval")]
+    pub target_poll_interval_secs: u64,
+    /// Fail over to another endpoint when the upstream sends no job for this long
+    #[serde(default = "default_upstream_idle_timeout")]
+    pub upstream_idle_timeout_secs: u64,
+    /// Give up on an upstream endpoint that does not accept a connection in this long
+    #[serde(default = "default_upstream_connect_timeout")]
+    pub upstream_connect_timeout_secs: u64,
+    /// Directory shares are queued in until the server acknowledges them
+    #[serde(default = "default_share_spool_dir")]
+    pub share_spool_dir: String,
+    /// Shares sent to the server per request
+    #[serde(default = "default_share_batch_size")]
+    pub share_batch_size: usize,
+    /// Longest a spooled share waits for its batch to fill up
+    #[serde(default = "default_share_batch_interval")]
+    pub share_batch_interval_ms: u64,
+    /// Proxy-side variable difficulty for Bitcoin-style downstream miners
+    #[serde(default)]
+    pub vardiff: VardiffConfig,
+    /// SV2 authority key downstream miners authenticate the proxy with
+    #[serde(default)]
+    pub authority: AuthorityConfig,
+    /// Key this proxy signs internal server API requests with
+    #[serde(default)]
+    pub server_auth: ServerAuthConfig,
+}
+
+fn default_target_poll_interval() -> u64 {
+    10
+}
+
+fn default_upstream_idle_timeout() -> u64 {
+    300
+}
+
+fn default_upstream_connect_timeout() -> u64 {
+    5
+}
+
+fn default_share_spool_dir() -> String {
+    "share-spool".to_string()
+}
+
+fn default_share_batch_size() -> usize {
+    100
+}
+
+fn default_share_batch_interval() -> u64 {
+    1000
+}
+
+/// Variable difficulty settings, applied per downstream session
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct VardiffConfig {
+    pub enabled: bool,
+    /// Desired average time between shares of one miner
+    pub target_share_time_secs: f64,
+    /// How often the difficulty is re-evaluated
+    pub retarget_time_secs: u64,
+    pub min_difficulty: f64,
+    pub max_difficulty: f64,
+}
+
+impl Default for VardiffConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            target_share_time_secs: 15.0,
+            retarget_time_secs: 90,
+            min_difficulty: 1.0,
+            max_difficulty: 1e15,
+        }
+    }
+}
+
+/// SV2 authority key settings
+#[derive(Deserialize, Clone)]
+#[serde(default)]
+pub struct AuthorityConfig {
+    /// Base58-check secret key; takes precedence over `key_file`
+    pub secret_key: Option<String>,
+    /// Key file, created with a new key on first run
+    pub key_file: String,
+    /// Validity of the certificate signed for each SV2 handshake
+    pub cert_validity_secs: u64,
+}
+
+impl Default for AuthorityConfig {
+    fn default() -> Self {
+        Self {
+            secret_key: None,
+            key_file: "defpool-proxy.key".to_string(),
+            cert_validity_secs: 3600,
+        }
+    }
+}
+
+// Keep the secret key out of logs
+impl std::fmt::Debug for AuthorityConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuthorityConfig")
+            .field("secret_key", &self.secret_key.as_ref().map(|_| "<redacted>"))
+            .field("key_file", &self.key_file)
+            .field("cert_validity_secs", &self.cert_validity_secs)
+            .finish()
+    }
+}
+
+/// Proxy identity on the server's internal API, issued with
+/// `defpool-server proxy-key issue <name>`
+#[derive(Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct ServerAuthConfig {
+    pub proxy_name: Option<String>,
+    pub key: Option<String>,
+}
+
+// Keep the key out of logs
+impl std::fmt::Debug for ServerAuthConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ServerAuthConfig")
+            .field("proxy_name", &self.proxy_name)
+            .field("key", &self.key.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
 }
 
 impl Config {
@@ -17,6 +145,9 @@ impl Config {
         if let Ok(endpoint) = std::env::var("DEFPOOL_SERVER_ENDPOINT") {
             config.server_endpoint = endpoint;
         }
+        if let Ok(key) = std::env::var("DEFPOOL_PROXY_KEY") {
+            config.server_auth.key = Some(key);
+        }
         
         Ok(config)
     }
@@ -0,0 +1,194 @@
+use crate::server_auth::ServerAuth;
+use crate::target::{fetch_target, Target};
+use anyhow::Result;
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+
+/// Command pushed by the server over `/api/v1/proxy/events`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ProxyEvent {
+    TargetChanged { target: Target },
+    ConfigUpdated { default_wallet: Option<String> },
+    Drain { reason: Option<String> },
+    Resume,
+}
+
+/// Server-controlled proxy state shared with every session
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProxyState {
+    /// Current upstream target, `None` until the server was reached once
+    pub target: Option<Target>,
+    /// Wallet used when a miner does not log in with one
+    pub default_wallet: Option<String>,
+    /// New miners are refused while draining
+    pub draining: bool,
+}
+
+impl ProxyState {
+    fn apply(&mut self, event: ProxyEvent) -> bool {
+        match event {
+            ProxyEvent::TargetChanged { target } => {
+                if target.name.is_empty() || target.address.is_empty() {
+                    warn!("Ignoring target change to an unnamed or addressless target: {:?}", target);
+                    return false;
+                }
+                if self.target.as_ref() == Some(&target) {
+                    return false;
+                }
+                info!("Target changed: {} ({})", target.name, target.address);
+                self.target = Some(target);
+            }
+            ProxyEvent::ConfigUpdated { default_wallet } => {
+                info!("Config updated by server");
+                self.default_wallet = default_wallet;
+            }
+            ProxyEvent::Drain { reason } => {
+                warn!("Draining: refusing new miners ({})", reason.as_deref().unwrap_or("no reason given"));
+                self.draining = true;
+            }
+            ProxyEvent::Resume => {
+                info!("Drain lifted: accepting new miners");
+                self.draining = false;
+            }
+        }
+        true
+    }
+}
+
+/// Subscribe to server commands.
+///
+/// Keeps an SSE connection to the server and reconnects with backoff when it
+/// drops. While disconnected the target is polled every `poll_interval` so
+/// sessions still follow switches.
+pub fn spawn_control_channel(
+    server_url: String,
+    auth: ServerAuth,
+    default_wallet: Option<String>,
+    poll_interval: Duration,
+) -> watch::Receiver<ProxyState> {
+    let (sender, receiver) = watch::channel(ProxyState {
+        default_wallet,
+        ..Default::default()
+    });
+
+    tokio::spawn(async move {
+        let client = reqwest::Client::new();
+        let mut backoff = Duration::from_secs(1);
+
+        loop {
+            match stream_events(&client, &auth, &server_url, &sender, &mut backoff).await {
+                Ok(()) => info!("Server event stream closed, reconnecting"),
+                Err(e) => warn!("Server event stream unavailable: {}", e),
+            }
+
+            // Resync by polling until the stream is back
+            match fetch_target(&server_url).await {
+                Ok(target) => {
+                    sender.send_if_modified(|state| state.apply(ProxyEvent::TargetChanged { target }));
+                }
+                Err(e) => debug!("Failed to poll target from server: {}", e),
+            }
+
+            tokio::time::sleep(backoff.min(poll_interval)).await;
+            backoff = (backoff * 2).min(Duration::from_secs(60));
+        }
+    });
+
+    receiver
+}
+
+/// Read events until the stream ends
+async fn stream_events(
+    client: &reqwest::Client,
+    auth: &ServerAuth,
+    server_url: &str,
+    sender: &watch::Sender<ProxyState>,
+    backoff: &mut Duration,
+) -> Result<()> {
+    let path = "/api/v1/proxy/events";
+    let url = format!("{}{}", server_url, path);
+    let request = client.get(&url).header("Accept", "text/event-stream");
+    let mut response = auth
+        .sign(request, "GET", path, &[])
+        .send()
+        .await?
+        .error_for_status()?;
+
+    info!("Subscribed to server events at {}", url);
+    *backoff = Duration::from_secs(1);
+
+    let mut buffer = String::new();
+    while let Some(chunk) = response.chunk().await? {
+        buffer.push_str(&String::from_utf8_lossy(&chunk));
+
+        while let Some(end) = buffer.find("\n\n") {
+            let frame: String = buffer.drain(..end + 2).collect();
+            if let Some(event) = parse_sse_frame(&frame) {
+                sender.send_if_modified(|state| state.apply(event));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Parse one SSE frame; keep-alive comments yield nothing
+fn parse_sse_frame(frame: &str) -> Option<ProxyEvent> {
+    let data: Vec<&str> = frame
+        .lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .map(|data| data.strip_prefix(' ').unwrap_or(data))
+        .collect();
+
+    if data.is_empty() {
+        return None;
+    }
+
+    match serde_json::from_str(&data.join("\n")) {
+        Ok(event) => Some(event),
+        Err(e) => {
+            warn!("Ignoring unknown server event: {}", e);
+            None
+        }
+    }
+}
+
+/// Get the latest known target, asking the server if none is known yet
+pub async fn current_target(server_url: &str, state: &watch::Receiver<ProxyState>) -> Result<Target> {
+    let known = state.borrow().target.clone();
+    match known {
+        Some(target) => Ok(target),
+        None => fetch_target(server_url).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_target_changed() {
+        let frame = "event: target_changed\ndata: {\"type\":\"target_changed\",\"target\":{\"name\":\"supportxmr\",\"address\":\"pool:3333\",\"pubkey\":null,\"protocol\":\"sv1\"}}\n\n";
+        let mut state = ProxyState::default();
+
+        assert!(state.apply(parse_sse_frame(frame).unwrap()));
+        assert_eq!(state.target.unwrap().name, "supportxmr");
+    }
+
+    #[test]
+    fn test_incomplete_target_ignored() {
+        let frame = "event: target_changed\ndata: {\"type\":\"target_changed\",\"target\":{\"address\":\"pool:3333\",\"pubkey\":null}}\n\n";
+        let mut state = ProxyState::default();
+
+        assert!(!state.apply(parse_sse_frame(frame).unwrap()));
+        assert!(state.target.is_none());
+    }
+
+    #[test]
+    fn test_keep_alive_ignored() {
+        assert!(parse_sse_frame(":\n\n").is_none());
+    }
+}
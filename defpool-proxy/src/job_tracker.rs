@@ -1,4 +1,5 @@
-use std::collections::HashMap;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, Mutex};
 use tracing::{debug, warn};
 
@@ -6,17 +7,14 @@ use tracing::{debug, warn};
 #[derive(Debug, Clone)]
 pub struct JobInfo {
     pub job_id: String,
-    pub target: String,
     pub difficulty: f64,
-    pub height: u64,
 }
 
 impl JobInfo {
-    /// Calculate difficulty from target hex string
+    /// Calculate difficulty from a Monero-style target hex string
     pub fn difficulty_from_target(target: &str) -> f64 {
-        // Target is a hex string representing the difficulty target
-        // Lower target = higher difficulty
-        // Difficulty = 0xFFFFFFFF / target_value
+        // Target is little-endian hex: 8 chars is the compact 32-bit form,
+        // 16 chars the full 64-bit form. Lower target = higher difficulty
         
         if target.is_empty() {
             return 1000.0; // Default difficulty
@@ -25,13 +23,26 @@ impl JobInfo {
         // Parse hex target (remove 0x prefix if present)
         let target_str = target.trim_start_matches("0x");
         
-        match u64::from_str_radix(target_str, 16) {
-            Ok(target_val) if target_val > 0 => {
-                let difficulty = 0xFFFFFFFFu64 as f64 / target_val as f64;
+        let difficulty = match target_str.len() {
+            8 => u32::from_str_radix(target_str, 16)
+                .ok()
+                .map(u32::swap_bytes)
+                .filter(|&target_val| target_val > 0)
+                .map(|target_val| u32::MAX as f64 / target_val as f64),
+            16 => u64::from_str_radix(target_str, 16)
+                .ok()
+                .map(u64::swap_bytes)
+                .filter(|&target_val| target_val > 0)
+                .map(|target_val| u64::MAX as f64 / target_val as f64),
+            _ => None,
+        };
+
+        match difficulty {
+            Some(difficulty) => {
                 debug!("Calculated difficulty {} from target {}", difficulty, target);
                 difficulty
             }
-            _ => {
+            None => {
                 warn!("Failed to parse target: {}, using default", target);
                 1000.0
             }
@@ -39,49 +50,71 @@ impl JobInfo {
     }
 }
 
+/// Jobs by id, plus their arrival order for eviction
+#[derive(Default)]
+struct Jobs {
+    by_id: HashMap<String, JobInfo>,
+    order: VecDeque<String>,
+}
+
 /// Tracks mining jobs and their difficulties
 pub struct JobTracker {
-    jobs: Arc<Mutex<HashMap<String, JobInfo>>>,
+    jobs: Arc<Mutex<Jobs>>,
     max_jobs: usize,
 }
 
 impl JobTracker {
     pub fn new(max_jobs: usize) -> Self {
         Self {
-            jobs: Arc::new(Mutex::new(HashMap::new())),
+            jobs: Arc::new(Mutex::new(Jobs::default())),
             max_jobs,
         }
     }
 
-    /// Add a new job
-    pub fn add_job(&self, job_id: String, target: String, height: u64) {
-        let difficulty = JobInfo::difficulty_from_target(&target);
-        
-        let job_info = JobInfo {
-            job_id: job_id.clone(),
-            target,
-            difficulty,
-            height,
-        };
+    /// Add a job from a Monero-style job object (`{"job_id", "target", ..}`)
+    pub fn add_sv1_job(&self, job: &Map<String, Value>) -> Option<String> {
+        let job_id = job.get("job_id")?.as_str()?.to_string();
+        let target = job.get("target").and_then(|v| v.as_str()).unwrap_or_default();
+        self.add_job(job_id.clone(), target);
+        Some(job_id)
+    }
+
+    /// Add a new job with the difficulty of its target
+    pub fn add_job(&self, job_id: String, target: &str) {
+        let difficulty = JobInfo::difficulty_from_target(target);
+        self.insert(JobInfo { job_id, difficulty });
+    }
+
+    /// Add a job whose difficulty was set separately (`mining.set_difficulty`)
+    pub fn add_job_with_difficulty(&self, job_id: String, difficulty: f64) {
+        self.insert(JobInfo { job_id, difficulty });
+    }
+
+    fn insert(&self, job_info: JobInfo) {
+        let job_id = job_info.job_id.clone();
+        let difficulty = job_info.difficulty;
 
         let mut jobs = self.jobs.lock().unwrap();
         
-        // Limit memory usage by removing old jobs
-        if jobs.len() >= self.max_jobs {
-            // Remove oldest job (simple approach - could use LRU)
-            if let Some(oldest_key) = jobs.keys().next().cloned() {
-                jobs.remove(&oldest_key);
+        // Limit memory usage by removing the oldest jobs
+        if !jobs.by_id.contains_key(&job_id) {
+            while jobs.by_id.len() >= self.max_jobs {
+                let Some(oldest_key) = jobs.order.pop_front() else {
+                    break;
+                };
+                jobs.by_id.remove(&oldest_key);
             }
+            jobs.order.push_back(job_id.clone());
         }
         
-        jobs.insert(job_id.clone(), job_info);
+        jobs.by_id.insert(job_id.clone(), job_info);
         debug!("Added job {} with difficulty {}", job_id, difficulty);
     }
 
     /// Get job information
     pub fn get_job(&self, job_id: &str) -> Option<JobInfo> {
         let jobs = self.jobs.lock().unwrap();
-        jobs.get(job_id).cloned()
+        jobs.by_id.get(job_id).cloned()
     }
 
     /// Get difficulty for a job
@@ -90,18 +123,6 @@ impl JobTracker {
             .map(|job| job.difficulty)
             .unwrap_or(1000.0) // Default difficulty if job not found
     }
-
-    /// Clear all jobs
-    pub fn clear(&self) {
-        let mut jobs = self.jobs.lock().unwrap();
-        jobs.clear();
-    }
-
-    /// Get job count
-    pub fn count(&self) -> usize {
-        let jobs = self.jobs.lock().unwrap();
-        jobs.len()
-    }
 }
 
 #[cfg(test)]
@@ -114,19 +135,29 @@ mod tests {
         let diff1 = JobInfo::difficulty_from_target("00010000");
         assert!(diff1 > 1000.0);
 
-        // Low difficulty (high target)
-        let diff2 = JobInfo::difficulty_from_target("01000000");
+        // Low difficulty (high target, little-endian)
+        let diff2 = JobInfo::difficulty_from_target("00000001");
         assert!(diff2 < diff1);
     }
 
+    #[test]
+    fn test_compact_and_full_targets() {
+        // Both encode difficulty 10000
+        let compact = JobInfo::difficulty_from_target("b88d0600");
+        let full = JobInfo::difficulty_from_target("cb10c7bab88d0600");
+
+        assert!((compact - 10000.0).abs() < 1.0);
+        assert!((full - 10000.0).abs() < 1.0);
+    }
+
     #[test]
     fn test_job_tracker() {
         let tracker = JobTracker::new(10);
         
-        tracker.add_job("job1".to_string(), "00010000".to_string(), 100);
-        tracker.add_job("job2".to_string(), "00020000".to_string(), 101);
+        tracker.add_job("job1".to_string(), "00010000");
+        tracker.add_job("job2".to_string(), "00020000");
         
-        assert_eq!(tracker.count(), 2);
+        assert!(tracker.get_job("job2").is_some());
         
         let job1 = tracker.get_job("job1").unwrap();
         assert_eq!(job1.job_id, "job1");
@@ -141,10 +172,12 @@ mod tests {
         let tracker = JobTracker::new(3);
         
         for i in 0..5 {
-            tracker.add_job(format!("job{}", i), "00010000".to_string(), i);
+            tracker.add_job(format!("job{}", i), "00010000");
         }
         
-        // Should only keep 3 jobs
-        assert!(tracker.count() <= 3);
+        // Should only keep the 3 newest jobs
+        assert!(tracker.get_job("job1").is_none());
+        assert!(tracker.get_job("job2").is_some());
+        assert!(tracker.get_job("job4").is_some());
     }
 }
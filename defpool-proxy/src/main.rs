@@ -1,8 +1,17 @@
+mod authority;
 mod config;
+mod control;
 mod job_tracker;
 mod proxy;
+mod server_auth;
 mod share_recorder;
+mod share_spool;
+mod share_validator;
 mod stratum;
+mod target;
+mod v1_session;
+mod vardiff;
+mod wallet_check;
 
 use anyhow::Result;
 use clap::Parser;
@@ -14,6 +23,10 @@ struct Args {
     /// Path to configuration file
     #[arg(short, long, default_value = "defpool-proxy.toml")]
     config: String,
+
+    /// Print the SV2 authority public key (creating the key if needed) and exit
+    #[arg(long)]
+    show_pubkey: bool,
 }
 
 #[tokio::main]
@@ -27,6 +40,12 @@ async fn main() -> Result<()> {
     let config = config::Config::load(&args.config)?;
     info!("Config loaded: {:?}", config);
 
+    if args.show_pubkey {
+        let authority = authority::AuthorityKeypair::load_or_generate(&config.authority)?;
+        println!("{}", authority.public_key());
+        return Ok(());
+    }
+
     proxy::start(config).await?;
 
     Ok(())
@@ -1,15 +1,21 @@
+use crate::authority::AuthorityKeypair;
 use crate::config::Config;
-use crate::share_recorder::{ShareRecorder, ShareSubmission};
+use crate::control::{current_target, spawn_control_channel, ProxyState};
+use crate::server_auth::ServerAuth;
+use crate::share_recorder::{ShareRecorder, ShareStatus, ShareSubmission};
+use crate::target::{fetch_target, Target};
+use crate::v1_session::V1Session;
+use crate::wallet_check::WalletChecker;
 use anyhow::{Context, Result};
 use std::sync::Arc;
 use tokio::net::{TcpListener, TcpStream};
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::sync::watch;
 use tracing::{error, info, warn};
-use serde::Deserialize;
 
 // Stratum V2 crates
 use stratum_core::{
-    codec_sv2::{HandshakeRole, StandardEitherFrame},
+    codec_sv2::{HandshakeRole, StandardEitherFrame, StandardSv2Frame},
     noise_sv2::{Initiator, Responder},
     parsers_sv2::AnyMessage,
 };
@@ -37,6 +43,14 @@ impl std::str::FromStr for Secp256k1PublicKey {
     }
 }
 
+impl std::fmt::Display for Secp256k1PublicKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut encoded = 1u16.to_le_bytes().to_vec();
+        encoded.extend_from_slice(&self.0.serialize());
+        write!(f, "{}", bs58::encode(encoded).with_check().into_string())
+    }
+}
+
 // Type aliases
 type Message = AnyMessage<'static>;
 
@@ -44,7 +58,7 @@ type Message = AnyMessage<'static>;
 #[derive(Debug)]
 pub enum Error {
     HandshakeRemoteInvalidMessage,
-    CodecError(stratum_core::codec_sv2::Error),
+    Codec(stratum_core::codec_sv2::Error),
     SocketClosed,
 }
 
@@ -52,7 +66,7 @@ impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Error::HandshakeRemoteInvalidMessage => write!(f, "Handshake remote invalid message"),
-            Error::CodecError(e) => write!(f, "Codec error: {:?}", e),
+            Error::Codec(e) => write!(f, "Codec error: {:?}", e),
             Error::SocketClosed => write!(f, "Socket closed"),
         }
     }
@@ -62,7 +76,7 @@ impl std::error::Error for Error {}
 
 impl From<stratum_core::codec_sv2::Error> for Error {
     fn from(e: stratum_core::codec_sv2::Error) -> Self {
-        Error::CodecError(e)
+        Error::Codec(e)
     }
 }
 
@@ -126,7 +140,7 @@ where
                             state = transport_state;
                             break;
                         }
-                        Err(Error::CodecError(stratum_core::codec_sv2::Error::MissingBytes(_))) => {
+                        Err(Error::Codec(stratum_core::codec_sv2::Error::MissingBytes(_))) => {
                             tokio::task::yield_now().await;
                         }
                         Err(e) => return Err(e),
@@ -152,7 +166,7 @@ where
                             state = transport_state;
                             break;
                         }
-                        Err(Error::CodecError(stratum_core::codec_sv2::Error::MissingBytes(_))) => {
+                        Err(Error::Codec(stratum_core::codec_sv2::Error::MissingBytes(_))) => {
                             tokio::task::yield_now().await;
                         }
                         Err(e) => return Err(e),
@@ -221,7 +235,7 @@ where
                     tokio::task::yield_now().await;
                     continue;
                 }
-                Err(e) => return Err(Error::CodecError(e)),
+                Err(e) => return Err(Error::Codec(e)),
             }
         }
     }
@@ -266,33 +280,7 @@ async fn receive_message<Message: Serialize + BinaryDeserialize<'static> + GetSi
         .await
         .map_err(|_| Error::SocketClosed)?;
     decoder.writable().copy_from_slice(&buffer);
-    decoder.next_frame(state).map_err(Error::CodecError)
-}
-
-// TODO: Define SV1 message structures
-// #[derive(Debug)] struct Sv1Message { ... }
-
-// TODO: Implement SV1 message parsing
-// fn parse_sv1_response(line: &str) -> Result<Sv1Message, Box<dyn std::error::Error>> { ... }
-
-// TODO: Implement SV2 message creation functions
-// fn create_sv2_setup_connection() -> Result<...> { ... }
-// fn create_sv2_open_channel(user_identity: &str) -> Result<...> { ... }
-
-// TODO: Implement message translation functions
-// async fn translate_sv2_to_sv1(...) -> Result<()> { ... }
-// async fn translate_sv1_to_sv2(...) -> Result<()> { ... }
-
-#[derive(Debug, Deserialize)]
-struct Target {
-    address: String,
-    pubkey: Option<String>,
-    #[serde(default = "default_protocol")]
-    protocol: String,
-}
-
-fn default_protocol() -> String {
-    "sv2".to_string()
+    decoder.next_frame(state).map_err(Error::Codec)
 }
 
 pub async fn start(config: Config) -> Result<()> {
@@ -311,35 +299,50 @@ pub async fn start(config: Config) -> Result<()> {
         }
     }
 
+    let authority = AuthorityKeypair::load_or_generate(&config.authority)?;
+    info!("SV2 authority public key: {}", authority.public_key());
+
+    let server_auth = ServerAuth::new(&config.server_auth);
+    let share_recorder = ShareRecorder::start(&config, server_auth.clone())?;
+    let wallet_checker = Arc::new(WalletChecker::new(&config, server_auth.clone()));
+
+    let control = spawn_control_channel(
+        config.server_endpoint.clone(),
+        server_auth,
+        config.default_wallet.clone(),
+        std::time::Duration::from_secs(config.target_poll_interval_secs),
+    );
     let config = Arc::new(config);
 
     loop {
         let (socket, addr) = listener.accept().await?;
+        if control.borrow().draining {
+            info!("Draining, refusing connection from {}", addr);
+            continue;
+        }
         info!("Accepted connection from {}", addr);
         
         let config = config.clone();
+        let targets = control.clone();
+        let share_recorder = share_recorder.clone();
+        let wallet_checker = wallet_checker.clone();
 
         tokio::spawn(async move {
             // Try to detect protocol by reading first few bytes
-            if let Err(e) = handle_connection_auto(socket, config).await {
+            if let Err(e) = handle_connection_auto(socket, config, targets, authority, share_recorder, wallet_checker).await {
                 error!("Connection error with {}: {:?}", addr, e);
             }
         });
     }
 }
 
-async fn fetch_target(server_url: &str) -> Result<Target> {
-    let url = format!("{}/target", server_url);
-    let target = reqwest::get(&url)
-        .await?
-        .json::<Target>()
-        .await?;
-    Ok(target)
-}
-
 async fn handle_connection_auto(
     downstream_socket: TcpStream,
     config: Arc<Config>,
+    targets: watch::Receiver<ProxyState>,
+    authority: AuthorityKeypair,
+    share_recorder: Arc<ShareRecorder>,
+    wallet_checker: Arc<WalletChecker>,
 ) -> Result<()> {
     // Peek at first byte to detect protocol
     // V1 (JSON-RPC): starts with '{' (0x7B)
@@ -350,36 +353,22 @@ async fn handle_connection_auto(
     
     if buf[0] == b'{' {
         info!("Detected Stratum V1 downstream connection");
-        handle_v1_passthrough(downstream_socket, config).await
+        handle_v1_passthrough(downstream_socket, config, targets, share_recorder, wallet_checker).await
     } else {
         info!("Detected Stratum V2 downstream connection");
-        // Generate keypair for SV2 (outside of async context to avoid Send issues)
-        let (proxy_pubkey_bytes, proxy_secret_key_bytes) = tokio::task::block_in_place(|| {
-            let mut rng = rand::thread_rng();
-            let proxy_secret_key = secp256k1::SecretKey::new(&mut rng);
-            let proxy_public_key = secp256k1::PublicKey::from_secret_key(&secp256k1::Secp256k1::new(), &proxy_secret_key);
-            
-            let proxy_secret_key_bytes = proxy_secret_key.secret_bytes();
-            let (x_only_pubkey, _) = proxy_public_key.x_only_public_key();
-            let proxy_pubkey_bytes = x_only_pubkey.serialize();
-            (proxy_pubkey_bytes, proxy_secret_key_bytes)
-        });
-        
-        handle_sv2_connection(downstream_socket, config, proxy_pubkey_bytes, proxy_secret_key_bytes).await
+        handle_sv2_connection(downstream_socket, config, targets, authority, share_recorder).await
     }
 }
 
 async fn handle_v1_passthrough(
     downstream_socket: TcpStream,
     config: Arc<Config>,
+    targets: watch::Receiver<ProxyState>,
+    share_recorder: Arc<ShareRecorder>,
+    wallet_checker: Arc<WalletChecker>,
 ) -> Result<()> {
-    use tokio::io::BufReader;
-    use tokio::io::AsyncBufReadExt;
-    use crate::stratum::Sv1Message;
-    
-    // Fetch target from server
     info!("Fetching target from server: {}", config.server_endpoint);
-    let target = fetch_target(&config.server_endpoint).await
+    let target = current_target(&config.server_endpoint, &targets).await
         .context("Failed to fetch target from server")?;
     info!("Got target: {} (Protocol: {})", target.address, target.protocol);
 
@@ -387,127 +376,21 @@ async fn handle_v1_passthrough(
         return Err(anyhow::anyhow!("V1 miner requires V1 upstream, but got {}", target.protocol));
     }
 
-    // Connect to upstream
-    info!("Connecting to upstream (SV1): {}", target.address);
-    let upstream_socket = TcpStream::connect(&target.address).await
-        .context("Failed to connect to upstream")?;
-    info!("Connected to upstream: {}", target.address);
-
-    // Create share recorder
-    let share_recorder = Arc::new(ShareRecorder::new(config.server_endpoint.clone()));
-    let target_name = share_recorder.get_current_target().await
-        .unwrap_or_else(|_| "unknown".to_string());
-
-    // Split and wrap in buffered readers for line-based protocol
-    let (d_read, mut d_write) = downstream_socket.into_split();
-    let (u_read, mut u_write) = upstream_socket.into_split();
-    
-    let mut d_reader = BufReader::new(d_read);
-    let mut u_reader = BufReader::new(u_read);
-
-    let mut wallet_address = config.default_wallet.clone();
-    let mut worker_name = String::from("worker1");
-    let share_recorder_clone = share_recorder.clone();
-    let target_name_clone = target_name.clone();
-
-    let downstream_to_upstream = async move {
-        let mut line = String::new();
-        loop {
-            line.clear();
-            let n = d_reader.read_line(&mut line).await?;
-            if n == 0 {
-                return Ok::<(), anyhow::Error>(());
-            }
-            
-            // Parse and log SV1 message
-            if let Ok(msg) = Sv1Message::from_json(&line) {
-                if let Some(method) = &msg.method {
-                    info!("V1 Miner → Pool: {}", method);
-                    
-                    // Extract wallet/worker from login
-                    if method == "login" {
-                        if let Some(params) = &msg.params {
-                            if let Some(serde_json::Value::String(login)) = params.first() {
-                                let parts: Vec<&str> = login.split(':').collect();
-                                if !parts.is_empty() {
-                                    wallet_address = Some(parts[0].to_string());
-                                    worker_name = parts.get(1).unwrap_or(&"worker1").to_string();
-                                    info!("Extracted wallet: {}, worker: {}", parts[0], worker_name);
-                                }
-                            }
-                        }
-                    }
-                    
-                    // Record share submissions
-                    if method == "submit" {
-                        let wallet = wallet_address.as_deref().unwrap_or("unknown");
-                        info!("Share submitted by {}/{}", wallet, worker_name);
-                        
-                        // Record share asynchronously (don't block on result)
-                        let recorder = share_recorder_clone.clone();
-                        let submission = ShareSubmission {
-                            wallet_address: wallet.to_string(),
-                            worker_name: worker_name.clone(),
-                            target_name: target_name_clone.clone(),
-                            difficulty: 1000.0, // TODO: Extract from job
-                            valid: true, // Will be validated by pool response
-                        };
-                        
-                        tokio::spawn(async move {
-                            if let Err(e) = recorder.record_share(submission).await {
-                                warn!("Failed to record share: {}", e);
-                            }
-                        });
-                    }
-                }
-            }
-            
-            u_write.write_all(line.as_bytes()).await?;
-        }
-    };
-
-    let upstream_to_downstream = async {
-        let mut line = String::new();
-        loop {
-            line.clear();
-            let n = u_reader.read_line(&mut line).await?;
-            if n == 0 {
-                return Ok::<(), anyhow::Error>(());
-            }
-            
-            // Parse and log SV1 response
-            if let Ok(msg) = Sv1Message::from_json(&line) {
-                if let Some(method) = &msg.method {
-                    info!("V1 Pool → Miner: {}", method);
-                } else if msg.is_response() {
-                    // Check for share acceptance/rejection
-                    if let Some(error) = &msg.error {
-                        warn!("Share rejected: {:?}", error);
-                    }
-                }
-            }
-            
-            d_write.write_all(line.as_bytes()).await?;
-        }
-    };
-
-    tokio::select! {
-        res = downstream_to_upstream => res,
-        res = upstream_to_downstream => res,
-    }
+    V1Session::run(downstream_socket, config, target, targets, share_recorder, wallet_checker).await
 }
 
 async fn handle_sv2_connection(
     downstream_socket: TcpStream, 
     config: Arc<Config>,
-    proxy_pubkey_bytes: [u8; 32],
-    proxy_secret_key_bytes: [u8; 32]
+    targets: watch::Receiver<ProxyState>,
+    authority: AuthorityKeypair,
+    share_recorder: Arc<ShareRecorder>,
 ) -> Result<()> {
-    // 1. Downstream Handshake (Responder)
+    // 1. Downstream Handshake (Responder), signing a fresh certificate with the authority key
     let responder = Responder::from_authority_kp(
-        &proxy_pubkey_bytes,
-        &proxy_secret_key_bytes,
-        std::time::Duration::from_secs(3600), // Cert validity
+        &authority.public_bytes(),
+        &authority.secret_bytes(),
+        std::time::Duration::from_secs(config.authority.cert_validity_secs),
     ).map_err(|e| anyhow::anyhow!("Failed to create responder: {:?}", e))?;
 
     let downstream_stream = NoiseTcpStream::<Message>::new(
@@ -519,25 +402,27 @@ async fn handle_sv2_connection(
 
     // 2. Fetch Target from Server
     info!("Fetching target from server: {}", config.server_endpoint);
-    let target = fetch_target(&config.server_endpoint).await
+    let target = current_target(&config.server_endpoint, &targets).await
         .context("Failed to fetch target from server")?;
     info!("Got target: {} (Protocol: {})", target.address, target.protocol);
 
+    let connect_timeout = std::time::Duration::from_secs(config.upstream_connect_timeout_secs);
     if target.protocol == "sv1" {
-        handle_sv1_upstream(downstream_stream, target, &config).await
+        let default_wallet = targets.borrow().default_wallet.clone();
+        handle_sv1_upstream(downstream_stream, target, default_wallet, connect_timeout, share_recorder).await
     } else {
-        handle_sv2_upstream(downstream_stream, target).await
+        handle_sv2_upstream(downstream_stream, target, connect_timeout).await
     }
 }
 
 async fn handle_sv2_upstream(
     downstream_stream: NoiseTcpStream<Message>,
     target: Target,
+    connect_timeout: std::time::Duration,
 ) -> Result<()> {
     // 3. Upstream Connection & Handshake (Initiator)
     info!("Connecting to upstream (SV2): {}", target.address);
-    let upstream_socket = TcpStream::connect(&target.address).await
-        .context("Failed to connect to upstream")?;
+    let (upstream_socket, _) = target.connect(None, connect_timeout).await?;
     
     let upstream_pubkey_str = target.pubkey.ok_or_else(|| anyhow::anyhow!("Missing pubkey for SV2 target"))?;
     let upstream_pubkey: Secp256k1PublicKey = upstream_pubkey_str.parse()
@@ -590,73 +475,116 @@ async fn handle_sv2_upstream(
     }
 }
 
-// Simple SV1 JSON-RPC handling
-// use tokio_util::codec::{Framed, LinesCodec}; // TODO: For SV1 support
-// use futures::{SinkExt, StreamExt}; // TODO: For async utilities
-// use serde_json::{json, Value}; // TODO: For SV1 message parsing
-
 async fn handle_sv1_upstream(
     downstream_stream: NoiseTcpStream<Message>,
     target: Target,
-    _config: &Config,
+    default_wallet: Option<String>,
+    connect_timeout: std::time::Duration,
+    share_recorder: Arc<ShareRecorder>,
 ) -> Result<()> {
+    use tokio::io::{AsyncBufReadExt, BufReader};
+    use crate::stratum::Sv1Message;
+    use crate::stratum::sv2::get_message_type;
+    use crate::stratum::translator::{StratumTranslator, SubmitTranslation};
+    use stratum_core::parsers_sv2::{CommonMessages, Mining};
+
     info!("SV2 miner connecting to SV1 upstream pool: {}", target.address);
     info!("Protocol translation: SV2 (downstream) → SV1 (upstream)");
 
+    let (mut downstream_read, mut downstream_write) = downstream_stream.into_split();
+
+    let Some(mut translator) = StratumTranslator::for_algorithm(&target.algorithm) else {
+        let setup = downstream_read.read_frame().await.ok().and_then(|frame| decode_sv2_frame(frame).ok());
+        if let Some(AnyMessage::Common(CommonMessages::SetupConnection(_))) = setup {
+            let reply = StratumTranslator::refuse_connection();
+            write_sv2_message(&mut downstream_write, AnyMessage::Common(reply)).await?;
+        }
+        return Err(anyhow::anyhow!(
+            "SV2 miners cannot be bridged to {} ({}): only SHA256d and Scrypt pools are supported",
+            target.name,
+            target.algorithm
+        ));
+    };
+
     // Connect to SV1 pool
-    let upstream_socket = TcpStream::connect(&target.address).await
-        .context("Failed to connect to upstream SV1 pool")?;
+    let (upstream_socket, endpoint) = target.connect(None, connect_timeout).await?;
+
+    info!("Connected to upstream SV1 pool: {}", endpoint);
 
-    info!("Connected to upstream SV1 pool: {}", target.address);
+    let (upstream_read, mut upstream_write) = upstream_socket.into_split();
+    let mut upstream_reader = BufReader::new(upstream_read);
 
-    // Split streams
-    let (mut downstream_read, _downstream_write) = downstream_stream.into_split();
-    let (mut upstream_read, _upstream_write) = upstream_socket.into_split();
+    let mut line = String::new();
 
     info!("SV2↔SV1 translation bridge established");
 
-    // Bidirectional forwarding with protocol translation
-    // Note: Full translation requires parsing SV2 messages and converting to SV1 JSON-RPC
-    // This is a complex task that requires understanding both protocol specifications
-    let mut buffer = vec![0u8; 8192];
-    
     loop {
         tokio::select! {
             // Read from SV2 downstream miner
             downstream_msg = downstream_read.read_frame() => {
-                match downstream_msg {
-                    Ok(_frame) => {
-                        info!("SV2 frame from miner (translation TODO)");
-                        // TODO: Parse SV2 frame, translate to SV1 JSON, send to upstream
-                        // This requires:
-                        // 1. Extract message from frame
-                        // 2. Match on message type (SetupConnection, OpenChannel, SubmitShares, etc.)
-                        // 3. Translate to equivalent SV1 JSON-RPC message
-                        // 4. Send to upstream pool
-                    }
+                let frame = match downstream_msg {
+                    Ok(frame) => frame,
                     Err(e) => {
                         warn!("Error reading from SV2 downstream: {:?}", e);
                         break;
                     }
+                };
+
+                match decode_sv2_frame(frame)? {
+                    AnyMessage::Common(CommonMessages::SetupConnection(setup)) => {
+                        let reply = translator.setup_connection(&setup);
+                        write_sv2_message(&mut downstream_write, AnyMessage::Common(reply)).await?;
+                    }
+                    AnyMessage::Mining(Mining::OpenStandardMiningChannel(open)) => {
+                        for request in translator.open_standard_channel(&open, default_wallet.as_deref()) {
+                            write_sv1_message(&mut upstream_write, &request).await?;
+                        }
+                    }
+                    AnyMessage::Mining(Mining::SubmitSharesStandard(submit)) => {
+                        match translator.translate_submit(&submit) {
+                            SubmitTranslation::Upstream(msg) => {
+                                write_sv1_message(&mut upstream_write, &msg).await?;
+                            }
+                            SubmitTranslation::Reject(reply) => {
+                                write_sv2_message(&mut downstream_write, AnyMessage::Mining(reply)).await?;
+                            }
+                        }
+                    }
+                    other => {
+                        info!("Ignoring SV2 {} from miner", get_message_type(&other));
+                    }
                 }
             }
 
             // Read from SV1 upstream pool
-            upstream_result = upstream_read.read(&mut buffer) => {
+            upstream_result = upstream_reader.read_line(&mut line) => {
                 match upstream_result {
-                    Ok(n) if n > 0 => {
-                        info!("SV1 data from pool: {} bytes (translation TODO)", n);
-                        // TODO: Parse SV1 JSON, translate to SV2 frame, send to downstream
-                    }
-                    Ok(_) => {
+                    Ok(0) => {
                         info!("Upstream connection closed");
                         break;
                     }
+                    Ok(_) => {}
                     Err(e) => {
                         warn!("Error reading from SV1 upstream: {:?}", e);
                         break;
                     }
                 }
+
+                let replies = match Sv1Message::from_json(&line) {
+                    Ok(msg) => translator.process_upstream_message(&msg),
+                    Err(e) => {
+                        warn!("Invalid SV1 message from pool: {}", e);
+                        Vec::new()
+                    }
+                };
+                line.clear();
+
+                for share in translator.take_settled_shares() {
+                    record_bridged_share(&share_recorder, &translator, &target, share);
+                }
+                for reply in replies {
+                    write_sv2_message(&mut downstream_write, AnyMessage::Mining(reply)).await?;
+                }
             }
         }
     }
@@ -664,3 +592,67 @@ async fn handle_sv1_upstream(
     info!("SV2↔SV1 bridge closed");
     Ok(())
 }
+
+/// Spool a share the pool answered for a bridged SV2 miner, as V1 sessions do
+fn record_bridged_share(
+    share_recorder: &Arc<ShareRecorder>,
+    translator: &crate::stratum::translator::StratumTranslator,
+    target: &Target,
+    share: crate::stratum::translator::SettledShare,
+) {
+    let wallet = translator.wallet_address().unwrap_or("unknown");
+    let worker = translator.worker_name().unwrap_or("worker1");
+    let status = match &share.error_message {
+        None => ShareStatus::Accepted,
+        Some(error) => ShareStatus::from_pool_error(error),
+    };
+    info!("Share {:?} for {}/{}", status, wallet, worker);
+
+    let recorder = share_recorder.clone();
+    let submission = ShareSubmission {
+        share_id: ShareSubmission::new_id(),
+        wallet_address: wallet.to_string(),
+        worker_name: worker.to_string(),
+        target_name: target.name.clone(),
+        difficulty: share.difficulty,
+        valid: status == ShareStatus::Accepted,
+        status,
+        error_message: share.error_message,
+    };
+
+    tokio::spawn(async move {
+        if let Err(e) = recorder.record_share(submission).await {
+            warn!("Failed to record share: {}", e);
+        }
+    });
+}
+
+/// Decode a transport frame into an owned SV2 message
+fn decode_sv2_frame(frame: StandardEitherFrame<Message>) -> Result<Message> {
+    let mut frame: StandardSv2Frame<Message> = frame
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Unexpected handshake frame"))?;
+    let header = frame
+        .get_header()
+        .ok_or_else(|| anyhow::anyhow!("SV2 frame without header"))?;
+    let message = AnyMessage::try_from((header, frame.payload()))
+        .map_err(|e| anyhow::anyhow!("Failed to parse SV2 message: {:?}", e))?;
+    Ok(message.into_static())
+}
+
+async fn write_sv2_message(writer: &mut NoiseTcpWriteHalf<Message>, message: Message) -> Result<()> {
+    let frame: StandardSv2Frame<Message> = message
+        .try_into()
+        .map_err(|e| anyhow::anyhow!("Failed to encode SV2 message: {:?}", e))?;
+    writer
+        .write_frame(frame.into())
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to write to downstream: {:?}", e))
+}
+
+async fn write_sv1_message(writer: &mut OwnedWriteHalf, message: &crate::stratum::Sv1Message) -> Result<()> {
+    let mut line = message.to_json()?;
+    line.push('\n');
+    writer.write_all(line.as_bytes()).await?;
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use crate::config::ServerAuthConfig;
+use hmac::{Hmac, Mac};
+use reqwest::RequestBuilder;
+use sha2::Sha256;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+const PROXY_HEADER: &str = "x-defpool-proxy";
+const TIMESTAMP_HEADER: &str = "x-defpool-timestamp";
+const SIGNATURE_HEADER: &str = "x-defpool-signature";
+
+/// Signs requests to the server's internal API (share recording, events)
+/// with this proxy's key
+#[derive(Clone)]
+pub struct ServerAuth {
+    /// Proxy name and secret; requests go out unsigned without them
+    credentials: Option<(String, String)>,
+}
+
+impl ServerAuth {
+    pub fn new(config: &ServerAuthConfig) -> Self {
+        let credentials = config.proxy_name.clone().zip(config.key.clone());
+        if credentials.is_none() {
+            warn!("No [server_auth] key configured; the server will refuse shares from this proxy");
+        }
+        Self { credentials }
+    }
+
+    /// Add the signature headers for `method path` with `body`
+    pub fn sign(&self, request: RequestBuilder, method: &str, path: &str, body: &[u8]) -> RequestBuilder {
+        let Some((name, key)) = &self.credentials else {
+            return request;
+        };
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+
+        request
+            .header(PROXY_HEADER, name)
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(SIGNATURE_HEADER, signature(key, timestamp, method, path, body))
+    }
+}
+
+/// Hex HMAC-SHA256 over `"{timestamp}\n{METHOD}\n{path}\n"` followed by the body
+fn signature(key: &str, timestamp: i64, method: &str, path: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(format!("{}\n{}\n{}\n", timestamp, method, path).as_bytes());
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature() {
+        // Same vector as the server's verification test
+        assert_eq!(
+            signature("test-secret", 1_700_000_000, "POST", "/api/v1/shares/batch", b"[]"),
+            "a901f28e9089988022c50ab92f4abacd80a4241b42475217915248efe39b5121"
+        );
+    }
+}
@@ -1,70 +1,336 @@
+use crate::config::Config;
+use crate::server_auth::ServerAuth;
+use crate::share_spool::ShareSpool;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Notify;
 use tracing::{debug, warn};
 
 /// Share submission to record on server
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ShareSubmission {
+    /// Idempotency key, so the server records a retried share once
+    pub share_id: String,
     pub wallet_address: String,
     pub worker_name: String,
     pub target_name: String,
     pub difficulty: f64,
     pub valid: bool,
+    pub status: ShareStatus,
+    /// Pool error message for shares that were not accepted
+    pub error_message: Option<String>,
 }
 
-/// Client for recording shares to the server
+impl ShareSubmission {
+    /// Fresh random share id
+    pub fn new_id() -> String {
+        format!("{:032x}", rand::random::<u128>())
+    }
+}
+
+/// Upstream pool verdict on a share
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShareStatus {
+    Accepted,
+    Rejected,
+    Stale,
+    Duplicate,
+}
+
+impl ShareStatus {
+    /// Classify a pool's submit error message
+    pub fn from_pool_error(message: &str) -> Self {
+        let message = message.to_lowercase();
+        if message.contains("duplicate") {
+            Self::Duplicate
+        } else if ["stale", "expired", "job not found", "unknown job", "outdated"]
+            .iter()
+            .any(|pattern| message.contains(pattern))
+        {
+            Self::Stale
+        } else {
+            Self::Rejected
+        }
+    }
+}
+
+/// Outcome the server reports for one share of a batch
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShareOutcome {
+    Created,
+    Duplicate,
+    Invalid,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShareBatchResult {
+    outcome: ShareOutcome,
+    error: Option<String>,
+}
+
+/// First retry delay after a failed delivery, doubled up to `MAX_RETRY_DELAY`
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Records shares to the server through an on-disk spool.
+///
+/// Shares are written to the spool before anything is sent. A single delivery
+/// task sends them in order, in batches once `share_batch_size` shares are
+/// waiting or every `share_batch_interval_ms`, retrying with backoff while the
+/// server is unreachable. Shares left over from a previous run are replayed on
+/// start.
 pub struct ShareRecorder {
     client: reqwest::Client,
     server_url: String,
+    auth: ServerAuth,
+    spool: ShareSpool,
+    batch_size: usize,
+    batch_interval: Duration,
+    /// Shares spooled since the last flush
+    queued: AtomicUsize,
+    wake: Notify,
 }
 
 impl ShareRecorder {
-    pub fn new(server_url: String) -> Self {
-        Self {
+    /// Open the spool and start the delivery task
+    pub fn start(config: &Config, auth: ServerAuth) -> Result<Arc<Self>> {
+        let recorder = Arc::new(Self {
             client: reqwest::Client::new(),
-            server_url,
-        }
+            server_url: config.server_endpoint.clone(),
+            auth,
+            spool: ShareSpool::open(&config.share_spool_dir)?,
+            batch_size: config.share_batch_size.max(1),
+            batch_interval: Duration::from_millis(config.share_batch_interval_ms),
+            queued: AtomicUsize::new(0),
+            wake: Notify::new(),
+        });
+        tokio::spawn(recorder.clone().deliver());
+        Ok(recorder)
     }
 
-    /// Record a share submission
+    /// Record a share submission. Returns once the share is spooled.
     pub async fn record_share(&self, submission: ShareSubmission) -> Result<()> {
-        let url = format!("{}/api/v1/shares", self.server_url);
-        
         debug!(
-            "Recording share: wallet={}, worker={}, target={}, difficulty={}, valid={}",
+            "Recording share {}: wallet={}, worker={}, target={}, difficulty={}, status={:?}",
+            submission.share_id,
             submission.wallet_address,
             submission.worker_name,
             submission.target_name,
             submission.difficulty,
-            submission.valid
+            submission.status
         );
 
-        let response = self.client
+        self.spool.push(&submission).await?;
+        if self.queued.fetch_add(1, Ordering::Relaxed) + 1 >= self.batch_size {
+            self.wake.notify_one();
+        }
+        Ok(())
+    }
+
+    async fn deliver(self: Arc<Self>) {
+        let mut retry_delay = INITIAL_RETRY_DELAY;
+        // Start with whatever a previous run left in the spool
+        let mut undelivered = true;
+        loop {
+            if undelivered || self.queued.load(Ordering::Relaxed) > 0 {
+                match self.flush().await {
+                    Ok(()) => {
+                        retry_delay = INITIAL_RETRY_DELAY;
+                        undelivered = false;
+                    }
+                    Err(e) => {
+                        warn!("Failed to deliver shares, retrying in {:?}: {}", retry_delay, e);
+                        tokio::time::sleep(retry_delay).await;
+                        retry_delay = (retry_delay * 2).min(MAX_RETRY_DELAY);
+                        undelivered = true;
+                        continue;
+                    }
+                }
+            }
+
+            // Send once a batch fills up or the interval passes
+            let _ = tokio::time::timeout(self.batch_interval, self.wake.notified()).await;
+        }
+    }
+
+    /// Send spooled shares oldest first, stopping at the first failed batch
+    async fn flush(&self) -> Result<()> {
+        self.queued.store(0, Ordering::Relaxed);
+
+        for paths in self.spool.pending().await?.chunks(self.batch_size) {
+            let mut batch = Vec::with_capacity(paths.len());
+            let mut batch_paths = Vec::with_capacity(paths.len());
+            for path in paths {
+                match self.spool.load(path).await {
+                    Ok(submission) => {
+                        batch.push(submission);
+                        batch_paths.push(path);
+                    }
+                    Err(e) => {
+                        warn!("Unreadable spooled share {}: {}", path.display(), e);
+                        self.spool.set_aside(path).await?;
+                    }
+                }
+            }
+            if batch.is_empty() {
+                continue;
+            }
+
+            let results = self.send_batch(&batch).await?;
+            for ((path, submission), result) in batch_paths.into_iter().zip(&batch).zip(results) {
+                if result.outcome == ShareOutcome::Invalid {
+                    warn!(
+                        "Server refused share {}: {}",
+                        submission.share_id,
+                        result.error.as_deref().unwrap_or("no reason given")
+                    );
+                    self.spool.set_aside(path).await?;
+                } else {
+                    self.spool.remove(path).await?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// POST a batch of shares, returning the server's verdict for each
+    async fn send_batch(&self, batch: &[ShareSubmission]) -> Result<Vec<ShareBatchResult>> {
+        let path = "/api/v1/shares/batch";
+        let url = format!("{}{}", self.server_url, path);
+        let body = serde_json::to_vec(batch)?;
+
+        let request = self.client
             .post(&url)
-            .json(&submission)
+            .header(reqwest::header::CONTENT_TYPE, "application/json");
+        let response = self.auth
+            .sign(request, "POST", path, &body)
+            .body(body)
             .send()
             .await?;
 
         if !response.status().is_success() {
-            warn!("Failed to record share: {}", response.status());
             anyhow::bail!("Server returned error: {}", response.status());
         }
 
-        debug!("Share recorded successfully");
-        Ok(())
+        let results: Vec<ShareBatchResult> = response.json().await?;
+        if results.len() != batch.len() {
+            anyhow::bail!("Server answered {} results for {} shares", results.len(), batch.len());
+        }
+
+        debug!("Delivered batch of {} shares", batch.len());
+        Ok(results)
     }
+}
 
-    /// Get current target name from server
-    pub async fn get_current_target(&self) -> Result<String> {
-        let url = format!("{}/api/v1/targets/current", self.server_url);
-        
-        let target_name = self.client
-            .get(&url)
-            .send()
-            .await?
-            .json::<String>()
-            .await?;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn submission(share_id: &str) -> ShareSubmission {
+        ShareSubmission {
+            share_id: share_id.to_string(),
+            wallet_address: "wallet".to_string(),
+            worker_name: "rig".to_string(),
+            target_name: "pool".to_string(),
+            difficulty: 1000.0,
+            valid: true,
+            status: ShareStatus::Accepted,
+            error_message: None,
+        }
+    }
+
+    /// Minimal batch endpoint: refuses share id "bad", reports "dup" as already recorded
+    async fn serve_batches(listener: TcpListener, requests: usize) {
+        for _ in 0..requests {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let body = loop {
+                let mut chunk = [0u8; 4096];
+                let n = socket.read(&mut chunk).await.unwrap();
+                request.extend_from_slice(&chunk[..n]);
+                let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") else {
+                    continue;
+                };
+                let headers = String::from_utf8_lossy(&request[..end]).to_lowercase();
+                let length: usize = headers
+                    .lines()
+                    .find_map(|line| line.strip_prefix("content-length:"))
+                    .map(|value| value.trim().parse().unwrap())
+                    .unwrap_or(0);
+                if request.len() >= end + 4 + length {
+                    break request[end + 4..end + 4 + length].to_vec();
+                }
+            };
+
+            let shares: Vec<serde_json::Value> = serde_json::from_slice(&body).unwrap();
+            let results: Vec<serde_json::Value> = shares
+                .iter()
+                .map(|share| {
+                    let outcome = match share["share_id"].as_str() {
+                        Some("bad") => "invalid",
+                        Some("dup") => "duplicate",
+                        _ => "created",
+                    };
+                    serde_json::json!({ "share_id": share["share_id"], "outcome": outcome })
+                })
+                .collect();
+            let body = serde_json::to_string(&results).unwrap();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replays_spool_in_batches() {
+        let dir = std::env::temp_dir().join(format!("defpool-recorder-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let spool = ShareSpool::open(&dir).unwrap();
+        for share_id in ["good", "bad", "dup"] {
+            spool.push(&submission(share_id)).await.unwrap();
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config: Config = toml::from_str(&format!(
+            "server_endpoint = \"http://{}\"\nlisten_address = \"127.0.0.1:0\"\nshare_spool_dir = {:?}\nshare_batch_size = 2",
+            listener.local_addr().unwrap(),
+            dir.display().to_string()
+        ))
+        .unwrap();
+        let server = tokio::spawn(serve_batches(listener, 2));
+        let _recorder = ShareRecorder::start(&config, ServerAuth::new(&config.server_auth)).unwrap();
+
+        tokio::time::timeout(Duration::from_secs(5), server).await.unwrap().unwrap();
+        let mut left = Vec::new();
+        for _ in 0..50 {
+            left = std::fs::read_dir(&dir)
+                .unwrap()
+                .map(|entry| entry.unwrap().path().extension().unwrap().to_string_lossy().into_owned())
+                .collect();
+            if left.len() == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(left, ["rejected"]);
+    }
 
-        Ok(target_name)
+    #[test]
+    fn test_classify_pool_errors() {
+        assert_eq!(ShareStatus::from_pool_error("Duplicate share"), ShareStatus::Duplicate);
+        assert_eq!(ShareStatus::from_pool_error("Block expired"), ShareStatus::Stale);
+        assert_eq!(ShareStatus::from_pool_error("Low difficulty share"), ShareStatus::Rejected);
     }
 }
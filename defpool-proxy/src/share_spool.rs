@@ -0,0 +1,123 @@
+use crate::share_recorder::ShareSubmission;
+use anyhow::{Context, Result};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+use tracing::warn;
+
+/// On-disk queue of shares waiting to be delivered to the server.
+///
+/// Each share is one JSON file, written to a temporary name and renamed into
+/// place, so a crash leaves either the whole share or nothing. File names start
+/// with the spool time, which keeps replay in submission order.
+pub struct ShareSpool {
+    dir: PathBuf,
+}
+
+impl ShareSpool {
+    /// Open (creating if needed) the spool directory
+    pub fn open(dir: impl Into<PathBuf>) -> Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("Failed to create share spool {}", dir.display()))?;
+
+        // Half-written shares from a crash were never acknowledged to anyone
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|ext| ext == "tmp") {
+                let _ = std::fs::remove_file(&path);
+            }
+        }
+
+        Ok(Self { dir })
+    }
+
+    /// Durably store a share
+    pub async fn push(&self, submission: &ShareSubmission) -> Result<()> {
+        let millis = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis();
+        let name = format!("{:013}-{}", millis, submission.share_id);
+        let tmp = self.dir.join(format!("{}.tmp", name));
+
+        let mut file = tokio::fs::File::create(&tmp).await?;
+        file.write_all(&serde_json::to_vec(submission)?).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(&tmp, self.dir.join(format!("{}.json", name))).await?;
+        Ok(())
+    }
+
+    /// Spooled share files, oldest first
+    pub async fn pending(&self) -> Result<Vec<PathBuf>> {
+        let mut entries = tokio::fs::read_dir(&self.dir).await?;
+        let mut paths = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "json") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    pub async fn load(&self, path: &Path) -> Result<ShareSubmission> {
+        let content = tokio::fs::read(path).await?;
+        Ok(serde_json::from_slice(&content)?)
+    }
+
+    /// Drop a delivered share
+    pub async fn remove(&self, path: &Path) -> Result<()> {
+        tokio::fs::remove_file(path).await?;
+        Ok(())
+    }
+
+    /// Keep a share that can never be delivered out of the queue, for inspection
+    pub async fn set_aside(&self, path: &Path) -> Result<()> {
+        warn!("Setting aside undeliverable share {}", path.display());
+        tokio::fs::rename(path, path.with_extension("rejected")).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::share_recorder::ShareStatus;
+
+    fn submission(share_id: &str) -> ShareSubmission {
+        ShareSubmission {
+            share_id: share_id.to_string(),
+            wallet_address: "wallet".to_string(),
+            worker_name: "rig".to_string(),
+            target_name: "pool".to_string(),
+            difficulty: 1000.0,
+            valid: true,
+            status: ShareStatus::Accepted,
+            error_message: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_spool_survives_reopen() {
+        let dir = std::env::temp_dir().join(format!("defpool-spool-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let spool = ShareSpool::open(&dir).unwrap();
+        spool.push(&submission("first")).await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+        spool.push(&submission("second")).await.unwrap();
+        std::fs::write(dir.join("0000000000000-torn.tmp"), b"{").unwrap();
+        drop(spool);
+
+        let spool = ShareSpool::open(&dir).unwrap();
+        let pending = spool.pending().await.unwrap();
+        let mut ids = Vec::new();
+        for path in &pending {
+            ids.push(spool.load(path).await.unwrap().share_id);
+        }
+        let leftover = std::fs::read_dir(&dir).unwrap().count();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(ids, ["first", "second"]);
+        assert_eq!(leftover, 2);
+    }
+}
@@ -0,0 +1,192 @@
+use crate::stratum::Sv1Message;
+use sha2::{Digest, Sha256};
+
+/// Difficulty 1 target mantissa (0xffff), shifted per algorithm below
+const DIFF1_MANTISSA: f64 = 65535.0;
+
+/// Proof-of-work function of a Bitcoin-family coin
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PowAlgorithm {
+    Sha256d,
+    Scrypt,
+}
+
+impl PowAlgorithm {
+    /// Map a server-side algorithm name (`"Scrypt"`, `"SHA256"`, ..)
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "sha256" | "sha256d" => Some(Self::Sha256d),
+            "scrypt" => Some(Self::Scrypt),
+            _ => None,
+        }
+    }
+
+    fn hash(&self, header: &[u8; 80]) -> [u8; 32] {
+        match self {
+            Self::Sha256d => sha256d(header),
+            Self::Scrypt => {
+                let params = scrypt::Params::new(10, 1, 1, 32).expect("valid scrypt params");
+                let mut hash = [0u8; 32];
+                scrypt::scrypt(header, header, &params, &mut hash).expect("valid scrypt output length");
+                hash
+            }
+        }
+    }
+
+    /// Little-endian 256-bit share target for a difficulty
+    pub fn target(&self, difficulty: f64) -> [u8; 32] {
+        let mut value = self.diff1() / difficulty.max(f64::MIN_POSITIVE);
+        let mut target = [0xffu8; 32];
+        if value >= 2f64.powi(256) {
+            return target;
+        }
+        for (i, byte) in target.iter_mut().enumerate().rev() {
+            let unit = 2f64.powi(8 * i as i32);
+            let digit = (value / unit).floor().min(255.0);
+            *byte = digit as u8;
+            value -= digit * unit;
+        }
+        target
+    }
+
+    /// Hash value of a share at difficulty 1
+    fn diff1(&self) -> f64 {
+        match self {
+            // 0x00000000ffff0000..
+            Self::Sha256d => DIFF1_MANTISSA * 2f64.powi(208),
+            // Scrypt pools use 0x0000ffff0000..
+            Self::Scrypt => DIFF1_MANTISSA * 2f64.powi(224),
+        }
+    }
+}
+
+/// `mining.notify` fields needed to rebuild a block header
+#[derive(Debug, Clone)]
+pub struct BitcoinJob {
+    pub job_id: String,
+    prev_hash: Vec<u8>,
+    coinbase1: Vec<u8>,
+    coinbase2: Vec<u8>,
+    merkle_branch: Vec<Vec<u8>>,
+    pub version: u32,
+    pub nbits: u32,
+    pub ntime: u32,
+    /// Whether the pool drops every earlier job
+    pub clean_jobs: bool,
+}
+
+impl BitcoinJob {
+    /// Parse `[job_id, prevhash, coinb1, coinb2, merkle_branch, version, nbits, ntime, clean_jobs]`
+    pub fn from_notify(msg: &Sv1Message) -> Option<Self> {
+        let params = msg.params.as_ref()?;
+        let merkle_branch = params
+            .get(4)?
+            .as_array()?
+            .iter()
+            .map(|branch| hex::decode(branch.as_str()?).ok())
+            .collect::<Option<Vec<_>>>()?;
+
+        Some(Self {
+            job_id: msg.param_str(0)?.to_string(),
+            prev_hash: hex::decode(msg.param_str(1)?).ok().filter(|hash| hash.len() == 32)?,
+            coinbase1: hex::decode(msg.param_str(2)?).ok()?,
+            coinbase2: hex::decode(msg.param_str(3)?).ok()?,
+            merkle_branch,
+            version: u32::from_str_radix(msg.param_str(5)?, 16).ok()?,
+            nbits: u32::from_str_radix(msg.param_str(6)?, 16).ok()?,
+            ntime: u32::from_str_radix(msg.param_str(7)?, 16).ok()?,
+            clean_jobs: msg.params.as_ref()?.get(8).and_then(|clean| clean.as_bool()).unwrap_or(false),
+        })
+    }
+
+    /// Previous block hash in header byte order
+    pub fn header_prev_hash(&self) -> [u8; 32] {
+        // Stratum sends the previous hash as byte-swapped 32-bit words
+        let mut prev_hash = [0u8; 32];
+        for (word, chunk) in self.prev_hash.chunks(4).enumerate() {
+            for (i, byte) in chunk.iter().rev().enumerate() {
+                prev_hash[word * 4 + i] = *byte;
+            }
+        }
+        prev_hash
+    }
+
+    /// Merkle root of the block with the coinbase built around the extranonces
+    pub fn merkle_root(&self, extranonce1: &[u8], extranonce2: &[u8]) -> [u8; 32] {
+        let coinbase = [&self.coinbase1[..], extranonce1, extranonce2, &self.coinbase2].concat();
+        self.merkle_branch
+            .iter()
+            .fold(sha256d(&coinbase), |root, branch| sha256d(&[&root[..], branch].concat()))
+    }
+
+    /// Difficulty of a `mining.submit` (`[worker, job_id, extranonce2, ntime, nonce]`)
+    pub fn share_difficulty(&self, algorithm: PowAlgorithm, extranonce1: &[u8], submit: &Sv1Message) -> Option<f64> {
+        let params = submit.params.as_ref()?;
+        if params.len() > 5 {
+            // Version rolling changes the header in ways we don't track
+            return None;
+        }
+
+        let extranonce2 = hex::decode(submit.param_str(2)?).ok()?;
+        let ntime = u32::from_str_radix(submit.param_str(3)?, 16).ok()?;
+        let nonce = u32::from_str_radix(submit.param_str(4)?, 16).ok()?;
+
+        let mut header = [0u8; 80];
+        header[0..4].copy_from_slice(&self.version.to_le_bytes());
+        header[4..36].copy_from_slice(&self.header_prev_hash());
+        header[36..68].copy_from_slice(&self.merkle_root(extranonce1, &extranonce2));
+        header[68..72].copy_from_slice(&ntime.to_le_bytes());
+        header[72..76].copy_from_slice(&self.nbits.to_le_bytes());
+        header[76..80].copy_from_slice(&nonce.to_le_bytes());
+
+        let hash = algorithm.hash(&header);
+        Some(algorithm.diff1() / hash_value(&hash))
+    }
+}
+
+/// Hash as a number, read little-endian
+fn hash_value(hash: &[u8; 32]) -> f64 {
+    hash.iter().rev().fold(0.0, |value, byte| value * 256.0 + *byte as f64).max(1.0)
+}
+
+fn sha256d(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(Sha256::digest(data)).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bitcoin_genesis_header() {
+        // Bitcoin genesis block, split at the extranonce for the test
+        let coinbase = "01000000010000000000000000000000000000000000000000000000000000000000000000ffffffff4d04ffff001d0104455468652054696d65732030332f4a616e2f32303039204368616e63656c6c6f72206f6e206272696e6b206f66207365636f6e64206261696c6f757420666f722062616e6b73ffffffff0100f2052a01000000434104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac00000000";
+        let notify = format!(
+            r#"{{"id":null,"method":"mining.notify","params":["g","{}","{}","{}",[],"00000001","1d00ffff","495fab29",true]}}"#,
+            "0".repeat(64),
+            &coinbase[..20],
+            &coinbase[28..],
+        );
+        let job = BitcoinJob::from_notify(&Sv1Message::from_json(&notify).unwrap()).unwrap();
+
+        let submit = r#"{"id":3,"method":"mining.submit","params":["w","g","0000","495fab29","7c2bac1d"]}"#;
+        let extranonce1 = hex::decode(&coinbase[20..24]).unwrap();
+        let difficulty = job
+            .share_difficulty(PowAlgorithm::Sha256d, &extranonce1, &Sv1Message::from_json(submit).unwrap())
+            .unwrap();
+
+        // The genesis block has difficulty ~2536 against diff1
+        assert!(difficulty > 2000.0 && difficulty < 3000.0);
+    }
+
+    #[test]
+    fn test_share_target() {
+        // Difficulty 1 is 0x00000000ffff0000.. for SHA256d
+        let target = PowAlgorithm::Sha256d.target(1.0);
+        assert_eq!(&target[24..], &[0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x00, 0x00]);
+        assert!(target[..24].iter().all(|b| *b == 0));
+
+        let target = PowAlgorithm::Scrypt.target(2.0);
+        assert_eq!(&target[26..], &[0x00, 0x80, 0xff, 0x7f, 0x00, 0x00]);
+    }
+}
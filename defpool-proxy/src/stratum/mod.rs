@@ -2,4 +2,4 @@ pub mod sv1;
 pub mod sv2;
 pub mod translator;
 
-pub use sv1::Sv1Message;
+pub use sv1::{Sv1Message, Sv1Method};
@@ -1,4 +1,4 @@
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::Value;
 
 /// Stratum V1 message
@@ -6,19 +6,58 @@ use serde_json::Value;
 pub struct Sv1Message {
     pub id: Option<Value>,
     pub method: Option<String>,
+    /// Positional params; Monero-style object params are kept as a single element
+    #[serde(
+        default,
+        serialize_with = "serialize_params",
+        deserialize_with = "deserialize_params"
+    )]
     pub params: Option<Vec<Value>>,
     pub result: Option<Value>,
     pub error: Option<Value>,
 }
 
+/// Accept both `[..]` (Bitcoin dialect) and `{..}` (Monero dialect) params
+fn deserialize_params<'de, D>(deserializer: D) -> Result<Option<Vec<Value>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(match Option::<Value>::deserialize(deserializer)? {
+        Some(Value::Array(params)) => Some(params),
+        Some(Value::Null) | None => None,
+        Some(other) => Some(vec![other]),
+    })
+}
+
+/// Write a single object param back out in Monero object form
+fn serialize_params<S>(params: &Option<Vec<Value>>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match params.as_deref() {
+        Some([object @ Value::Object(_)]) => object.serialize(serializer),
+        other => other.serialize(serializer),
+    }
+}
+
 /// Stratum V1 methods
 #[allow(dead_code)]
 #[derive(Debug, Clone, PartialEq)]
 pub enum Sv1Method {
+    // Monero dialect
     Login,
     GetJob,
     Submit,
     KeepAlive,
+    Job,
+    // Bitcoin dialect
+    Subscribe,
+    Authorize,
+    Notify,
+    SetDifficulty,
+    MiningSubmit,
+    ExtranonceSubscribe,
+    SetExtranonce,
     Unknown(String),
 }
 
@@ -30,6 +69,14 @@ impl Sv1Method {
             "getjob" => Self::GetJob,
             "submit" => Self::Submit,
             "keepalived" => Self::KeepAlive,
+            "job" => Self::Job,
+            "mining.subscribe" => Self::Subscribe,
+            "mining.authorize" => Self::Authorize,
+            "mining.notify" => Self::Notify,
+            "mining.set_difficulty" => Self::SetDifficulty,
+            "mining.submit" => Self::MiningSubmit,
+            "mining.extranonce.subscribe" => Self::ExtranonceSubscribe,
+            "mining.set_extranonce" => Self::SetExtranonce,
             _ => Self::Unknown(s.to_string()),
         }
     }
@@ -40,9 +87,31 @@ impl Sv1Method {
             Self::GetJob => "getjob",
             Self::Submit => "submit",
             Self::KeepAlive => "keepalived",
+            Self::Job => "job",
+            Self::Subscribe => "mining.subscribe",
+            Self::Authorize => "mining.authorize",
+            Self::Notify => "mining.notify",
+            Self::SetDifficulty => "mining.set_difficulty",
+            Self::MiningSubmit => "mining.submit",
+            Self::ExtranonceSubscribe => "mining.extranonce.subscribe",
+            Self::SetExtranonce => "mining.set_extranonce",
             Self::Unknown(s) => s,
         }
     }
+
+    /// Whether this method belongs to the Bitcoin (`mining.*`) dialect
+    pub fn is_bitcoin_dialect(&self) -> bool {
+        matches!(
+            self,
+            Self::Subscribe
+                | Self::Authorize
+                | Self::Notify
+                | Self::SetDifficulty
+                | Self::MiningSubmit
+                | Self::ExtranonceSubscribe
+                | Self::SetExtranonce
+        )
+    }
 }
 
 impl Sv1Message {
@@ -66,45 +135,128 @@ impl Sv1Message {
         self.result.is_some() || self.error.is_some()
     }
 
-    /// Create a login request
-    pub fn login(id: u64, wallet: &str, worker: &str) -> Self {
+    /// Get the Monero-style object params (`{"login": ..}`, `{"job_id": ..}`)
+    pub fn param_object(&self) -> Option<&serde_json::Map<String, Value>> {
+        self.params.as_ref()?.first()?.as_object()
+    }
+
+    /// Get the Monero-style result object of a response
+    pub fn result_object(&self) -> Option<&serde_json::Map<String, Value>> {
+        self.result.as_ref()?.as_object()
+    }
+
+    /// Get a positional param as a string
+    pub fn param_str(&self, index: usize) -> Option<&str> {
+        self.params.as_ref()?.get(index)?.as_str()
+    }
+
+    /// Job id a share refers to (`submit` or `mining.submit`)
+    pub fn submit_job_id(&self) -> Option<&str> {
+        match self.get_method()? {
+            Sv1Method::Submit => match self.params.as_ref()?.first()? {
+                Value::Object(params) => params.get("job_id")?.as_str(),
+                Value::String(job_id) => Some(job_id),
+                _ => None,
+            },
+            // [worker, job_id, extranonce2, ntime, nonce]
+            Sv1Method::MiningSubmit => self.param_str(1),
+            _ => None,
+        }
+    }
+
+    /// Share difficulty announced by `mining.set_difficulty`
+    pub fn set_difficulty(&self) -> Option<f64> {
+        if self.get_method()? != Sv1Method::SetDifficulty {
+            return None;
+        }
+        self.params.as_ref()?.first()?.as_f64()
+    }
+
+    /// Whether a response reports success (`result: false` counts as a rejection)
+    pub fn is_accepted(&self) -> bool {
+        self.error_message().is_none() && self.result != Some(Value::Bool(false))
+    }
+
+    /// Extranonce1 and extranonce2 size from a `mining.subscribe` result
+    /// (`[[subscriptions], extranonce1, extranonce2_size]`)
+    pub fn subscribe_extranonce(&self) -> Option<(Vec<u8>, usize)> {
+        let result = self.result.as_ref()?;
+        let extranonce1 = hex::decode(result.get(1)?.as_str()?).ok()?;
+        let extranonce2_size = result.get(2)?.as_u64()?;
+        Some((extranonce1, extranonce2_size as usize))
+    }
+
+    /// Get the numeric request id, if any
+    pub fn numeric_id(&self) -> Option<u64> {
+        self.id.as_ref()?.as_u64()
+    }
+
+    /// Get the error message of a failed response
+    pub fn error_message(&self) -> Option<String> {
+        let error = self.error.as_ref().filter(|e| !e.is_null())?;
+        // Monero pools send {"code", "message"}, Bitcoin pools send [code, "message", data]
+        let message = error.get("message").or_else(|| error.get(1));
+        Some(
+            message
+                .and_then(|m| m.as_str())
+                .map(|m| m.to_string())
+                .unwrap_or_else(|| error.to_string()),
+        )
+    }
+
+    /// Create a `mining.subscribe` request to an upstream pool
+    pub fn pool_subscribe(id: u64) -> Self {
+        Self::request(id, "mining.subscribe", vec![
+            Value::String(concat!("defpool-proxy/", env!("CARGO_PKG_VERSION")).to_string()),
+        ])
+    }
+
+    /// Create a `mining.authorize` request to an upstream pool
+    pub fn pool_authorize(id: u64, login: &str, pass: &str) -> Self {
+        Self::request(id, "mining.authorize", vec![
+            Value::String(login.to_string()),
+            Value::String(pass.to_string()),
+        ])
+    }
+
+    /// Create a `mining.submit` request (`[worker, job_id, extranonce2, ntime, nonce]`)
+    pub fn pool_mining_submit(id: u64, worker: &str, job_id: &str, extranonce2: &str, ntime: u32, nonce: u32) -> Self {
+        Self::request(id, "mining.submit", vec![
+            Value::String(worker.to_string()),
+            Value::String(job_id.to_string()),
+            Value::String(extranonce2.to_string()),
+            Value::String(format!("{:08x}", ntime)),
+            Value::String(format!("{:08x}", nonce)),
+        ])
+    }
+
+    fn request(id: u64, method: &str, params: Vec<Value>) -> Self {
         Self {
             id: Some(Value::Number(id.into())),
-            method: Some("login".to_string()),
-            params: Some(vec![
-                Value::String(format!("{}:{}", wallet, worker)),
-            ]),
+            method: Some(method.to_string()),
+            params: Some(params),
             result: None,
             error: None,
         }
     }
 
-    /// Create a submit request
-    pub fn submit(id: u64, job_id: &str, nonce: &str, result: &str) -> Self {
+    /// Create a `mining.set_difficulty` notification
+    pub fn difficulty_notification(difficulty: f64) -> Self {
         Self {
-            id: Some(Value::Number(id.into())),
-            method: Some("submit".to_string()),
-            params: Some(vec![
-                Value::String(job_id.to_string()),
-                Value::String(nonce.to_string()),
-                Value::String(result.to_string()),
-            ]),
+            id: None,
+            method: Some("mining.set_difficulty".to_string()),
+            params: Some(vec![serde_json::json!(difficulty)]),
             result: None,
             error: None,
         }
     }
 
-    /// Create a job notification
-    pub fn job(job_id: &str, blob: &str, target: &str, height: u64) -> Self {
+    /// Create a `mining.set_extranonce` notification
+    pub fn extranonce_notification(extranonce1: &[u8], extranonce2_size: usize) -> Self {
         Self {
             id: None,
-            method: Some("job".to_string()),
-            params: Some(vec![
-                Value::String(job_id.to_string()),
-                Value::String(blob.to_string()),
-                Value::String(target.to_string()),
-                Value::Number(height.into()),
-            ]),
+            method: Some("mining.set_extranonce".to_string()),
+            params: Some(vec![serde_json::json!(hex::encode(extranonce1)), serde_json::json!(extranonce2_size)]),
             result: None,
             error: None,
         }
@@ -122,7 +274,6 @@ impl Sv1Message {
     }
 
     /// Create an error response
-    #[allow(dead_code)]
     pub fn error_response(id: Value, code: i32, message: &str) -> Self {
         Self {
             id: Some(id),
@@ -149,9 +300,31 @@ mod tests {
     }
 
     #[test]
-    fn test_serialize_job() {
-        let msg = Sv1Message::job("job1", "blob", "target", 100);
-        let json = msg.to_json().unwrap();
-        assert!(json.contains("job"));
+    fn test_object_params_round_trip() {
+        let json = r#"{"id":1,"method":"login","params":{"login":"wallet","pass":"x"}}"#;
+        let msg = Sv1Message::from_json(json).unwrap();
+        assert_eq!(msg.param_object().unwrap()["login"], "wallet");
+
+        let out = msg.to_json().unwrap();
+        assert!(out.contains(r#""params":{"#));
+    }
+
+    #[test]
+    fn test_parse_bitcoin_dialect() {
+        let json = r#"{"id":4,"method":"mining.submit","params":["ltc1qwallet.rig1","bf","00000001","5a9e4c1d","1f2e3d4c"]}"#;
+        let msg = Sv1Message::from_json(json).unwrap();
+        assert_eq!(msg.get_method(), Some(Sv1Method::MiningSubmit));
+        assert_eq!(msg.submit_job_id(), Some("bf"));
+
+        let json = r#"{"id":null,"method":"mining.set_difficulty","params":[512]}"#;
+        let msg = Sv1Message::from_json(json).unwrap();
+        assert_eq!(msg.set_difficulty(), Some(512.0));
+
+        let json = r#"{"id":4,"result":false,"error":null}"#;
+        assert!(!Sv1Message::from_json(json).unwrap().is_accepted());
+
+        let json = r#"{"id":1,"result":[[["mining.notify","ae"]],"08000002",4],"error":null}"#;
+        let msg = Sv1Message::from_json(json).unwrap();
+        assert_eq!(msg.subscribe_extranonce(), Some((vec![0x08, 0x00, 0x00, 0x02], 4)));
     }
 }
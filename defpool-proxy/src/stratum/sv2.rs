@@ -4,7 +4,6 @@
 use stratum_core::parsers_sv2::AnyMessage;
 
 /// Extract message type from SV2 message
-#[allow(dead_code)]
 pub fn get_message_type(msg: &AnyMessage) -> &'static str {
     match msg {
         AnyMessage::Mining(mining_msg) => {
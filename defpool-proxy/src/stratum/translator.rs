@@ -1,200 +1,540 @@
 use super::sv1::{Sv1Message, Sv1Method};
-use anyhow::Result;
+use crate::share_validator::{BitcoinJob, PowAlgorithm};
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
+use stratum_core::{
+    binary_sv2::{Str0255, Sv2Option, B032, U256},
+    common_messages_sv2::{SetupConnection, SetupConnectionError, SetupConnectionSuccess},
+    mining_sv2::{
+        NewMiningJob, OpenMiningChannelError, OpenStandardMiningChannel,
+        OpenStandardMiningChannelSuccess, SetNewPrevHash, SetTarget, SubmitSharesError,
+        SubmitSharesStandard, SubmitSharesSuccess,
+    },
+    parsers_sv2::{CommonMessages, Mining},
+};
 use tracing::{debug, warn};
 
+/// SV2 protocol version negotiated with downstream miners
+const SV2_PROTOCOL_VERSION: u16 = 2;
+
+/// The single standard channel opened per SV2 connection
+const CHANNEL_ID: u32 = 1;
+
+/// Number of translated jobs kept for late share submissions
+const MAX_TRANSLATED_JOBS: usize = 16;
+
+/// A translated `mining.notify`, keyed by its SV2 job id
+#[derive(Debug, Clone)]
+struct TranslatedJob {
+    sv1_job_id: String,
+    version: u32,
+}
+
+/// A share forwarded upstream and waiting for the pool's answer
+#[derive(Debug, Clone)]
+struct PendingSubmit {
+    sequence_number: u32,
+    difficulty: f64,
+}
+
+/// Result of translating an SV2 share submission
+#[derive(Debug)]
+pub enum SubmitTranslation {
+    /// Forward this SV1 submit to the upstream pool
+    Upstream(Sv1Message),
+    /// Answer the miner directly without contacting the pool
+    Reject(Mining<'static>),
+}
+
+/// A bridged share the pool has answered
+#[derive(Debug, Clone)]
+pub struct SettledShare {
+    pub difficulty: f64,
+    /// Pool error message for shares that were not accepted
+    pub error_message: Option<String>,
+}
+
 /// Translator between Stratum V1 and V2 protocols
-#[allow(dead_code)]
 pub struct StratumTranslator {
-    /// Map SV1 job IDs to SV2 job IDs
-    job_id_map: Arc<Mutex<HashMap<String, String>>>,
-    /// Current job counter
-    job_counter: Arc<Mutex<u64>>,
     /// Wallet address for this connection
     wallet_address: Option<String>,
     /// Worker name for this connection
     worker_name: Option<String>,
+    /// Proof-of-work of the upstream coin, for share targets
+    algorithm: PowAlgorithm,
+    /// SV2 jobs sent downstream, keyed by SV2 job id
+    jobs: HashMap<u32, TranslatedJob>,
+    /// Shares sent upstream, keyed by SV1 request id
+    pending_submits: HashMap<u64, PendingSubmit>,
+    /// Shares the pool has answered since they were last taken
+    settled_shares: Vec<SettledShare>,
+    /// Last SV2 job id sent downstream
+    last_job_id: u32,
+    /// Last request id used towards the upstream pool
+    last_request_id: u64,
+    /// Login the upstream session is authorized with
+    login: Option<String>,
+    /// Request id of the upstream `mining.subscribe`, until it is answered
+    subscribe_request_id: Option<u64>,
+    /// Request id of the upstream `mining.authorize`, until it is answered
+    authorize_request_id: Option<u64>,
+    /// SV2 request id of the channel waiting for the upstream session
+    open_channel_request_id: Option<u32>,
+    /// Extranonce1 the pool assigned on subscribe
+    extranonce1: Option<Vec<u8>>,
+    /// Fixed extranonce2 the channel's coinbases are completed with
+    extranonce2: Vec<u8>,
+    /// Whether the pool accepted the login
+    authorized: bool,
+    /// Whether the downstream channel is open
+    channel_open: bool,
+    /// Share difficulty set by the pool
+    difficulty: f64,
+    /// Latest pool job, sent when the channel opens
+    latest_job: Option<BitcoinJob>,
+    /// Last previous-block hash sent downstream
+    current_prev_hash: Option<[u8; 32]>,
 }
 
-#[allow(dead_code)]
+/// SV2 downstream ↔ SV1 upstream bridging.
+///
+/// The standard channel is backed by one Bitcoin-dialect session: the pool's
+/// coinbase is completed with the session's extranonce1 and a fixed
+/// extranonce2, so the miner rolls the nonce and ntime of a plain header.
 impl StratumTranslator {
-    pub fn new() -> Self {
-        Self {
-            job_id_map: Arc::new(Mutex::new(HashMap::new())),
-            job_counter: Arc::new(Mutex::new(0)),
+    /// Translator for pools of an algorithm, or None when SV2 miners cannot
+    /// mine it: Monero-style pools hash RandomX blobs, not block headers.
+    pub fn for_algorithm(algorithm: &str) -> Option<Self> {
+        Some(Self {
             wallet_address: None,
             worker_name: None,
-        }
+            algorithm: PowAlgorithm::from_name(algorithm)?,
+            jobs: HashMap::new(),
+            pending_submits: HashMap::new(),
+            settled_shares: Vec::new(),
+            last_job_id: 0,
+            last_request_id: 0,
+            login: None,
+            subscribe_request_id: None,
+            authorize_request_id: None,
+            open_channel_request_id: None,
+            extranonce1: None,
+            extranonce2: Vec::new(),
+            authorized: false,
+            channel_open: false,
+            difficulty: 1.0,
+            latest_job: None,
+            current_prev_hash: None,
+        })
+    }
+
+    /// Wallet the channel mines for
+    pub fn wallet_address(&self) -> Option<&str> {
+        self.wallet_address.as_deref()
+    }
+
+    /// Worker name the channel was opened with
+    pub fn worker_name(&self) -> Option<&str> {
+        self.worker_name.as_deref()
+    }
+
+    /// Take the shares the pool has answered, for recording
+    pub fn take_settled_shares(&mut self) -> Vec<SettledShare> {
+        std::mem::take(&mut self.settled_shares)
+    }
+
+    /// Answer a downstream SetupConnection locally
+    pub fn setup_connection(&self, msg: &SetupConnection) -> CommonMessages<'static> {
+        debug!("SV2 SetupConnection: {}", msg);
+        CommonMessages::SetupConnectionSuccess(SetupConnectionSuccess {
+            used_version: SV2_PROTOCOL_VERSION,
+            flags: 0,
+        })
     }
 
-    /// Handle SV1 login message
-    pub fn handle_sv1_login(&mut self, msg: &Sv1Message) -> Result<Sv1Message> {
-        debug!("Handling SV1 login");
+    /// Refuse a downstream SetupConnection for an upstream that cannot be bridged
+    pub fn refuse_connection() -> CommonMessages<'static> {
+        CommonMessages::SetupConnectionError(SetupConnectionError {
+            flags: 0,
+            error_code: str0255("unsupported-protocol"),
+        })
+    }
+
+    /// Turn an OpenStandardMiningChannel into an upstream subscribe and authorize.
+    ///
+    /// The channel is confirmed once the pool answers both.
+    pub fn open_standard_channel(
+        &mut self,
+        msg: &OpenStandardMiningChannel,
+        default_wallet: Option<&str>,
+    ) -> Vec<Sv1Message> {
+        let identity = msg.user_identity.as_utf8_or_hex();
+        let login = if identity.is_empty() {
+            default_wallet.unwrap_or_default().to_string()
+        } else {
+            identity
+        };
+
+        let mut parts = login.splitn(2, '.');
+        self.wallet_address = parts.next().map(|s| s.to_string());
+        self.worker_name = parts.next().map(|s| s.to_string());
+        self.open_channel_request_id = Some(msg.request_id.as_u32());
 
-        // Extract wallet and worker from params
-        if let Some(params) = &msg.params {
-            if let Some(Value::String(login)) = params.first() {
-                let parts: Vec<&str> = login.split(':').collect();
-                self.wallet_address = Some(parts[0].to_string());
-                self.worker_name = parts.get(1).map(|s| s.to_string());
+        let subscribe_id = self.next_request_id();
+        let authorize_id = self.next_request_id();
+        self.subscribe_request_id = Some(subscribe_id);
+        self.authorize_request_id = Some(authorize_id);
 
-                debug!(
-                    "Login: wallet={}, worker={:?}",
-                    self.wallet_address.as_ref().unwrap(),
-                    self.worker_name
-                );
+        debug!("Opening standard channel for {}", login);
+        let authorize = Sv1Message::pool_authorize(authorize_id, &login, "x");
+        self.login = Some(login);
+        vec![Sv1Message::pool_subscribe(subscribe_id), authorize]
+    }
+
+    /// Translate a message received from the SV1 upstream pool
+    pub fn process_upstream_message(&mut self, msg: &Sv1Message) -> Vec<Mining<'static>> {
+        match msg.get_method() {
+            Some(Sv1Method::Notify) => return self.handle_notify(msg),
+            Some(Sv1Method::SetDifficulty) => return self.handle_set_difficulty(msg),
+            Some(method) => {
+                debug!("Ignoring SV1 {} from pool", method.as_str());
+                return Vec::new();
             }
+            None => {}
         }
 
-        // Create success response
-        let response = Sv1Message::ok_response(
-            msg.id.clone().unwrap_or(serde_json::Value::Null),
-            serde_json::json!({
-                "id": "proxy_connection",
-                "job": {
-                    "job_id": "initial",
-                    "blob": "",
-                    "target": "00000000"
-                },
-                "status": "OK"
-            }),
+        let Some(id) = msg.numeric_id() else {
+            return Vec::new();
+        };
+
+        if self.subscribe_request_id == Some(id) {
+            self.subscribe_request_id = None;
+            return self.handle_subscribe_response(msg);
+        }
+        if self.authorize_request_id == Some(id) {
+            self.authorize_request_id = None;
+            return self.handle_authorize_response(msg);
+        }
+
+        self.handle_submit_response(id, msg).into_iter().collect()
+    }
+
+    /// Translate an SV2 share into a `mining.submit`
+    pub fn translate_submit(&mut self, submit: &SubmitSharesStandard) -> SubmitTranslation {
+        if submit.channel_id != CHANNEL_ID || !self.channel_open {
+            return SubmitTranslation::Reject(submit_error(submit, "invalid-channel-id"));
+        }
+        let Some(job) = self.jobs.get(&submit.job_id).cloned() else {
+            return SubmitTranslation::Reject(submit_error(submit, "invalid-job-id"));
+        };
+        if submit.version != job.version {
+            // Version rolling is not negotiated with the pool
+            return SubmitTranslation::Reject(submit_error(submit, "version-rolling-unsupported"));
+        }
+        let login = self.login.clone().unwrap_or_default();
+
+        let id = self.next_request_id();
+        self.pending_submits.insert(
+            id,
+            PendingSubmit {
+                sequence_number: submit.sequence_number,
+                difficulty: self.difficulty,
+            },
         );
 
-        Ok(response)
+        SubmitTranslation::Upstream(Sv1Message::pool_mining_submit(
+            id,
+            &login,
+            &job.sv1_job_id,
+            &hex::encode(&self.extranonce2),
+            submit.ntime,
+            submit.nonce,
+        ))
     }
 
-    /// Handle SV1 submit message
-    pub fn handle_sv1_submit(&self, msg: &Sv1Message) -> Result<Sv1Message> {
-        debug!("Handling SV1 submit");
+    /// `[[subscriptions], extranonce1, extranonce2_size]`
+    fn handle_subscribe_response(&mut self, msg: &Sv1Message) -> Vec<Mining<'static>> {
+        if let Some(error) = msg.error_message() {
+            warn!("Upstream subscribe rejected: {}", error);
+            return self.channel_error(&format!("upstream-subscribe-failed: {}", error));
+        }
+
+        let Some((extranonce1, extranonce2_size)) = msg.subscribe_extranonce() else {
+            warn!("Upstream subscribe response without extranonce");
+            return self.channel_error("upstream-subscribe-failed");
+        };
 
-        // Extract submit parameters
-        if let Some(params) = &msg.params {
-            if params.len() >= 3 {
-                let job_id = params[0].as_str().unwrap_or("");
-                let nonce = params[1].as_str().unwrap_or("");
-                let result = params[2].as_str().unwrap_or("");
+        self.extranonce1 = Some(extranonce1);
+        self.extranonce2 = vec![0; extranonce2_size];
+        self.open_channel()
+    }
 
-                debug!("Submit: job_id={}, nonce={}, result={}", job_id, nonce, result);
+    fn handle_authorize_response(&mut self, msg: &Sv1Message) -> Vec<Mining<'static>> {
+        if !msg.is_accepted() {
+            let error = msg.error_message().unwrap_or_else(|| "unauthorized".to_string());
+            warn!("Upstream login rejected: {}", error);
+            return self.channel_error(&format!("upstream-login-failed: {}", error));
+        }
 
-                // TODO: Translate to SV2 share submission
-                // For now, accept the share
-                let response = Sv1Message::ok_response(
-                    msg.id.clone().unwrap_or(serde_json::Value::Null),
-                    serde_json::json!({"status": "OK"}),
-                );
+        self.authorized = true;
+        self.open_channel()
+    }
 
-                return Ok(response);
-            }
+    /// Confirm the channel once the pool has both subscribed and authorized the session
+    fn open_channel(&mut self) -> Vec<Mining<'static>> {
+        if self.channel_open || !self.authorized || self.extranonce1.is_none() {
+            return Vec::new();
         }
+        let Some(request_id) = self.open_channel_request_id.take() else {
+            return Vec::new();
+        };
+        self.channel_open = true;
 
-        // Invalid submit
-        Ok(Sv1Message::error_response(
-            msg.id.clone().unwrap_or(serde_json::Value::Null),
-            -1,
-            "Invalid submit parameters",
-        ))
+        let mut messages = vec![Mining::OpenStandardMiningChannelSuccess(
+            OpenStandardMiningChannelSuccess {
+                request_id: request_id.into(),
+                channel_id: CHANNEL_ID,
+                target: U256::from(self.algorithm.target(self.difficulty)),
+                extranonce_prefix: B032::try_from(Vec::new()).expect("empty extranonce fits"),
+                group_channel_id: 0,
+            },
+        )];
+
+        if let Some(job) = self.latest_job.clone() {
+            messages.extend(self.translate_job(&job));
+        }
+        messages
     }
 
-    /// Generate a new SV1 job ID
-    pub fn generate_job_id(&self) -> String {
-        let mut counter = self.job_counter.lock().unwrap();
-        *counter += 1;
-        format!("job_{}", *counter)
+    fn channel_error(&mut self, error_code: &str) -> Vec<Mining<'static>> {
+        let Some(request_id) = self.open_channel_request_id.take() else {
+            return Vec::new();
+        };
+        vec![Mining::OpenMiningChannelError(OpenMiningChannelError {
+            request_id,
+            error_code: str0255(error_code),
+        })]
     }
 
-    /// Map SV2 job to SV1 job
-    pub fn map_job(&self, sv2_job_id: String, sv1_job_id: String) {
-        let mut map = self.job_id_map.lock().unwrap();
-        map.insert(sv1_job_id, sv2_job_id);
+    fn handle_submit_response(&mut self, id: u64, msg: &Sv1Message) -> Option<Mining<'static>> {
+        let pending = self.pending_submits.remove(&id)?;
+
+        if !msg.is_accepted() {
+            let error = msg.error_message().unwrap_or_else(|| "rejected".to_string());
+            debug!("Upstream rejected share {}: {}", pending.sequence_number, error);
+            self.settled_shares.push(SettledShare {
+                difficulty: pending.difficulty,
+                error_message: Some(error.clone()),
+            });
+            return Some(Mining::SubmitSharesError(SubmitSharesError {
+                channel_id: CHANNEL_ID,
+                sequence_number: pending.sequence_number,
+                error_code: str0255(&error),
+            }));
+        }
+
+        self.settled_shares.push(SettledShare {
+            difficulty: pending.difficulty,
+            error_message: None,
+        });
+        Some(Mining::SubmitSharesSuccess(SubmitSharesSuccess {
+            channel_id: CHANNEL_ID,
+            last_sequence_number: pending.sequence_number,
+            new_submits_accepted_count: 1,
+            // Pools may set fractional difficulties; never report less work than was done
+            new_shares_sum: pending.difficulty.ceil() as u64,
+        }))
     }
 
-    /// Get SV2 job ID from SV1 job ID
-    pub fn get_sv2_job_id(&self, sv1_job_id: &str) -> Option<String> {
-        let map = self.job_id_map.lock().unwrap();
-        map.get(sv1_job_id).cloned()
+    fn handle_set_difficulty(&mut self, msg: &Sv1Message) -> Vec<Mining<'static>> {
+        let Some(difficulty) = msg.set_difficulty().filter(|difficulty| *difficulty > 0.0) else {
+            warn!("Invalid mining.set_difficulty from pool");
+            return Vec::new();
+        };
+        self.difficulty = difficulty;
+
+        if !self.channel_open {
+            return Vec::new();
+        }
+        vec![Mining::SetTarget(SetTarget {
+            channel_id: CHANNEL_ID,
+            maximum_target: U256::from(self.algorithm.target(difficulty)),
+        })]
     }
 
-    /// Get wallet address
-    pub fn wallet_address(&self) -> Option<&str> {
-        self.wallet_address.as_deref()
+    fn handle_notify(&mut self, msg: &Sv1Message) -> Vec<Mining<'static>> {
+        let Some(job) = BitcoinJob::from_notify(msg) else {
+            warn!("Cannot translate invalid mining.notify from pool");
+            return Vec::new();
+        };
+
+        if job.clean_jobs {
+            self.jobs.clear();
+        }
+        self.latest_job = Some(job.clone());
+
+        if !self.channel_open {
+            return Vec::new();
+        }
+        self.translate_job(&job)
     }
 
-    /// Get worker name
-    pub fn worker_name(&self) -> Option<&str> {
-        self.worker_name.as_deref()
+    /// Translate a pool job into NewMiningJob, plus SetNewPrevHash on a new block
+    fn translate_job(&mut self, job: &BitcoinJob) -> Vec<Mining<'static>> {
+        let Some(extranonce1) = self.extranonce1.as_deref() else {
+            return Vec::new();
+        };
+        let merkle_root = job.merkle_root(extranonce1, &self.extranonce2);
+        let prev_hash = job.header_prev_hash();
+
+        let job_id = self.generate_job_id_u32();
+        self.remember_job(
+            job_id,
+            TranslatedJob {
+                sv1_job_id: job.job_id.clone(),
+                version: job.version,
+            },
+        );
+
+        // A future job, activated by SetNewPrevHash, drops earlier jobs downstream
+        let new_block = job.clean_jobs || self.current_prev_hash != Some(prev_hash);
+        let min_ntime = if new_block { None } else { Some(job.ntime) };
+
+        let mut messages = vec![Mining::NewMiningJob(NewMiningJob {
+            channel_id: CHANNEL_ID,
+            job_id,
+            min_ntime: Sv2Option::new(min_ntime),
+            version: job.version,
+            merkle_root: U256::from(merkle_root),
+        })];
+
+        if new_block {
+            self.current_prev_hash = Some(prev_hash);
+            messages.push(Mining::SetNewPrevHash(SetNewPrevHash {
+                channel_id: CHANNEL_ID,
+                job_id,
+                prev_hash: U256::from(prev_hash),
+                min_ntime: job.ntime,
+                nbits: job.nbits,
+            }));
+        }
+
+        debug!("Translated SV1 job {} to SV2 job {}", job.job_id, job_id);
+        messages
     }
 
-    /// Process SV1 message from miner
-    pub fn process_sv1_message(&mut self, msg: &Sv1Message) -> Result<Option<Sv1Message>> {
-        match msg.get_method() {
-            Some(Sv1Method::Login) => Ok(Some(self.handle_sv1_login(msg)?)),
-            Some(Sv1Method::Submit) => Ok(Some(self.handle_sv1_submit(msg)?)),
-            Some(Sv1Method::KeepAlive) => {
-                // Respond to keepalive
-                Ok(Some(Sv1Message::ok_response(
-                    msg.id.clone().unwrap_or(serde_json::Value::Null),
-                    serde_json::json!({"status": "KEEPALIVED"}),
-                )))
-            }
-            Some(Sv1Method::GetJob) => {
-                // Miner requesting new job
-                warn!("GetJob not implemented yet");
-                Ok(None)
-            }
-            Some(Sv1Method::Unknown(method)) => {
-                warn!("Unknown SV1 method: {}", method);
-                Ok(Some(Sv1Message::error_response(
-                    msg.id.clone().unwrap_or(serde_json::Value::Null),
-                    -1,
-                    &format!("Unknown method: {}", method),
-                )))
-            }
-            None => {
-                // Response message, ignore
-                Ok(None)
+    fn remember_job(&mut self, job_id: u32, job: TranslatedJob) {
+        if self.jobs.len() >= MAX_TRANSLATED_JOBS {
+            if let Some(oldest) = self.jobs.keys().min().copied() {
+                self.jobs.remove(&oldest);
             }
         }
+        self.jobs.insert(job_id, job);
     }
 
-    /// Create SV1 job notification from mining data
-    pub fn create_sv1_job(&self, blob: &str, target: &str, height: u64) -> Sv1Message {
-        let job_id = self.generate_job_id();
-        Sv1Message::job(&job_id, blob, target, height)
+    fn generate_job_id_u32(&mut self) -> u32 {
+        self.last_job_id += 1;
+        self.last_job_id
     }
-}
 
-impl Default for StratumTranslator {
-    fn default() -> Self {
-        Self::new()
+    fn next_request_id(&mut self) -> u64 {
+        self.last_request_id += 1;
+        self.last_request_id
     }
 }
 
-use serde_json::Value;
+fn submit_error(submit: &SubmitSharesStandard, code: &str) -> Mining<'static> {
+    Mining::SubmitSharesError(SubmitSharesError {
+        channel_id: submit.channel_id,
+        sequence_number: submit.sequence_number,
+        error_code: str0255(code),
+    })
+}
+
+/// Build an SV2 string, truncated to the 255-byte limit
+fn str0255(s: &str) -> Str0255<'static> {
+    let mut bytes = s.as_bytes().to_vec();
+    bytes.truncate(255);
+    Str0255::try_from(bytes).expect("truncated to 255 bytes")
+}
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
-    fn test_login_handling() {
-        let mut translator = StratumTranslator::new();
-        let login_msg = Sv1Message::login(1, "wallet123", "worker1");
-        
-        let response = translator.handle_sv1_login(&login_msg).unwrap();
-        assert!(response.is_response());
-        assert_eq!(translator.wallet_address(), Some("wallet123"));
-        assert_eq!(translator.worker_name(), Some("worker1"));
+    fn test_refuses_randomx_upstream() {
+        assert!(StratumTranslator::for_algorithm("RandomX").is_none());
+        assert!(StratumTranslator::for_algorithm("Scrypt").is_some());
+    }
+
+    fn upstream(translator: &mut StratumTranslator, json: &str) -> Vec<Mining<'static>> {
+        translator.process_upstream_message(&Sv1Message::from_json(json).unwrap())
     }
 
     #[test]
-    fn test_job_id_mapping() {
-        let translator = StratumTranslator::new();
-        translator.map_job("sv2_job_1".to_string(), "sv1_job_1".to_string());
-        
-        assert_eq!(
-            translator.get_sv2_job_id("sv1_job_1"),
-            Some("sv2_job_1".to_string())
+    fn test_bitcoin_pool_bridge() {
+        let mut translator = StratumTranslator::for_algorithm("SHA256").unwrap();
+        let open = OpenStandardMiningChannel {
+            request_id: 9.into(),
+            user_identity: str0255("ltc1qwallet.rig1"),
+            nominal_hash_rate: 0.0,
+            max_target: U256::from([0xff; 32]),
+        };
+        let requests = translator.open_standard_channel(&open, None);
+        assert_eq!(requests[0].method.as_deref(), Some("mining.subscribe"));
+        assert_eq!(requests[1].param_str(0), Some("ltc1qwallet.rig1"));
+
+        // Jobs and difficulty are held until the pool has authorized the session
+        assert!(upstream(&mut translator, r#"{"id":1,"result":[[["mining.notify","ae"]],"08000002",4],"error":null}"#).is_empty());
+        assert!(upstream(&mut translator, r#"{"id":null,"method":"mining.set_difficulty","params":[2.5]}"#).is_empty());
+        let notify = format!(
+            r#"{{"id":null,"method":"mining.notify","params":["bf","{}","01000000","ffffffff",[],"20000000","1d00ffff","495fab29",true]}}"#,
+            "00".repeat(32)
         );
+        assert!(upstream(&mut translator, &notify).is_empty());
+
+        let messages = upstream(&mut translator, r#"{"id":2,"result":true,"error":null}"#);
+        let Mining::OpenStandardMiningChannelSuccess(success) = &messages[0] else {
+            panic!("expected channel success, got {:?}", messages[0]);
+        };
+        assert_eq!(success.request_id.as_u32(), 9);
+        let Mining::NewMiningJob(job) = &messages[1] else {
+            panic!("expected a job, got {:?}", messages[1]);
+        };
+        assert!(job.min_ntime.clone().into_inner().is_none());
+        assert_eq!(job.version, 0x20000000);
+        assert!(matches!(messages[2], Mining::SetNewPrevHash(_)));
+
+        let submit = SubmitSharesStandard {
+            channel_id: CHANNEL_ID,
+            sequence_number: 4,
+            job_id: job.job_id,
+            nonce: 0x1f2e3d4c,
+            ntime: 0x495fab2a,
+            version: 0x20000000,
+        };
+        let SubmitTranslation::Upstream(msg) = translator.translate_submit(&submit) else {
+            panic!("expected the share to go upstream");
+        };
+        let params = msg.params.clone().unwrap();
+        assert_eq!(params, vec!["ltc1qwallet.rig1", "bf", "00000000", "495fab2a", "1f2e3d4c"]);
+
+        let Some(Mining::SubmitSharesSuccess(accepted)) =
+            upstream(&mut translator, &format!(r#"{{"id":{},"result":true,"error":null}}"#, msg.numeric_id().unwrap())).pop()
+        else {
+            panic!("expected the share to be accepted");
+        };
+        assert_eq!(accepted.last_sequence_number, 4);
+        assert_eq!(accepted.new_shares_sum, 3);
+        let settled = translator.take_settled_shares();
+        assert_eq!(settled.len(), 1);
+        assert_eq!(settled[0].difficulty, 2.5);
+        assert!(settled[0].error_message.is_none());
+        assert_eq!(translator.wallet_address(), Some("ltc1qwallet"));
+        assert_eq!(translator.worker_name(), Some("rig1"));
+
+        // Rolled versions cannot be submitted without negotiating them upstream
+        let rolled = SubmitSharesStandard { version: 0x20002000, ..submit };
+        assert!(matches!(translator.translate_submit(&rolled), SubmitTranslation::Reject(_)));
     }
 }
@@ -0,0 +1,145 @@
+use anyhow::Result;
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tracing::{info, warn};
+
+/// Upstream mining target selected by the server
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Target {
+    #[serde(default)]
+    pub name: String,
+    pub address: String,
+    /// Fallback endpoints, tried in order when `address` is unreachable
+    #[serde(default)]
+    pub backup_addresses: Vec<String>,
+    pub pubkey: Option<String>,
+    #[serde(default = "default_protocol")]
+    pub protocol: String,
+    /// Proof-of-work algorithm, e.g. `"RandomX"` or `"Scrypt"`
+    #[serde(default)]
+    pub algorithm: String,
+}
+
+fn default_protocol() -> String {
+    "sv2".to_string()
+}
+
+impl Target {
+    /// Connect to the first reachable endpoint, trying `avoid` (a failed one) last.
+    /// Each endpoint gets `timeout` to accept the connection.
+    pub async fn connect(&self, avoid: Option<&str>, timeout: Duration) -> Result<(TcpStream, String)> {
+        let mut endpoints: Vec<&String> = std::iter::once(&self.address).chain(&self.backup_addresses).collect();
+        if let Some(avoid) = avoid {
+            endpoints.sort_by_key(|endpoint| endpoint.as_str() == avoid);
+        }
+
+        let mut last_error = None;
+        for endpoint in endpoints {
+            let error = match tokio::time::timeout(timeout, TcpStream::connect(endpoint)).await {
+                Ok(Ok(socket)) => {
+                    info!("Connected to upstream {} ({})", endpoint, self.name);
+                    return Ok((socket, endpoint.clone()));
+                }
+                Ok(Err(e)) => e.to_string(),
+                Err(_) => "connect timed out".to_string(),
+            };
+            warn!("Upstream endpoint {} unreachable: {}", endpoint, error);
+            last_error = Some(format!("{}: {}", endpoint, error));
+        }
+
+        anyhow::bail!(
+            "No endpoint of target {} is reachable: {}",
+            self.name,
+            last_error.expect("target has at least one endpoint")
+        )
+    }
+}
+
+/// Fetch the current target from the server
+pub async fn fetch_target(server_url: &str) -> Result<Target> {
+    let url = format!("{}/api/v1/target", server_url);
+    let target = reqwest::get(&url)
+        .await?
+        .json::<Target>()
+        .await?;
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::{TcpListener, TcpSocket};
+
+    fn target(address: String, backup_addresses: Vec<String>) -> Target {
+        Target {
+            name: "test".to_string(),
+            address,
+            backup_addresses,
+            pubkey: None,
+            protocol: "sv1".to_string(),
+            algorithm: String::new(),
+        }
+    }
+
+    /// Address nothing listens on
+    async fn unreachable() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_skips_unreachable_endpoints() {
+        let live = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let live_address = live.local_addr().unwrap().to_string();
+        let target = target(unreachable().await, vec![unreachable().await, live_address.clone()]);
+
+        let (_, endpoint) = target.connect(None, Duration::from_secs(1)).await.unwrap();
+        assert_eq!(endpoint, live_address);
+    }
+
+    #[tokio::test]
+    async fn test_tries_avoided_endpoint_last() {
+        let primary = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backup = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let primary_address = primary.local_addr().unwrap().to_string();
+        let backup_address = backup.local_addr().unwrap().to_string();
+        let target = target(primary_address.clone(), vec![backup_address.clone()]);
+
+        let (_, endpoint) = target.connect(Some(&primary_address), Duration::from_secs(1)).await.unwrap();
+        assert_eq!(endpoint, backup_address);
+
+        // Still used when nothing else is up
+        drop(backup);
+        let (_, endpoint) = target.connect(Some(&primary_address), Duration::from_secs(1)).await.unwrap();
+        assert_eq!(endpoint, primary_address);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_on_endpoints_that_never_accept() {
+        // A listener whose accept queue is full drops further connection attempts
+        let socket = TcpSocket::new_v4().unwrap();
+        socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let hung = socket.listen(0).unwrap();
+        let hung_address = hung.local_addr().unwrap().to_string();
+        let mut queued = Vec::new();
+        while let Ok(Ok(stream)) =
+            tokio::time::timeout(Duration::from_millis(200), TcpStream::connect(&hung_address)).await
+        {
+            queued.push(stream);
+        }
+
+        let live = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let live_address = live.local_addr().unwrap().to_string();
+        let started = tokio::time::Instant::now();
+        let (_, endpoint) = target(hung_address.clone(), vec![live_address.clone()])
+            .connect(None, Duration::from_millis(300))
+            .await
+            .unwrap();
+        assert_eq!(endpoint, live_address);
+        assert!(started.elapsed() >= Duration::from_millis(300));
+
+        let error = target(hung_address, Vec::new()).connect(None, Duration::from_millis(300)).await.unwrap_err();
+        assert!(error.to_string().contains("connect timed out"), "{}", error);
+    }
+}
@@ -0,0 +1,937 @@
+use crate::config::Config;
+use crate::control::ProxyState;
+use crate::job_tracker::JobTracker;
+use crate::share_recorder::{ShareRecorder, ShareStatus, ShareSubmission};
+use crate::share_validator::{BitcoinJob, PowAlgorithm};
+use crate::stratum::{Sv1Message, Sv1Method};
+use crate::target::Target;
+use crate::vardiff::Vardiff;
+use crate::wallet_check::WalletChecker;
+use anyhow::Result;
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::TcpStream;
+use tokio::sync::watch;
+use tracing::{debug, info, warn};
+
+/// Request id of logins the proxy replays on behalf of the miner
+const SWITCH_LOGIN_ID: &str = "defpool-switch";
+
+/// Request id of `mining.subscribe` replayed on a switch
+const SWITCH_SUBSCRIBE_ID: &str = "defpool-switch-subscribe";
+
+/// Request id of `mining.extranonce.subscribe` replayed on a switch
+const SWITCH_EXTRANONCE_ID: &str = "defpool-switch-extranonce";
+
+/// Job ids remembered per upstream for stale-share detection
+const MAX_TRACKED_JOBS: usize = 64;
+
+/// First wait after every endpoint failed, doubled up to `MAX_RECONNECT_DELAY`
+const INITIAL_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// Connection to a single SV1 upstream pool
+struct Upstream {
+    target: Target,
+    /// Endpoint of the target we are connected to
+    endpoint: String,
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+    /// Session id assigned by the pool on login
+    session_id: Option<String>,
+    /// Jobs issued by this pool, for difficulty lookup and stale detection
+    jobs: JobTracker,
+    /// Last `mining.set_difficulty`, applied to jobs notified after it
+    difficulty: f64,
+    /// Extranonce1 from the `mining.subscribe` result
+    extranonce1: Option<Vec<u8>>,
+    /// Recent `mining.notify` jobs, for share hashing
+    bitcoin_jobs: VecDeque<BitcoinJob>,
+    /// When the pool last sent a job (or we connected)
+    last_job: tokio::time::Instant,
+}
+
+impl Upstream {
+    async fn connect(target: Target, avoid: Option<&str>, timeout: Duration) -> Result<Self> {
+        info!("Connecting to upstream (SV1): {}", target.address);
+        let (socket, endpoint) = target.connect(avoid, timeout).await?;
+
+        let (reader, writer) = socket.into_split();
+        Ok(Self {
+            target,
+            endpoint,
+            reader: BufReader::new(reader),
+            writer,
+            session_id: None,
+            jobs: JobTracker::new(MAX_TRACKED_JOBS),
+            difficulty: 1.0,
+            extranonce1: None,
+            bitcoin_jobs: VecDeque::new(),
+            last_job: tokio::time::Instant::now(),
+        })
+    }
+
+    async fn send(&mut self, msg: &Sv1Message) -> Result<()> {
+        write_line(&mut self.writer, &msg.to_json()?).await
+    }
+
+    fn track_bitcoin_job(&mut self, job: BitcoinJob) {
+        if self.bitcoin_jobs.len() >= MAX_TRACKED_JOBS {
+            self.bitcoin_jobs.pop_front();
+        }
+        self.bitcoin_jobs.push_back(job);
+    }
+
+    fn bitcoin_job(&self, job_id: &str) -> Option<&BitcoinJob> {
+        self.bitcoin_jobs.iter().rev().find(|job| job.job_id == job_id)
+    }
+}
+
+/// A Stratum V1 miner session that follows the server's target.
+///
+/// Both the Monero (`login`/`job`/`submit`) and Bitcoin (`mining.*`) dialects
+/// are accounted. When the target changes, a Monero session logs in to the new
+/// upstream with the miner's original credentials and pushes the new pool's
+/// job to the miner, keeping the downstream socket open. A Bitcoin session
+/// replays its subscribe and authorize, then hands the miner the new pool's
+/// extranonce with `mining.set_extranonce` ahead of its `mining.notify`; a
+/// miner that never sent `mining.extranonce.subscribe` cannot follow and is
+/// closed instead so it reconnects.
+///
+/// If the pool drops the connection or stops sending jobs, the session fails
+/// over to the target's next endpoint the same way. While no endpoint is
+/// reachable the miner stays connected and the target is retried with backoff.
+///
+/// With vardiff enabled a Bitcoin-style miner works at its own difficulty; the
+/// proxy hashes its shares, credits those below the pool's difficulty locally
+/// and forwards the rest. Monero-style shares cannot be hashed here (RandomX),
+/// so they all go to the pool, which alone decides what gets credited.
+pub struct V1Session {
+    share_recorder: Arc<ShareRecorder>,
+    wallet_checker: Arc<WalletChecker>,
+    miner_writer: OwnedWriteHalf,
+    upstream: Upstream,
+    wallet_address: Option<String>,
+    worker_name: String,
+    /// Miner's `login` or `mining.authorize`, replayed on every switch
+    login: Option<Sv1Message>,
+    /// Miner's `mining.subscribe`, replayed on every switch
+    subscribe: Option<Sv1Message>,
+    /// Miner's `mining.extranonce.subscribe`, if it can take a new extranonce
+    extranonce_subscribe: Option<Sv1Message>,
+    /// The next `mining.notify` must make the miner drop the old pool's work
+    clean_next_job: bool,
+    /// Session id the miner got from its first login
+    miner_session_id: Option<String>,
+    /// Jobs of the upstream we switched away from
+    stale_jobs: JobTracker,
+    /// A replayed login is waiting for the new pool's answer
+    switching: bool,
+    /// Submits awaiting the pool's verdict, keyed by JSON-RPC id
+    pending_submits: HashMap<String, PendingShare>,
+    /// Miner speaks the Bitcoin (`mining.*`) dialect
+    bitcoin_dialect: bool,
+    /// Proxy-side difficulty, `None` when disabled
+    vardiff: Option<Vardiff>,
+    /// Difficulty handed to the miner per job
+    assigned_jobs: JobTracker,
+    /// Last `mining.set_difficulty` sent to the miner
+    miner_difficulty: Option<f64>,
+    /// Fail over when the pool sends no job for this long
+    idle_timeout: Duration,
+    /// Longest wait for an upstream endpoint to accept a connection
+    connect_timeout: Duration,
+}
+
+/// Share forwarded upstream whose result is not known yet
+struct PendingShare {
+    target_name: String,
+    difficulty: f64,
+}
+
+impl V1Session {
+    /// Run a V1 session until either side disconnects
+    pub async fn run(
+        downstream_socket: TcpStream,
+        config: Arc<Config>,
+        target: Target,
+        mut control: watch::Receiver<ProxyState>,
+        share_recorder: Arc<ShareRecorder>,
+        wallet_checker: Arc<WalletChecker>,
+    ) -> Result<()> {
+        let connect_timeout = Duration::from_secs(config.upstream_connect_timeout_secs);
+        let upstream = Upstream::connect(target, None, connect_timeout).await?;
+        let (miner_read, miner_writer) = downstream_socket.into_split();
+        let mut miner_reader = BufReader::new(miner_read);
+
+        let mut session = Self {
+            share_recorder,
+            wallet_checker,
+            miner_writer,
+            upstream,
+            wallet_address: control.borrow().default_wallet.clone(),
+            worker_name: String::from("worker1"),
+            login: None,
+            subscribe: None,
+            extranonce_subscribe: None,
+            clean_next_job: false,
+            miner_session_id: None,
+            stale_jobs: JobTracker::new(MAX_TRACKED_JOBS),
+            switching: false,
+            pending_submits: HashMap::new(),
+            bitcoin_dialect: false,
+            vardiff: config.vardiff.enabled.then(|| Vardiff::new(config.vardiff.clone(), Instant::now())),
+            assigned_jobs: JobTracker::new(MAX_TRACKED_JOBS),
+            miner_difficulty: None,
+            idle_timeout: Duration::from_secs(config.upstream_idle_timeout_secs),
+            connect_timeout,
+        };
+
+        control.mark_unchanged();
+        let mut watching = true;
+        let mut miner_line = String::new();
+        let mut upstream_line = String::new();
+
+        loop {
+            tokio::select! {
+                read = miner_reader.read_line(&mut miner_line) => {
+                    if read? == 0 {
+                        return Ok(());
+                    }
+                    session.handle_miner_line(&miner_line).await?;
+                    miner_line.clear();
+                }
+                read = session.upstream.reader.read_line(&mut upstream_line) => {
+                    let miner_left = match read {
+                        Ok(0) => !session.fail_over(&mut miner_reader, &control, "closed the connection").await?,
+                        Ok(_) => {
+                            session.handle_upstream_line(&upstream_line).await?;
+                            false
+                        }
+                        Err(e) => !session.fail_over(&mut miner_reader, &control, &format!("failed: {}", e)).await?,
+                    };
+                    if miner_left {
+                        return Ok(());
+                    }
+                    upstream_line.clear();
+                }
+                _ = tokio::time::sleep_until(session.upstream.last_job + session.idle_timeout) => {
+                    if !session.fail_over(&mut miner_reader, &control, "sent no job in time").await? {
+                        return Ok(());
+                    }
+                }
+                changed = control.changed(), if watching => {
+                    if changed.is_err() {
+                        warn!("Server control channel stopped; session stays on {}", session.upstream.target.address);
+                        watching = false;
+                        continue;
+                    }
+                    let target = control.borrow_and_update().target.clone();
+                    if let Some(target) = target {
+                        if target.address != session.upstream.target.address {
+                            if !session.can_switch() {
+                                info!("Target switched to {}, disconnecting Bitcoin-style miner to reconnect", target.address);
+                                return Ok(());
+                            }
+                            session.switch_upstream(target).await?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    async fn handle_miner_line(&mut self, line: &str) -> Result<()> {
+        let Ok(mut msg) = Sv1Message::from_json(line) else {
+            return write_line(&mut self.upstream.writer, line).await;
+        };
+
+        let method = msg.get_method();
+        if method.as_ref().is_some_and(Sv1Method::is_bitcoin_dialect) {
+            self.bitcoin_dialect = true;
+        }
+
+        match method {
+            Some(Sv1Method::Login) => {
+                info!("V1 Miner → Pool: login");
+                if self.refuse_invalid_wallet(&msg).await? {
+                    return Ok(());
+                }
+                self.extract_identity(&msg);
+                self.login = Some(msg.clone());
+            }
+            Some(Sv1Method::Subscribe) => {
+                info!("V1 Miner → Pool: mining.subscribe");
+                self.subscribe = Some(msg.clone());
+            }
+            Some(Sv1Method::ExtranonceSubscribe) => {
+                info!("V1 Miner → Pool: mining.extranonce.subscribe");
+                self.extranonce_subscribe = Some(msg.clone());
+            }
+            Some(Sv1Method::Authorize) => {
+                info!("V1 Miner → Pool: mining.authorize");
+                if self.refuse_invalid_wallet(&msg).await? {
+                    return Ok(());
+                }
+                self.extract_identity(&msg);
+                self.login = Some(msg.clone());
+            }
+            Some(Sv1Method::Submit | Sv1Method::MiningSubmit) => {
+                let job_id = msg.submit_job_id().unwrap_or_default().to_string();
+                if self.switching || self.is_stale_job(&job_id) {
+                    info!("Rejecting stale share for job {} after pool switch", job_id);
+                    let message = "Stale share: pool switched";
+                    let share = PendingShare {
+                        target_name: self.upstream.target.name.clone(),
+                        difficulty: self.stale_jobs.get_difficulty(&job_id),
+                    };
+                    self.record_share(share, ShareStatus::Stale, Some(message.to_string()));
+                    let reply = Sv1Message::error_response(msg.id.clone().unwrap_or(Value::Null), -1, message);
+                    return write_line(&mut self.miner_writer, &reply.to_json()?).await;
+                }
+
+                let upstream_difficulty = self.upstream.jobs.get_difficulty(&job_id);
+                let share = PendingShare {
+                    target_name: self.upstream.target.name.clone(),
+                    difficulty: self
+                        .assigned_jobs
+                        .get_job(&job_id)
+                        .map_or(upstream_difficulty, |job| job.difficulty),
+                };
+                if let Some(share_difficulty) = self.share_difficulty(&msg, &job_id) {
+                    if share_difficulty < share.difficulty {
+                        let message = "Low difficulty share";
+                        self.record_share(share, ShareStatus::Rejected, Some(message.to_string()));
+                        let reply = Sv1Message::error_response(msg.id.clone().unwrap_or(Value::Null), -1, message);
+                        return write_line(&mut self.miner_writer, &reply.to_json()?).await;
+                    }
+                    if let Some(vardiff) = &mut self.vardiff {
+                        vardiff.record_share(Instant::now());
+                    }
+                    if share_difficulty < upstream_difficulty {
+                        // Meets our difficulty but not the pool's: credit it without forwarding
+                        debug!("Accepting share for job {} locally", job_id);
+                        self.record_share(share, ShareStatus::Accepted, None);
+                        let result = if self.bitcoin_dialect {
+                            Value::Bool(true)
+                        } else {
+                            serde_json::json!({"status": "OK"})
+                        };
+                        let reply = Sv1Message::ok_response(msg.id.clone().unwrap_or(Value::Null), result);
+                        return write_line(&mut self.miner_writer, &reply.to_json()?).await;
+                    }
+                }
+                match &msg.id {
+                    Some(id) => {
+                        self.pending_submits.insert(id.to_string(), share);
+                    }
+                    None => warn!("Submit without id from {}, its result cannot be tracked", self.worker_name),
+                }
+            }
+            Some(method) => info!("V1 Miner → Pool: {}", method.as_str()),
+            None => {}
+        }
+
+        if self.rewrite_session_id(&mut msg) {
+            self.upstream.send(&msg).await
+        } else {
+            write_line(&mut self.upstream.writer, line).await
+        }
+    }
+
+    async fn handle_upstream_line(&mut self, line: &str) -> Result<()> {
+        let Ok(mut msg) = Sv1Message::from_json(line) else {
+            return write_line(&mut self.miner_writer, line).await;
+        };
+
+        match msg.id.as_ref().and_then(|id| id.as_str()) {
+            Some(SWITCH_LOGIN_ID) => return self.finish_switch(&msg).await,
+            Some(SWITCH_SUBSCRIBE_ID) => return self.push_extranonce(&msg).await,
+            Some(SWITCH_EXTRANONCE_ID) => return Ok(()),
+            _ => {}
+        }
+
+        let mut rewritten = false;
+        if let Some(method) = msg.get_method() {
+            info!("V1 Pool → Miner: {}", method.as_str());
+            match method {
+                Sv1Method::Job => {
+                    if let Some(job) = msg.param_object() {
+                        self.track_job(job);
+                    }
+                }
+                Sv1Method::SetDifficulty => {
+                    if let Some(difficulty) = msg.set_difficulty() {
+                        self.upstream.difficulty = difficulty;
+                    }
+                    if self.bitcoin_vardiff().is_some() {
+                        // The miner gets our difficulty with the next job instead
+                        return Ok(());
+                    }
+                }
+                Sv1Method::SetExtranonce => {
+                    self.upstream.extranonce1 = msg.param_str(0).and_then(|extranonce1| hex::decode(extranonce1).ok());
+                }
+                Sv1Method::Notify => {
+                    // [job_id, prevhash, coinb1, coinb2, merkle_branch, version, nbits, ntime, clean_jobs]
+                    if let Some(job_id) = msg.param_str(0) {
+                        self.upstream.jobs.add_job_with_difficulty(job_id.to_string(), self.upstream.difficulty);
+                        self.upstream.last_job = tokio::time::Instant::now();
+                    }
+                    if let Some(job) = BitcoinJob::from_notify(&msg) {
+                        self.upstream.track_bitcoin_job(job);
+                    }
+                    if std::mem::take(&mut self.clean_next_job) {
+                        if let Some(clean_jobs) = msg.params.as_mut().and_then(|params| params.get_mut(8)) {
+                            *clean_jobs = Value::Bool(true);
+                            rewritten = true;
+                        }
+                    }
+                    self.assign_bitcoin_job(&msg).await?;
+                }
+                _ => {}
+            }
+        } else if msg.is_response() {
+            let subscribe_id = self.subscribe.as_ref().and_then(|subscribe| subscribe.id.as_ref());
+            if subscribe_id.is_some() && msg.id.as_ref() == subscribe_id {
+                self.upstream.extranonce1 = msg.subscribe_extranonce().map(|(extranonce1, _)| extranonce1);
+            }
+
+            let pending = msg.id.as_ref().and_then(|id| self.pending_submits.remove(&id.to_string()));
+            if let Some(pending) = pending {
+                let (status, error) = if msg.is_accepted() {
+                    (ShareStatus::Accepted, None)
+                } else {
+                    let error = msg.error_message().unwrap_or_else(|| "Rejected by pool".to_string());
+                    warn!("Share rejected: {}", error);
+                    (ShareStatus::from_pool_error(&error), Some(error))
+                };
+                self.record_share(pending, status, error);
+            } else if let Some(error) = msg.error_message() {
+                warn!("Pool error: {}", error);
+            }
+            if self.is_login_response(&msg) {
+                self.track_login_result(&msg);
+                if let Some(job) = msg.result_object().and_then(|result| result.get("job")?.as_object()) {
+                    self.track_job(job);
+                }
+            }
+        }
+
+        if rewritten {
+            write_line(&mut self.miner_writer, &msg.to_json()?).await
+        } else {
+            write_line(&mut self.miner_writer, line).await
+        }
+    }
+
+    /// Track a Monero-style job; its target stays the pool's
+    fn track_job(&mut self, job: &serde_json::Map<String, Value>) {
+        if self.upstream.jobs.add_sv1_job(job).is_some() {
+            self.upstream.last_job = tokio::time::Instant::now();
+        }
+    }
+
+    /// Send the miner its vardiff difficulty ahead of a `mining.notify`
+    async fn assign_bitcoin_job(&mut self, notify: &Sv1Message) -> Result<()> {
+        let Some(job_id) = notify.param_str(0) else {
+            return Ok(());
+        };
+        let upstream_difficulty = self.upstream.difficulty;
+        let Some(vardiff) = self.bitcoin_vardiff() else {
+            return Ok(());
+        };
+
+        vardiff.retarget(Instant::now());
+        let difficulty = vardiff.assign(upstream_difficulty);
+        self.assigned_jobs.add_job_with_difficulty(job_id.to_string(), difficulty);
+
+        if self.miner_difficulty != Some(difficulty) {
+            self.miner_difficulty = Some(difficulty);
+            let message = Sv1Message::difficulty_notification(difficulty);
+            write_line(&mut self.miner_writer, &message.to_json()?).await?;
+        }
+        Ok(())
+    }
+
+    /// Whether a job belongs to the upstream we switched away from. Pools
+    /// number their jobs independently, so an id the current upstream issued
+    /// as well is the current upstream's job.
+    fn is_stale_job(&self, job_id: &str) -> bool {
+        self.upstream.jobs.get_job(job_id).is_none() && self.stale_jobs.get_job(job_id).is_some()
+    }
+
+    /// Vardiff for a Bitcoin-style session, if its shares can be hashed
+    fn bitcoin_vardiff(&mut self) -> Option<&mut Vardiff> {
+        PowAlgorithm::from_name(&self.upstream.target.algorithm)?;
+        self.upstream.extranonce1.as_ref()?;
+        self.vardiff.as_mut()
+    }
+
+    /// Actual difficulty of a submitted share, when vardiff can check it
+    fn share_difficulty(&self, submit: &Sv1Message, job_id: &str) -> Option<f64> {
+        self.vardiff.as_ref()?;
+        if !self.bitcoin_dialect {
+            return None;
+        }
+
+        let algorithm = PowAlgorithm::from_name(&self.upstream.target.algorithm)?;
+        let extranonce1 = self.upstream.extranonce1.as_ref()?;
+        self.upstream.bitcoin_job(job_id)?.share_difficulty(algorithm, extranonce1, submit)
+    }
+
+    /// Move the session to a new upstream without dropping the miner
+    async fn switch_upstream(&mut self, target: Target) -> Result<()> {
+        info!(
+            "Switching miner session from {} to {} ({})",
+            self.upstream.target.address, target.name, target.address
+        );
+
+        match Upstream::connect(target, None, self.connect_timeout).await {
+            Ok(upstream) => self.replace_upstream(upstream).await,
+            Err(e) => {
+                warn!("Switch failed, staying on {}: {}", self.upstream.target.address, e);
+                Ok(())
+            }
+        }
+    }
+
+    /// Whether the miner can be moved to another upstream without reconnecting
+    fn can_switch(&self) -> bool {
+        !self.bitcoin_dialect || self.extranonce_subscribe.is_some()
+    }
+
+    /// Move to another endpoint after the upstream failed, trying the failed
+    /// one last. Until an endpoint of the latest target accepts, the miner is
+    /// held with backoff between attempts; returns false if it left meanwhile.
+    async fn fail_over(
+        &mut self,
+        miner_reader: &mut BufReader<OwnedReadHalf>,
+        control: &watch::Receiver<ProxyState>,
+        reason: &str,
+    ) -> Result<bool> {
+        if !self.can_switch() || self.login.is_none() {
+            // Nothing to replay: the miner reconnects and the proxy picks a live endpoint
+            anyhow::bail!("Upstream {} {}", self.upstream.endpoint, reason);
+        }
+
+        warn!("Upstream {} {}, failing over", self.upstream.endpoint, reason);
+        let failed = self.upstream.endpoint.clone();
+        let mut delay = INITIAL_RECONNECT_DELAY;
+        loop {
+            // The server may have moved on to another target meanwhile
+            let target = control.borrow().target.clone().unwrap_or_else(|| self.upstream.target.clone());
+            match Upstream::connect(target, Some(&failed), self.connect_timeout).await {
+                Ok(upstream) => {
+                    self.replace_upstream(upstream).await?;
+                    return Ok(true);
+                }
+                Err(e) => warn!("{}, holding the miner and retrying in {:?}", e, delay),
+            }
+
+            if !wait_for_miner(miner_reader, delay).await? {
+                info!("Miner {} left while no upstream was reachable", self.worker_name);
+                return Ok(false);
+            }
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+    }
+
+    /// Log in to a new upstream on the miner's behalf; old jobs become stale
+    async fn replace_upstream(&mut self, mut upstream: Upstream) -> Result<()> {
+        if self.bitcoin_dialect {
+            // The new pool assigns its own extranonce, which the miner gets before any job
+            for (request, id) in [(&self.subscribe, SWITCH_SUBSCRIBE_ID), (&self.extranonce_subscribe, SWITCH_EXTRANONCE_ID)] {
+                if let Some(request) = request {
+                    upstream.send(&replay(request, id)).await?;
+                }
+            }
+            self.clean_next_job = true;
+        }
+        if let Some(login) = &self.login {
+            upstream.send(&replay(login, SWITCH_LOGIN_ID)).await?;
+            self.switching = true;
+        }
+
+        let previous = std::mem::replace(&mut self.upstream, upstream);
+        self.stale_jobs = previous.jobs;
+
+        // The old pool will never answer these
+        for (_, pending) in std::mem::take(&mut self.pending_submits) {
+            self.record_share(pending, ShareStatus::Stale, Some("Pool switched before answering".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Send the miner the extranonce the new pool assigned to a replayed subscribe
+    async fn push_extranonce(&mut self, msg: &Sv1Message) -> Result<()> {
+        let Some((extranonce1, extranonce2_size)) = msg.subscribe_extranonce() else {
+            let error = msg.error_message().unwrap_or_else(|| "no extranonce in the answer".to_string());
+            anyhow::bail!("Subscribe to {} failed after switch: {}", self.upstream.target.address, error);
+        };
+
+        let notification = Sv1Message::extranonce_notification(&extranonce1, extranonce2_size);
+        self.upstream.extranonce1 = Some(extranonce1);
+        write_line(&mut self.miner_writer, &notification.to_json()?).await
+    }
+
+    /// Handle the new pool's answer to a replayed login
+    async fn finish_switch(&mut self, msg: &Sv1Message) -> Result<()> {
+        if !msg.is_accepted() {
+            let error = msg.error_message().unwrap_or_else(|| "unauthorized".to_string());
+            anyhow::bail!("Login to {} failed after switch: {}", self.upstream.target.address, error);
+        }
+
+        self.track_login_result(msg);
+        self.switching = false;
+
+        if self.bitcoin_dialect {
+            info!("Switched to {}, the pool's jobs go to the miner", self.upstream.target.address);
+            return Ok(());
+        }
+
+        let job = msg.result_object().and_then(|result| result.get("job")?.as_object()).cloned();
+        match job {
+            Some(job) => {
+                self.track_job(&job);
+                let notification = Sv1Message {
+                    id: None,
+                    method: Some("job".to_string()),
+                    params: Some(vec![Value::Object(job)]),
+                    result: None,
+                    error: None,
+                };
+                info!("Switched to {}, pushing new job to miner", self.upstream.target.address);
+                write_line(&mut self.miner_writer, &notification.to_json()?).await
+            }
+            None => {
+                warn!("Login to {} returned no job", self.upstream.target.address);
+                Ok(())
+            }
+        }
+    }
+
+    fn is_login_response(&self, msg: &Sv1Message) -> bool {
+        let login_id = self.login.as_ref().and_then(|login| login.id.as_ref());
+        login_id.is_some() && msg.id.as_ref() == login_id
+    }
+
+    /// Remember the session id and first job from a login result
+    fn track_login_result(&mut self, msg: &Sv1Message) {
+        let Some(result) = msg.result_object() else {
+            return;
+        };
+
+        if let Some(session_id) = result.get("id").and_then(|v| v.as_str()) {
+            self.upstream.session_id = Some(session_id.to_string());
+            if self.miner_session_id.is_none() {
+                self.miner_session_id = Some(session_id.to_string());
+            }
+        }
+
+    }
+
+    /// Replace the miner's session id with the current upstream's one
+    fn rewrite_session_id(&self, msg: &mut Sv1Message) -> bool {
+        let (Some(miner_id), Some(upstream_id)) = (&self.miner_session_id, &self.upstream.session_id) else {
+            return false;
+        };
+        if miner_id == upstream_id {
+            return false;
+        }
+
+        match msg.params.as_mut().and_then(|params| params.first_mut()) {
+            Some(Value::Object(params)) if params.get("id").and_then(|v| v.as_str()) == Some(miner_id) => {
+                params.insert("id".to_string(), Value::String(upstream_id.clone()));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Extract wallet/worker from a login or authorize
+    /// (`"wallet:worker"`, `"wallet.worker"` or `{"login": ..}`)
+    fn extract_identity(&mut self, msg: &Sv1Message) {
+        let Some(login) = login_param(msg) else {
+            return;
+        };
+
+        let (wallet, worker) = split_login(login);
+        if !wallet.is_empty() {
+            self.wallet_address = Some(wallet.to_string());
+            self.worker_name = worker.unwrap_or("worker1").to_string();
+            info!("Extracted wallet: {}, worker: {}", wallet, self.worker_name);
+        }
+    }
+
+    /// Answer a login whose wallet the server refuses with an error instead of
+    /// forwarding it; returns whether it was refused
+    async fn refuse_invalid_wallet(&mut self, msg: &Sv1Message) -> Result<bool> {
+        let wallet = login_param(msg).map(|login| split_login(login).0).unwrap_or_default();
+        if wallet.is_empty() {
+            return Ok(false);
+        }
+        let Some(reason) = self.wallet_checker.refusal(wallet).await else {
+            return Ok(false);
+        };
+
+        warn!("Refusing login of invalid wallet {}: {}", wallet, reason);
+        let message = format!("Invalid wallet address: {}", reason);
+        let reply = Sv1Message::error_response(msg.id.clone().unwrap_or(Value::Null), -1, &message);
+        write_line(&mut self.miner_writer, &reply.to_json()?).await?;
+        Ok(true)
+    }
+
+    fn record_share(&self, share: PendingShare, status: ShareStatus, error_message: Option<String>) {
+        let wallet = self.wallet_address.as_deref().unwrap_or("unknown");
+        info!("Share {:?} for {}/{}", status, wallet, self.worker_name);
+
+        // Spool the share asynchronously (don't block on result)
+        let recorder = self.share_recorder.clone();
+        let submission = ShareSubmission {
+            share_id: ShareSubmission::new_id(),
+            wallet_address: wallet.to_string(),
+            worker_name: self.worker_name.clone(),
+            target_name: share.target_name,
+            difficulty: share.difficulty,
+            valid: status == ShareStatus::Accepted,
+            status,
+            error_message,
+        };
+
+        tokio::spawn(async move {
+            if let Err(e) = recorder.record_share(submission).await {
+                warn!("Failed to record share: {}", e);
+            }
+        });
+    }
+}
+
+/// Copy of a miner request sent upstream under a proxy-owned id
+fn replay(request: &Sv1Message, id: &str) -> Sv1Message {
+    let mut request = request.clone();
+    request.id = Some(Value::String(id.to_string()));
+    request
+}
+
+/// Login string of a `login` or `mining.authorize` request
+fn login_param(msg: &Sv1Message) -> Option<&str> {
+    match msg.params.as_ref()?.first()? {
+        Value::String(login) => Some(login.as_str()),
+        Value::Object(params) => Some(params.get("login").and_then(|v| v.as_str()).unwrap_or_default()),
+        _ => None,
+    }
+}
+
+/// Sleep for `delay`, returning false early if the miner disconnects. What
+/// the miner sends meanwhile stays buffered for the next upstream.
+async fn wait_for_miner(miner_reader: &mut BufReader<OwnedReadHalf>, delay: Duration) -> Result<bool> {
+    let sleep = tokio::time::sleep(delay);
+    tokio::pin!(sleep);
+    tokio::select! {
+        _ = &mut sleep => return Ok(true),
+        buffered = miner_reader.fill_buf() => {
+            if buffered?.is_empty() {
+                return Ok(false);
+            }
+        }
+    }
+    sleep.await;
+    Ok(true)
+}
+
+/// Split a login into wallet and worker at the first `:` or `.`
+fn split_login(login: &str) -> (&str, Option<&str>) {
+    match login.split_once([':', '.']) {
+        Some((wallet, worker)) => (wallet, Some(worker)),
+        None => (login, None),
+    }
+}
+
+async fn write_line(writer: &mut OwnedWriteHalf, line: &str) -> Result<()> {
+    writer.write_all(line.trim_end().as_bytes()).await?;
+    writer.write_all(b"\n").await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server_auth::ServerAuth;
+    use tokio::net::TcpListener;
+
+    /// Line-based end of a test connection, miner or pool side
+    struct Peer {
+        reader: BufReader<OwnedReadHalf>,
+        writer: OwnedWriteHalf,
+    }
+
+    impl Peer {
+        fn new(socket: TcpStream) -> Self {
+            let (reader, writer) = socket.into_split();
+            Self { reader: BufReader::new(reader), writer }
+        }
+
+        async fn accept(listener: &TcpListener) -> Self {
+            let (socket, _) = tokio::time::timeout(Duration::from_secs(5), listener.accept()).await.unwrap().unwrap();
+            Self::new(socket)
+        }
+
+        async fn send(&mut self, line: &str) {
+            write_line(&mut self.writer, line).await.unwrap();
+        }
+
+        async fn recv(&mut self) -> Value {
+            let mut line = String::new();
+            tokio::time::timeout(Duration::from_secs(5), self.reader.read_line(&mut line)).await.unwrap().unwrap();
+            serde_json::from_str(&line).unwrap()
+        }
+    }
+
+    fn target(name: &str, address: String, backup_addresses: Vec<String>) -> Target {
+        Target {
+            name: name.to_string(),
+            address,
+            backup_addresses,
+            pubkey: None,
+            protocol: "sv1".to_string(),
+            algorithm: "RandomX".to_string(),
+        }
+    }
+
+    /// Start a session for `target` with extra config lines, returning the
+    /// miner's end and the control channel
+    async fn start_session(name: &str, target: Target, extra_config: &str) -> (Peer, watch::Sender<ProxyState>) {
+        let spool_dir = std::env::temp_dir().join(format!("defpool-session-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&spool_dir);
+        // Nothing listens on the server endpoint: wallets go through, shares stay spooled
+        let config: Config = toml::from_str(&format!(
+            "server_endpoint = \"http://127.0.0.1:1\"\nlisten_address = \"127.0.0.1:0\"\nshare_spool_dir = {:?}\nupstream_connect_timeout_secs = 1\n{}",
+            spool_dir.display().to_string(),
+            extra_config
+        ))
+        .unwrap();
+        let config = Arc::new(config);
+        let share_recorder = ShareRecorder::start(&config, ServerAuth::new(&config.server_auth)).unwrap();
+        let wallet_checker = Arc::new(WalletChecker::new(&config, ServerAuth::new(&config.server_auth)));
+        let (control, state) = watch::channel(ProxyState { target: Some(target.clone()), ..Default::default() });
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let miner = TcpStream::connect(listener.local_addr().unwrap()).await.unwrap();
+        let (downstream, _) = listener.accept().await.unwrap();
+        tokio::spawn(V1Session::run(downstream, config, target, state, share_recorder, wallet_checker));
+        (Peer::new(miner), control)
+    }
+
+    /// Answer a Monero-style login with a session id and a job
+    async fn accept_login(pool: &mut Peer, session_id: &str, job_id: &str) {
+        let login = pool.recv().await;
+        assert_eq!(login["method"], "login");
+        let response = serde_json::json!({
+            "id": login["id"],
+            "jsonrpc": "2.0",
+            "result": {"id": session_id, "job": {"job_id": job_id, "blob": "00", "target": "b88d0600"}, "status": "OK"},
+        });
+        pool.send(&response.to_string()).await;
+    }
+
+    /// Log the miner in to `pool` and hand it the first job
+    async fn log_in(miner: &mut Peer, pool: &mut Peer) {
+        miner.send(r#"{"id":1,"method":"login","params":{"login":"4Awallet","pass":"x"}}"#).await;
+        accept_login(pool, "session-1", "1").await;
+        assert_eq!(miner.recv().await["result"]["job"]["job_id"], "1");
+    }
+
+    fn submit(id: u64, session_id: &str, job_id: &str) -> String {
+        serde_json::json!({
+            "id": id,
+            "method": "submit",
+            "params": {"id": session_id, "job_id": job_id, "nonce": "00000000", "result": "00"},
+        })
+        .to_string()
+    }
+
+    #[tokio::test]
+    async fn test_job_id_reused_by_new_pool_is_not_stale() {
+        let old_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let new_listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let old_target = target("old", old_listener.local_addr().unwrap().to_string(), Vec::new());
+        let new_target = target("new", new_listener.local_addr().unwrap().to_string(), Vec::new());
+
+        let (mut miner, control) = start_session("collision", old_target, "").await;
+        let mut old_pool = Peer::accept(&old_listener).await;
+        miner.send(r#"{"id":1,"method":"login","params":{"login":"4Awallet","pass":"x"}}"#).await;
+        accept_login(&mut old_pool, "old-session", "1").await;
+        assert_eq!(miner.recv().await["result"]["job"]["job_id"], "1");
+        old_pool.send(r#"{"jsonrpc":"2.0","method":"job","params":{"job_id":"2","blob":"00","target":"b88d0600"}}"#).await;
+        assert_eq!(miner.recv().await["params"]["job_id"], "2");
+
+        control.send_modify(|state| state.target = Some(new_target));
+        let mut new_pool = Peer::accept(&new_listener).await;
+        // The new pool numbers its jobs from 1 as well
+        accept_login(&mut new_pool, "new-session", "1").await;
+        assert_eq!(miner.recv().await["params"]["job_id"], "1");
+
+        miner.send(&submit(2, "old-session", "2")).await;
+        let stale = miner.recv().await;
+        assert_eq!(stale["id"], 2);
+        assert_eq!(stale["error"]["message"], "Stale share: pool switched");
+
+        miner.send(&submit(3, "old-session", "1")).await;
+        let forwarded = new_pool.recv().await;
+        assert_eq!(forwarded["id"], 3);
+        assert_eq!(forwarded["params"]["id"], "new-session");
+    }
+
+    #[test]
+    fn test_split_login() {
+        assert_eq!(split_login("4Awallet:rig1"), ("4Awallet", Some("rig1")));
+        assert_eq!(split_login("ltc1qwallet.rig2"), ("ltc1qwallet", Some("rig2")));
+        assert_eq!(split_login("4Awallet"), ("4Awallet", None));
+    }
+
+    #[tokio::test]
+    async fn test_fails_over_to_backup_when_pool_goes_idle() {
+        let primary = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let backup = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = target(
+            "idle",
+            primary.local_addr().unwrap().to_string(),
+            vec![backup.local_addr().unwrap().to_string()],
+        );
+
+        let (mut miner, _control) = start_session("idle", target, "upstream_idle_timeout_secs = 1").await;
+        let mut primary_pool = Peer::accept(&primary).await;
+        log_in(&mut miner, &mut primary_pool).await;
+
+        // The primary sends nothing more; the login is replayed to the backup
+        let mut backup_pool = Peer::accept(&backup).await;
+        accept_login(&mut backup_pool, "session-2", "7").await;
+        assert_eq!(miner.recv().await["params"]["job_id"], "7");
+    }
+
+    #[tokio::test]
+    async fn test_holds_miner_while_no_endpoint_is_reachable() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let target = target("outage", address.to_string(), Vec::new());
+
+        let (mut miner, _control) = start_session("outage", target, "").await;
+        let mut pool = Peer::accept(&listener).await;
+        log_in(&mut miner, &mut pool).await;
+
+        // The whole target goes down, then comes back on the same address
+        drop(listener);
+        drop(pool);
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        let listener = TcpListener::bind(address).await.unwrap();
+
+        let mut pool = Peer::accept(&listener).await;
+        accept_login(&mut pool, "session-2", "7").await;
+        assert_eq!(miner.recv().await["params"]["job_id"], "7");
+    }
+}
@@ -0,0 +1,116 @@
+use crate::config::VardiffConfig;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// Retargets are skipped while the share rate is within this factor of the goal
+const RETARGET_TOLERANCE: f64 = 1.5;
+
+/// Largest change of difficulty in a single retarget
+const MAX_RETARGET_FACTOR: f64 = 4.0;
+
+/// Per-session variable difficulty.
+///
+/// The assigned difficulty never exceeds the upstream job's, so every share
+/// the pool would accept still reaches it.
+pub struct Vardiff {
+    config: VardiffConfig,
+    difficulty: Option<f64>,
+    window_start: Instant,
+    shares: u32,
+}
+
+impl Vardiff {
+    pub fn new(config: VardiffConfig, now: Instant) -> Self {
+        Self {
+            config,
+            difficulty: None,
+            window_start: now,
+            shares: 0,
+        }
+    }
+
+    /// Difficulty to hand the miner for a job at `upstream` difficulty
+    pub fn assign(&mut self, upstream: f64) -> f64 {
+        let difficulty = self
+            .difficulty
+            .unwrap_or(upstream)
+            .min(upstream)
+            .max(self.config.min_difficulty.min(upstream));
+        self.difficulty = Some(difficulty);
+        difficulty
+    }
+
+    /// Count a share that met the assigned difficulty
+    pub fn record_share(&mut self, now: Instant) {
+        self.shares += 1;
+        self.retarget(now);
+    }
+
+    /// Adjust the difficulty once a retarget window has passed.
+    /// Returns true when the difficulty changed.
+    pub fn retarget(&mut self, now: Instant) -> bool {
+        let Some(difficulty) = self.difficulty else {
+            return false;
+        };
+        let elapsed = now.duration_since(self.window_start);
+        if elapsed < Duration::from_secs(self.config.retarget_time_secs) {
+            return false;
+        }
+
+        // No shares in a whole window counts as one, to still move down
+        let share_time = elapsed.as_secs_f64() / self.shares.max(1) as f64;
+        let ratio = self.config.target_share_time_secs / share_time;
+        self.window_start = now;
+        self.shares = 0;
+
+        if (1.0 / RETARGET_TOLERANCE..=RETARGET_TOLERANCE).contains(&ratio) {
+            return false;
+        }
+
+        let ratio = ratio.clamp(1.0 / MAX_RETARGET_FACTOR, MAX_RETARGET_FACTOR);
+        let retargeted = (difficulty * ratio).clamp(self.config.min_difficulty, self.config.max_difficulty);
+        debug!("Vardiff retarget: {} -> {} (share every {:.1}s)", difficulty, retargeted, share_time);
+        self.difficulty = Some(retargeted);
+        retargeted != difficulty
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> VardiffConfig {
+        VardiffConfig {
+            enabled: true,
+            target_share_time_secs: 10.0,
+            retarget_time_secs: 60,
+            min_difficulty: 100.0,
+            max_difficulty: 1e12,
+        }
+    }
+
+    #[test]
+    fn test_lowers_difficulty_for_slow_miner() {
+        let start = Instant::now();
+        let mut vardiff = Vardiff::new(config(), start);
+        assert_eq!(vardiff.assign(100_000.0), 100_000.0);
+
+        // One share per minute against a 10s goal
+        vardiff.record_share(start + Duration::from_secs(60));
+        assert_eq!(vardiff.assign(100_000.0), 25_000.0);
+    }
+
+    #[test]
+    fn test_never_above_upstream() {
+        let start = Instant::now();
+        let mut vardiff = Vardiff::new(config(), start);
+        vardiff.assign(1000.0);
+
+        // A share every second would push difficulty up, but upstream caps it
+        for second in 1..=60 {
+            vardiff.record_share(start + Duration::from_secs(second));
+        }
+        assert_eq!(vardiff.assign(1000.0), 1000.0);
+        assert_eq!(vardiff.assign(500.0), 500.0);
+    }
+}
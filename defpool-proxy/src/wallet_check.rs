@@ -0,0 +1,147 @@
+use crate::config::Config;
+use crate::server_auth::ServerAuth;
+use anyhow::Result;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::warn;
+
+/// Wallets accepted before the cache starts over
+const MAX_ACCEPTED: usize = 10_000;
+
+/// How long a login waits for the server's verdict
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Deserialize)]
+struct Verdict {
+    valid: bool,
+    error: Option<String>,
+}
+
+/// Asks the server whether a miner's wallet is an address of one of the
+/// pool's coins before its login goes upstream.
+///
+/// When the server cannot be asked the login goes through: the server
+/// refuses the wallet's shares anyway.
+pub struct WalletChecker {
+    client: reqwest::Client,
+    server_url: String,
+    auth: ServerAuth,
+    accepted: Mutex<HashSet<String>>,
+}
+
+impl WalletChecker {
+    pub fn new(config: &Config, auth: ServerAuth) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            server_url: config.server_endpoint.clone(),
+            auth,
+            accepted: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Why a wallet is refused, or None to let its login through
+    pub async fn refusal(&self, wallet: &str) -> Option<String> {
+        if self.accepted.lock().unwrap().contains(wallet) {
+            return None;
+        }
+
+        match self.ask(wallet).await {
+            Ok(Verdict { valid: true, .. }) => {
+                let mut accepted = self.accepted.lock().unwrap();
+                if accepted.len() >= MAX_ACCEPTED {
+                    accepted.clear();
+                }
+                accepted.insert(wallet.to_string());
+                None
+            }
+            Ok(Verdict { error, .. }) => Some(error.unwrap_or_else(|| "invalid wallet address".to_string())),
+            Err(e) => {
+                warn!("Failed to check wallet {} with the server, letting it in: {}", wallet, e);
+                None
+            }
+        }
+    }
+
+    async fn ask(&self, wallet: &str) -> Result<Verdict> {
+        let response = self.post("/api/v1/addresses/validate", &serde_json::json!({ "address": wallet })).await?;
+        if !response.status().is_success() {
+            anyhow::bail!("Server returned error: {}", response.status());
+        }
+        Ok(response.json().await?)
+    }
+
+    async fn post(&self, path: &str, body: &serde_json::Value) -> Result<reqwest::Response> {
+        let url = format!("{}{}", self.server_url, path);
+        let body = serde_json::to_vec(body)?;
+
+        let request = self.client
+            .post(&url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .timeout(CHECK_TIMEOUT);
+        Ok(self.auth
+            .sign(request, "POST", path, &body)
+            .body(body)
+            .send()
+            .await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Answers one request per verdict, in order
+    async fn serve_verdicts(listener: TcpListener, verdicts: Vec<&'static str>) {
+        for verdict in verdicts {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut chunk = [0u8; 4096];
+            let _ = socket.read(&mut chunk).await.unwrap();
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                verdict.len(),
+                verdict
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        }
+    }
+
+    fn checker(server_endpoint: String) -> WalletChecker {
+        let config: Config = toml::from_str(&format!(
+            "server_endpoint = {:?}\nlisten_address = \"127.0.0.1:0\"",
+            server_endpoint
+        ))
+        .unwrap();
+        WalletChecker::new(&config, ServerAuth::new(&config.server_auth))
+    }
+
+    #[tokio::test]
+    async fn test_refuses_invalid_wallets_and_remembers_valid_ones() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let checker = checker(format!("http://{}", listener.local_addr().unwrap()));
+        tokio::spawn(serve_verdicts(listener, vec![
+            r#"{"valid":false,"error":"not a valid address of any pool coin (LTC, XMR)"}"#,
+            r#"{"valid":true,"coin":"XMR"}"#,
+        ]));
+
+        assert_eq!(
+            checker.refusal("4Atypo").await.as_deref(),
+            Some("not a valid address of any pool coin (LTC, XMR)")
+        );
+        assert_eq!(checker.refusal("4Awallet").await, None);
+        // Answered from the cache, the server is gone
+        assert_eq!(checker.refusal("4Awallet").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_lets_logins_through_without_a_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let checker = checker(format!("http://{}", listener.local_addr().unwrap()));
+        drop(listener);
+
+        assert_eq!(checker.refusal("4Awallet").await, None);
+    }
+}
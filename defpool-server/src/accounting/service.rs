@@ -1,33 +1,53 @@
+use crate::address::AddressValidator;
 use crate::db::{models::*, repository::ShareRepository};
 use anyhow::Result;
 use std::sync::Arc;
 use tracing::{info, warn};
 
+/// A share that can never be recorded, with the reason
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct InvalidShare(pub String);
+
 /// Accounting service for share tracking and miner stats
 pub struct AccountingService {
     repository: Arc<ShareRepository>,
+    addresses: Arc<AddressValidator>,
 }
 
 impl AccountingService {
-    pub fn new(repository: Arc<ShareRepository>) -> Self {
-        Self { repository }
+    pub fn new(repository: Arc<ShareRepository>, addresses: Arc<AddressValidator>) -> Self {
+        Self { repository, addresses }
     }
 
-    /// Record a share submission
-    pub async fn record_share(&self, submission: ShareSubmission) -> Result<()> {
+    /// Record a share submission. Returns false for a duplicate delivery and
+    /// an [`InvalidShare`] error for a share that can never be recorded.
+    pub async fn record_share(&self, submission: ShareSubmission) -> Result<bool> {
         info!(
-            "Recording share: wallet={}, worker={}, target={}, difficulty={}, valid={}",
+            "Recording share: wallet={}, worker={}, target={}, difficulty={}, status={}",
             submission.wallet_address,
             submission.worker_name,
             submission.target_name,
             submission.difficulty,
-            submission.valid
+            submission.status().as_str()
         );
 
+        if let Err(reason) = self.validate(&submission).await {
+            warn!("Refusing share from {}: {}", submission.wallet_address, reason);
+            return Err(InvalidShare(reason).into());
+        }
+
         match self.repository.create_share(&submission).await {
-            Ok(share) => {
+            Ok(Some(share)) => {
                 info!("Share recorded: id={}", share.id);
-                Ok(())
+                Ok(true)
+            }
+            Ok(None) => {
+                info!(
+                    "Ignoring duplicate delivery of share {}",
+                    submission.share_id.as_deref().unwrap_or_default()
+                );
+                Ok(false)
             }
             Err(e) => {
                 warn!("Failed to record share: {}", e);
@@ -36,6 +56,37 @@ impl AccountingService {
         }
     }
 
+    /// Record a batch of share submissions, returning one result per share in order.
+    /// Malformed shares are reported as invalid without failing the batch.
+    pub async fn record_shares(&self, submissions: Vec<ShareSubmission>) -> Result<Vec<ShareBatchResult>> {
+        let mut results: Vec<ShareBatchResult> = Vec::with_capacity(submissions.len());
+        for submission in &submissions {
+            results.push(ShareBatchResult {
+                share_id: submission.share_id.clone(),
+                outcome: ShareOutcome::Invalid,
+                error: self.validate(submission).await.err(),
+            });
+        }
+
+        let (indices, valid): (Vec<usize>, Vec<ShareSubmission>) = submissions
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| results[*i].error.is_none())
+            .unzip();
+
+        let outcomes = self.repository.create_shares(&valid).await.map_err(|e| {
+            warn!("Failed to record share batch: {}", e);
+            e
+        })?;
+        for (i, outcome) in indices.into_iter().zip(outcomes) {
+            results[i].outcome = outcome;
+        }
+
+        let created = results.iter().filter(|r| r.outcome == ShareOutcome::Created).count();
+        info!("Recorded share batch: {} of {} shares created", created, results.len());
+        Ok(results)
+    }
+
     /// Get miner statistics
     pub async fn get_miner_stats(&self, wallet_address: &str) -> Result<Option<MinerStats>> {
         self.repository.get_miner_stats(wallet_address).await
@@ -50,4 +101,19 @@ impl AccountingService {
     pub async fn get_pool_stats(&self) -> Result<crate::api::PoolStats> {
         self.repository.get_pool_stats().await
     }
+
+    /// Reject shares that could never be recorded, such as shares of a wallet
+    /// that is no address of the pool's coins and could never be paid
+    async fn validate(&self, submission: &ShareSubmission) -> std::result::Result<(), String> {
+        if submission.wallet_address.is_empty() {
+            return Err("Missing wallet address".to_string());
+        }
+        if !submission.difficulty.is_finite() || submission.difficulty <= 0.0 {
+            return Err(format!("Invalid difficulty {}", submission.difficulty));
+        }
+        if let Err(e) = self.addresses.check_miner(&submission.wallet_address).await {
+            return Err(format!("Invalid wallet address {}: {}", submission.wallet_address, e));
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,352 @@
+//! Wallet address validation.
+//!
+//! Addresses are checked offline against each coin's format: Monero's block
+//! base58 with a Keccak checksum, base58check for Bitcoin-style coins and
+//! bech32/bech32m for segwit ones. Bitcoin-style daemons can be asked to
+//! confirm an address as well.
+
+use crate::config::Config;
+use crate::daemon::rpc_client::DaemonRpcClient;
+use bech32::{FromBase32, Variant};
+use sha2::{Digest, Sha256};
+use sha3::Keccak256;
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+use tracing::warn;
+
+/// Longest address accepted for coins without a known format
+const MAX_ADDRESS_LEN: usize = 128;
+
+/// Daemon-confirmed addresses remembered before the cache starts over
+const MAX_CONFIRMED: usize = 10_000;
+
+/// Why an address was refused
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum AddressError {
+    #[error("wallet address is empty")]
+    Empty,
+    #[error("not a valid {coin} address: {reason}")]
+    Invalid { coin: String, reason: &'static str },
+    #[error("the {coin} daemon does not accept it as an address")]
+    RefusedByDaemon { coin: String },
+    #[error("not a valid address of any pool coin ({})", .coins.join(", "))]
+    NoCoin { coins: Vec<String> },
+}
+
+/// Check an address against a coin's format, without a daemon. Coins without
+/// a known format only get a length and character check.
+pub fn validate(coin: &str, address: &str) -> Result<(), AddressError> {
+    if address.is_empty() {
+        return Err(AddressError::Empty);
+    }
+    let invalid = |reason| Err(AddressError::Invalid { coin: coin.to_string(), reason });
+
+    let checked = match coin.to_ascii_uppercase().as_str() {
+        "XMR" => monero(address),
+        "BTC" => bitcoin_style(address, &[0x00], &[0x05], Some("bc")),
+        "LTC" => bitcoin_style(address, &[0x30], &[0x32, 0x05], Some("ltc")),
+        "DOGE" => bitcoin_style(address, &[0x1e], &[0x16], None),
+        _ if address.len() > MAX_ADDRESS_LEN => Err("too long"),
+        _ if !address.chars().all(|c| c.is_ascii_alphanumeric()) => Err("unexpected characters"),
+        _ => Ok(()),
+    };
+    checked.or_else(invalid)
+}
+
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Characters a Monero base58 block of 0 to 8 bytes encodes to
+const MONERO_BLOCK_CHARS: [usize; 9] = [0, 2, 3, 5, 6, 7, 9, 10, 11];
+
+/// Mainnet network bytes of standard, integrated and subaddresses, with the
+/// decoded length of each
+const MONERO_PREFIXES: [(u8, usize); 3] = [(18, 69), (19, 77), (42, 69)];
+
+/// Monero base58: 8-byte blocks of 11 characters each, the last block shorter
+fn monero_base58_decode(encoded: &str) -> Option<Vec<u8>> {
+    let encoded = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(encoded.len() * 8 / 11 + 8);
+    for block in encoded.chunks(11) {
+        let size = MONERO_BLOCK_CHARS.iter().position(|&chars| chars == block.len())?;
+        let mut value: u128 = 0;
+        for c in block {
+            let digit = BASE58_ALPHABET.iter().position(|a| a == c)?;
+            value = value * 58 + digit as u128;
+        }
+        if value >> (8 * size) != 0 {
+            return None;
+        }
+        decoded.extend_from_slice(&value.to_be_bytes()[16 - size..]);
+    }
+    Some(decoded)
+}
+
+fn monero(address: &str) -> Result<(), &'static str> {
+    let decoded = monero_base58_decode(address).ok_or("not Monero base58")?;
+    let Some(&(_, len)) = MONERO_PREFIXES.iter().find(|(prefix, _)| decoded.first() == Some(prefix)) else {
+        return Err("not a mainnet address");
+    };
+    if decoded.len() != len {
+        return Err("wrong length");
+    }
+
+    let (data, checksum) = decoded.split_at(len - 4);
+    if Keccak256::digest(data)[..4] != *checksum {
+        return Err("checksum mismatch");
+    }
+    Ok(())
+}
+
+/// Base58check with one of the version bytes, or a segwit address of `hrp`
+fn bitcoin_style(address: &str, p2pkh: &[u8], p2sh: &[u8], hrp: Option<&str>) -> Result<(), &'static str> {
+    if let Some(hrp) = hrp {
+        let prefix = format!("{}1", hrp);
+        if address.get(..prefix.len()).is_some_and(|start| start.eq_ignore_ascii_case(&prefix)) {
+            return segwit(address, hrp);
+        }
+    }
+
+    let decoded = bs58::decode(address).into_vec().map_err(|_| "not base58")?;
+    if decoded.len() != 25 {
+        return Err("wrong length");
+    }
+    let (payload, checksum) = decoded.split_at(21);
+    if Sha256::digest(Sha256::digest(payload))[..4] != *checksum {
+        return Err("checksum mismatch");
+    }
+    if !p2pkh.contains(&payload[0]) && !p2sh.contains(&payload[0]) {
+        return Err("not a mainnet address");
+    }
+    Ok(())
+}
+
+/// BIP 173/350: witness version 0 in bech32 with a 20 or 32 byte program,
+/// later versions in bech32m with 2 to 40 bytes
+fn segwit(address: &str, hrp: &str) -> Result<(), &'static str> {
+    let (decoded_hrp, data, variant) = bech32::decode(address).map_err(|_| "bech32 checksum mismatch")?;
+    if decoded_hrp != hrp {
+        return Err("not a mainnet address");
+    }
+    let (version, program) = data.split_first().ok_or("missing witness version")?;
+    let program = Vec::<u8>::from_base32(program).map_err(|_| "bad witness program")?;
+    match (version.to_u8(), variant) {
+        (0, Variant::Bech32) if program.len() == 20 || program.len() == 32 => Ok(()),
+        (1..=16, Variant::Bech32m) if (2..=40).contains(&program.len()) => Ok(()),
+        _ => Err("bad witness program"),
+    }
+}
+
+/// Validates wallet addresses for the pool's coins, asking their daemons too
+/// when `[addresses] daemon_check` is on
+pub struct AddressValidator {
+    /// Coins a miner's wallet may belong to: mined, paid out or exchanged to
+    coins: Vec<String>,
+    /// Coins of the pool's targets, paid out to the miner's wallet
+    mined: Vec<String>,
+    /// Bitcoin-style daemon of each coin, for `validateaddress`
+    daemons: HashMap<String, DaemonRpcClient>,
+    /// Coin and address pairs a daemon already accepted
+    confirmed: Mutex<HashSet<(String, String)>>,
+}
+
+impl AddressValidator {
+    pub fn new(config: &Config) -> Self {
+        let mut mined: Vec<String> = config.targets.iter().map(|t| t.coin.to_ascii_uppercase()).collect();
+        mined.sort();
+        mined.dedup();
+        let mut coins: Vec<String> = mined
+            .iter()
+            .cloned()
+            .chain(config.payout_wallets.keys().map(|coin| coin.to_ascii_uppercase()))
+            .chain(config.exchange.iter().map(|e| e.quote.to_ascii_uppercase()))
+            .collect();
+        coins.sort();
+        coins.dedup();
+
+        // monerod has no address check; the offline one is complete for Monero
+        let daemons = config
+            .targets
+            .iter()
+            .filter(|_| config.addresses.daemon_check)
+            .filter(|t| !t.coin.eq_ignore_ascii_case("XMR"))
+            .filter_map(|t| {
+                let url = t.daemon_rpc_url.clone()?;
+                let client = DaemonRpcClient::new(url, t.daemon_rpc_user.clone(), t.daemon_rpc_password.clone());
+                Some((t.coin.to_ascii_uppercase(), client))
+            })
+            .collect();
+
+        Self { coins, mined, daemons, confirmed: Mutex::new(HashSet::new()) }
+    }
+
+    /// Check an address of one coin. A daemon that cannot be reached does not
+    /// refuse the address.
+    pub async fn check(&self, coin: &str, address: &str) -> Result<(), AddressError> {
+        validate(coin, address)?;
+
+        let coin = coin.to_ascii_uppercase();
+        let Some(daemon) = self.daemons.get(&coin) else {
+            return Ok(());
+        };
+        let key = (coin, address.to_string());
+        if self.confirmed.lock().unwrap().contains(&key) {
+            return Ok(());
+        }
+
+        match daemon.validate_address(address).await {
+            Ok(true) => {
+                let mut confirmed = self.confirmed.lock().unwrap();
+                if confirmed.len() >= MAX_CONFIRMED {
+                    confirmed.clear();
+                }
+                confirmed.insert(key);
+                Ok(())
+            }
+            Ok(false) => Err(AddressError::RefusedByDaemon { coin: key.0 }),
+            Err(e) => {
+                warn!("Failed to check {} address {} with its daemon: {:#}", key.0, address, e);
+                Ok(())
+            }
+        }
+    }
+
+    /// Check a miner's wallet, which must be an address of one of the pool's
+    /// coins. Returns the coin it belongs to.
+    pub async fn check_miner(&self, address: &str) -> Result<String, AddressError> {
+        self.check_any(&self.coins, address).await
+    }
+
+    /// Check a miner's wallet against the coins the pool mines, which are paid
+    /// out to it. Returns the coin it belongs to.
+    pub async fn check_mined(&self, address: &str) -> Result<String, AddressError> {
+        self.check_any(&self.mined, address).await
+    }
+
+    async fn check_any(&self, coins: &[String], address: &str) -> Result<String, AddressError> {
+        if address.is_empty() {
+            return Err(AddressError::Empty);
+        }
+        let Some(coin) = coins.iter().find(|coin| validate(coin, address).is_ok()) else {
+            return Err(AddressError::NoCoin { coins: coins.to_vec() });
+        };
+        self.check(coin, address).await?;
+        Ok(coin.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bech32::{ToBase32, u5};
+
+    fn monero_base58_encode(data: &[u8]) -> String {
+        data.chunks(8)
+            .map(|block| {
+                let mut value = block.iter().fold(0u128, |value, &b| value << 8 | b as u128);
+                let mut chars = vec![b'1'; MONERO_BLOCK_CHARS[block.len()]];
+                for c in chars.iter_mut().rev() {
+                    *c = BASE58_ALPHABET[(value % 58) as usize];
+                    value /= 58;
+                }
+                String::from_utf8(chars).unwrap()
+            })
+            .collect()
+    }
+
+    fn monero_address(prefix: u8, keys: &[u8]) -> String {
+        let mut data = vec![prefix];
+        data.extend_from_slice(keys);
+        let checksum = Keccak256::digest(&data);
+        data.extend_from_slice(&checksum[..4]);
+        monero_base58_encode(&data)
+    }
+
+    fn base58check(version: u8) -> String {
+        let mut payload = vec![version];
+        payload.extend_from_slice(&[0x5a; 20]);
+        let checksum = Sha256::digest(Sha256::digest(&payload));
+        payload.extend_from_slice(&checksum[..4]);
+        bs58::encode(payload).into_string()
+    }
+
+    fn segwit_address(hrp: &str, version: u8, program: &[u8], variant: Variant) -> String {
+        let mut data = vec![u5::try_from_u8(version).unwrap()];
+        data.extend(program.to_base32());
+        bech32::encode(hrp, data, variant).unwrap()
+    }
+
+    fn reason(coin: &str, address: &str) -> Option<&'static str> {
+        match validate(coin, address) {
+            Err(AddressError::Invalid { reason, .. }) => Some(reason),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_monero_addresses() {
+        // Monero General Fund
+        let general_fund = "44AFFq5kSiGBoZ4NMDwYtN18obc8AemS33DBLWs3H7otXft3XjrpDtQGv7SqSsaBYBb98uNbr2VBBEt7f2wfn3RVGQBEP3A";
+        let subaddress = monero_address(42, &[7; 64]);
+        let integrated = monero_address(19, &[7; 72]);
+
+        assert_eq!(validate("XMR", general_fund), Ok(()));
+        assert!(subaddress.starts_with('8'));
+        assert_eq!(validate("XMR", &subaddress), Ok(()));
+        assert_eq!(validate("XMR", &integrated), Ok(()));
+
+        let typo = general_fund.replacen("44AFF", "44AFG", 1);
+        assert_eq!(reason("XMR", &typo), Some("checksum mismatch"));
+        assert_eq!(reason("XMR", &monero_address(24, &[7; 64])), Some("not a mainnet address"));
+        assert_eq!(reason("XMR", "4Awallet"), Some("not Monero base58"));
+    }
+
+    #[test]
+    fn test_base58check_addresses() {
+        assert_eq!(validate("BTC", "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa"), Ok(()));
+        assert_eq!(validate("LTC", &base58check(0x30)), Ok(()));
+        assert_eq!(validate("LTC", &base58check(0x32)), Ok(()));
+        assert_eq!(validate("DOGE", &base58check(0x1e)), Ok(()));
+
+        assert_eq!(reason("LTC", &base58check(0x1e)), Some("not a mainnet address"));
+        assert_eq!(reason("BTC", "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNb"), Some("checksum mismatch"));
+        assert_eq!(reason("DOGE", "D0wallet"), Some("not base58"));
+    }
+
+    #[test]
+    fn test_segwit_addresses() {
+        let v0 = segwit_address("ltc", 0, &[0x75; 20], Variant::Bech32);
+        let taproot = segwit_address("bc", 1, &[0x79; 32], Variant::Bech32m);
+
+        assert_eq!(validate("LTC", &v0), Ok(()));
+        assert_eq!(validate("LTC", &v0.to_uppercase()), Ok(()));
+        assert_eq!(validate("BTC", &taproot), Ok(()));
+
+        assert_eq!(reason("BTC", &segwit_address("bc", 0, &[0x75; 20], Variant::Bech32m)), Some("bad witness program"));
+        assert_eq!(reason("BTC", &segwit_address("bc", 0, &[0x75; 21], Variant::Bech32)), Some("bad witness program"));
+        assert_eq!(reason("LTC", &format!("{}q", &v0[..v0.len() - 1])), Some("bech32 checksum mismatch"));
+        assert!(validate("LTC", &taproot).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_wallets_checked_against_mined_coins() {
+        let validator = AddressValidator {
+            coins: vec!["BTC".to_string(), "XMR".to_string()],
+            mined: vec!["XMR".to_string()],
+            daemons: HashMap::new(),
+            confirmed: Mutex::new(HashSet::new()),
+        };
+        let btc = "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa";
+        let xmr = monero_address(18, &[7; 64]);
+
+        assert_eq!(validator.check_miner(btc).await, Ok("BTC".to_string()));
+        assert_eq!(validator.check_mined(&xmr).await, Ok("XMR".to_string()));
+        assert_eq!(validator.check_mined(btc).await, Err(AddressError::NoCoin { coins: vec!["XMR".to_string()] }));
+    }
+
+    #[test]
+    fn test_unknown_coins_get_basic_checks() {
+        assert_eq!(validate("RVN", "RWalletAddress123"), Ok(()));
+        assert_eq!(validate("RVN", ""), Err(AddressError::Empty));
+        assert_eq!(reason("RVN", "wallet with spaces"), Some("unexpected characters"));
+    }
+}
@@ -1,13 +1,30 @@
 use axum::{
-    extract::{State, Path},
-    response::Json,
+    extract::{State, Path, Query},
+    response::{Json, sse::{Event, KeepAlive, Sse}},
     http::StatusCode,
 };
-use crate::state::{AppState, Target};
+use crate::accounting::service::InvalidShare;
+use crate::events::ProxyEvent;
+use crate::state::{AppState, Target, TargetHealth};
 use crate::profitability::ProfitabilityScore;
-use crate::db::models::{ShareSubmission, MinerStats, Worker, Balance, Payout, PayoutRequest};
-use tracing::info;
+use crate::payout::{RefusedPayout, RewardScheme};
+use crate::money::Decimal;
+use crate::ledger::MinerEntry;
+use crate::db::models::{ShareSubmission, ShareBatchResult, MinerStats, Worker, Balance, Payout, PayoutRequest, Block, BlockReport};
+use tracing::{info, warn};
 use serde::{Deserialize, Serialize};
+use futures::{Stream, StreamExt};
+use tokio::sync::broadcast::error::RecvError;
+
+/// Body of a refused request
+#[derive(Debug, Serialize)]
+pub struct ApiError {
+    pub error: String,
+}
+
+pub fn refuse(status: StatusCode, error: impl ToString) -> (StatusCode, Json<ApiError>) {
+    (status, Json(ApiError { error: error.to_string() }))
+}
 
 /// GET /api/v1/target - Get current mining target
 pub async fn get_current_target(State(state): State<AppState>) -> Json<Target> {
@@ -30,6 +47,42 @@ pub async fn get_current_target_name(State(state): State<AppState>) -> Json<Stri
     Json(current_target)
 }
 
+/// GET /api/v1/targets/health - Reachability of every target
+pub async fn get_target_health(State(state): State<AppState>) -> Json<Vec<TargetHealth>> {
+    let health = state.target_health.read().unwrap().values().cloned().collect();
+    Json(health)
+}
+
+/// GET /api/v1/proxy/events - Stream commands to proxies (SSE)
+///
+/// The current target is sent first so a reconnecting proxy resyncs at once.
+pub async fn proxy_events(
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    info!("API: Proxy subscribed to events");
+
+    let current = ProxyEvent::TargetChanged { target: state.get_current_target() };
+    let receiver = state.proxy_events.subscribe();
+
+    let live = futures::stream::unfold((receiver, state), |(mut receiver, state)| async move {
+        let event = match receiver.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(skipped)) => {
+                warn!("Proxy event stream lagged by {} events, resyncing target", skipped);
+                ProxyEvent::TargetChanged { target: state.get_current_target() }
+            }
+            Err(RecvError::Closed) => return None,
+        };
+        Some((event, (receiver, state)))
+    });
+
+    let stream = futures::stream::once(async { current })
+        .chain(live)
+        .map(|event| Event::default().event(event.name()).json_data(&event));
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
 /// GET /api/v1/miners/{wallet}/stats - Get miner statistics
 pub async fn get_miner_stats(
     State(state): State<AppState>,
@@ -58,13 +111,118 @@ pub async fn get_miner_workers(
 }
 
 /// POST /api/v1/shares - Record a share (internal, from proxy)
+///
+/// Redelivering a share with a known `share_id` is acknowledged with 200 OK;
+/// a share that can never be recorded, e.g. of an invalid wallet, is refused
+/// with 422.
 pub async fn record_share(
     State(state): State<AppState>,
     Json(submission): Json<ShareSubmission>,
-) -> StatusCode {
+) -> Result<StatusCode, (StatusCode, Json<ApiError>)> {
     match state.accounting_service.record_share(submission).await {
-        Ok(_) => StatusCode::CREATED,
-        Err(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        Ok(true) => Ok(StatusCode::CREATED),
+        Ok(false) => Ok(StatusCode::OK),
+        Err(e) => match e.downcast_ref::<InvalidShare>() {
+            Some(invalid) => Err(refuse(StatusCode::UNPROCESSABLE_ENTITY, invalid)),
+            None => Err(refuse(StatusCode::INTERNAL_SERVER_ERROR, "failed to record share")),
+        },
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AddressCheck {
+    pub address: String,
+}
+
+/// Verdict on a miner's wallet
+#[derive(Serialize)]
+pub struct AddressVerdict {
+    pub valid: bool,
+    /// Coin the address belongs to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub coin: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// POST /api/v1/addresses/validate - Check a miner's wallet before its login is accepted (internal, from proxy)
+pub async fn validate_address(
+    State(state): State<AppState>,
+    Json(check): Json<AddressCheck>,
+) -> Json<AddressVerdict> {
+    let verdict = match state.addresses.check_miner(&check.address).await {
+        Ok(coin) => AddressVerdict { valid: true, coin: Some(coin), error: None },
+        Err(e) => {
+            info!("API: Refusing wallet {}: {}", check.address, e);
+            AddressVerdict { valid: false, coin: None, error: Some(e.to_string()) }
+        }
+    };
+    Json(verdict)
+}
+
+/// Largest batch accepted by `POST /api/v1/shares/batch`
+const MAX_SHARE_BATCH: usize = 1000;
+
+/// POST /api/v1/shares/batch - Record many shares at once (internal, from proxy)
+///
+/// Returns one result per share, in request order.
+pub async fn record_share_batch(
+    State(state): State<AppState>,
+    Json(submissions): Json<Vec<ShareSubmission>>,
+) -> Result<Json<Vec<ShareBatchResult>>, StatusCode> {
+    if submissions.len() > MAX_SHARE_BATCH {
+        warn!("API: Share batch of {} exceeds limit of {}", submissions.len(), MAX_SHARE_BATCH);
+        return Err(StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    match state.accounting_service.record_shares(submissions).await {
+        Ok(results) => Ok(Json(results)),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// POST /api/v1/blocks/candidates - Record a block found on a daemon target (internal, from proxy)
+///
+/// Reporting a known block again is acknowledged with 200 OK.
+pub async fn record_block(
+    State(state): State<AppState>,
+    Json(report): Json<BlockReport>,
+) -> StatusCode {
+    match state.blocks.record_candidate(&report).await {
+        Ok(true) => StatusCode::CREATED,
+        Ok(false) => StatusCode::OK,
+        Err(e) => {
+            warn!("API: Failed to record block {}: {:#}", report.hash, e);
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+    }
+}
+
+/// Most blocks returned by `GET /api/v1/blocks`
+const MAX_BLOCKS: i64 = 500;
+
+#[derive(Deserialize)]
+pub struct BlocksQuery {
+    pub coin: Option<String>,
+    #[serde(default = "default_blocks_limit")]
+    pub limit: i64,
+}
+
+fn default_blocks_limit() -> i64 {
+    50
+}
+
+/// GET /api/v1/blocks - Recently found blocks with their effort and status
+pub async fn list_blocks(
+    State(state): State<AppState>,
+    Query(query): Query<BlocksQuery>,
+) -> Result<Json<Vec<Block>>, StatusCode> {
+    info!("API: Listing blocks");
+
+    let limit = query.limit.clamp(1, MAX_BLOCKS);
+    match state.blocks.list_blocks(query.coin.as_deref(), limit).await {
+        Ok(blocks) => Ok(Json(blocks)),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
     }
 }
 
@@ -121,11 +279,15 @@ pub async fn get_miner_balance(
 }
 
 /// POST /api/v1/miners/{wallet}/payout - Request a payout
+///
+/// Requires the wallet's PIN, checked by [`crate::auth::require_miner_pin`].
+/// A request the balance or payout threshold does not allow is refused with
+/// 422.
 pub async fn request_payout(
     State(state): State<AppState>,
     Path(wallet): Path<String>,
     Json(mut request): Json<PayoutRequest>,
-) -> Result<Json<Payout>, StatusCode> {
+) -> Result<Json<Payout>, (StatusCode, Json<ApiError>)> {
     info!("API: Payout request for miner: {}", wallet);
     
     // Ensure wallet matches path
@@ -133,7 +295,16 @@ pub async fn request_payout(
     
     match state.payout_service.request_payout(request).await {
         Ok(payout) => Ok(Json(payout)),
-        Err(_) => Err(StatusCode::BAD_REQUEST),
+        Err(e) => match e.downcast_ref::<RefusedPayout>() {
+            Some(refused) => {
+                warn!("Payout request refused: {}", refused);
+                Err(refuse(StatusCode::UNPROCESSABLE_ENTITY, refused))
+            }
+            None => {
+                warn!("Failed to create payout: {:#}", e);
+                Err(refuse(StatusCode::INTERNAL_SERVER_ERROR, "failed to create payout"))
+            }
+        },
     }
 }
 
@@ -150,28 +321,102 @@ pub async fn get_payout_history(
     }
 }
 
+/// Maximum ledger entries returned at once
+const MAX_LEDGER_ENTRIES: i64 = 1000;
+
+#[derive(Deserialize)]
+pub struct LedgerQuery {
+    pub coin: Option<String>,
+    #[serde(default = "default_ledger_limit")]
+    pub limit: i64,
+}
+
+fn default_ledger_limit() -> i64 {
+    100
+}
+
+/// GET /api/v1/miners/{wallet}/ledger - Every movement of a miner's balances
+pub async fn get_miner_ledger(
+    State(state): State<AppState>,
+    Path(wallet): Path<String>,
+    Query(query): Query<LedgerQuery>,
+) -> Result<Json<Vec<MinerEntry>>, StatusCode> {
+    info!("API: Fetching ledger for miner: {}", wallet);
+
+    let limit = query.limit.clamp(1, MAX_LEDGER_ENTRIES);
+    match state.payout_service.get_ledger(&wallet, query.coin.as_deref(), limit).await {
+        Ok(entries) => Ok(Json(entries)),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
 #[derive(Deserialize)]
 pub struct PayoutSettingsUpdate {
-    pub min_payout_threshold: f64,
+    pub min_payout_threshold: Decimal,
     pub payout_coin: String,
+    /// Address of the payout coin payouts in it go to, when the wallet is not one
+    #[serde(default)]
+    pub payout_address: Option<String>,
     pub auto_exchange: bool,
+    /// PPS, FPPS or SOLO instead of the coin's scheme; omitted follows the coin
+    #[serde(default)]
+    pub reward_scheme: Option<RewardScheme>,
 }
 
 /// PUT /api/v1/miners/{wallet}/payout-settings - Update payout settings
+///
+/// Requires the wallet's PIN, checked by [`crate::auth::require_miner_pin`].
+/// The wallet must be an address of a mined coin, as mined coins are paid out
+/// to it. Payouts in another payout coin need a `payout_address` of that coin.
 pub async fn update_payout_settings(
     State(state): State<AppState>,
     Path(wallet): Path<String>,
     Json(settings): Json<PayoutSettingsUpdate>,
-) -> Result<Json<crate::db::models::PayoutSettings>, StatusCode> {
+) -> Result<Json<crate::db::models::PayoutSettings>, (StatusCode, Json<ApiError>)> {
     info!("API: Updating payout settings for miner: {}", wallet);
-    
+
+    if let Some(scheme) = settings.reward_scheme.filter(|s| !s.is_miner_selectable()) {
+        warn!("Reward scheme {} can only be set per coin", scheme.as_str());
+        return Err(refuse(
+            StatusCode::BAD_REQUEST,
+            format!("reward scheme {} can only be set per coin", scheme.as_str()),
+        ));
+    }
+
+    let wallet_coin = match state.addresses.check_mined(&wallet).await {
+        Ok(coin) => coin,
+        Err(e) => {
+            warn!("Refusing payout settings of {}: {}", wallet, e);
+            return Err(refuse(StatusCode::UNPROCESSABLE_ENTITY, e));
+        }
+    };
+
+    match &settings.payout_address {
+        Some(address) => {
+            if let Err(e) = state.addresses.check(&settings.payout_coin, address).await {
+                warn!("Refusing payout address of {}: {}", wallet, e);
+                return Err(refuse(StatusCode::UNPROCESSABLE_ENTITY, e));
+            }
+        }
+        None if !settings.payout_coin.eq_ignore_ascii_case(&wallet_coin) => {
+            warn!("Refusing payout settings of {}: no {} payout address", wallet, settings.payout_coin);
+            return Err(refuse(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                format!("the wallet is a {} address; {} payouts need a payout_address", wallet_coin, settings.payout_coin),
+            ));
+        }
+        None => {}
+    }
+
     match state.payout_service.update_payout_settings(
         &wallet,
         settings.min_payout_threshold,
         &settings.payout_coin,
+        settings.payout_address.as_deref(),
         settings.auto_exchange,
+        settings.reward_scheme,
     ).await {
         Ok(settings) => Ok(Json(settings)),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+        Err(_) => Err(refuse(StatusCode::INTERNAL_SERVER_ERROR, "failed to update payout settings")),
     }
 }
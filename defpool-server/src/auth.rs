@@ -0,0 +1,491 @@
+use crate::api::{refuse, ApiError};
+use crate::state::AppState;
+use anyhow::Result;
+use axum::{
+    body::{to_bytes, Body},
+    extract::{ConnectInfo, Path, Request, State},
+    http::{request::Parts, StatusCode},
+    middleware::Next,
+    response::{Json, Response},
+};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::{Rng, RngCore};
+use sha2::Sha256;
+use sqlx::{FromRow, PgPool};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Mutex;
+use std::time::Instant;
+use tracing::{debug, info, warn};
+
+/// Name of the signing proxy
+pub const PROXY_HEADER: &str = "x-defpool-proxy";
+/// Unix time the request was signed at
+pub const TIMESTAMP_HEADER: &str = "x-defpool-timestamp";
+/// Hex HMAC-SHA256, see [`signer`]
+pub const SIGNATURE_HEADER: &str = "x-defpool-signature";
+
+/// PIN of the wallet a miner-changing request is for
+pub const PIN_HEADER: &str = "x-defpool-pin";
+
+/// Signed requests older or newer than this are refused, limiting replays
+const MAX_CLOCK_SKEW_SECS: i64 = 300;
+
+/// Largest internal request body accepted (a full share batch is well below)
+const MAX_BODY_BYTES: usize = 4 * 1024 * 1024;
+
+/// Wrong PINs from one caller before it is locked out
+const MAX_PIN_ATTEMPTS: u32 = 5;
+
+/// Wrong PINs for one wallet, from any callers, before it is locked
+const MAX_WALLET_PIN_ATTEMPTS: i64 = 20;
+
+/// How long a locked out caller or wallet is refused, and a wrong PIN is remembered
+const PIN_LOCKOUT_SECS: i64 = 900;
+
+/// Digits in a PIN issued by an operator
+const PIN_DIGITS: usize = 8;
+
+/// Request signature: HMAC-SHA256 keyed with the proxy secret over
+/// `"{timestamp}\n{METHOD}\n{path}\n"` followed by the raw body
+fn signer(secret: &str, timestamp: i64, method: &str, path: &str, body: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(format!("{}\n{}\n{}\n", timestamp, method, path).as_bytes());
+    mac.update(body);
+    mac
+}
+
+/// A proxy allowed to use the internal API
+#[derive(Debug, Clone, FromRow)]
+pub struct ProxyKey {
+    pub name: String,
+    pub created_at: DateTime<Utc>,
+    pub revoked_at: Option<DateTime<Utc>>,
+}
+
+/// Proxy keys, kept in the database so they can be issued and revoked while
+/// the server runs
+pub struct ProxyKeyStore {
+    pool: PgPool,
+}
+
+impl ProxyKeyStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Secret of a proxy that has not been revoked
+    pub async fn active_secret(&self, name: &str) -> Result<Option<String>> {
+        let secret = sqlx::query_scalar(
+            "SELECT secret FROM proxy_keys WHERE name = $1 AND revoked_at IS NULL"
+        )
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(secret)
+    }
+
+    /// Issue a key for a new proxy, or a fresh key for a revoked one.
+    /// Returns the secret to put in the proxy's config.
+    pub async fn issue(&self, name: &str) -> Result<String> {
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let secret = hex::encode(bytes);
+
+        let issued = sqlx::query(
+            r#"
+            INSERT INTO proxy_keys (name, secret, created_at)
+            VALUES ($1, $2, NOW())
+            ON CONFLICT (name) DO UPDATE
+                SET secret = EXCLUDED.secret, created_at = EXCLUDED.created_at, revoked_at = NULL
+                WHERE proxy_keys.revoked_at IS NOT NULL
+            "#
+        )
+        .bind(name)
+        .bind(&secret)
+        .execute(&self.pool)
+        .await?;
+
+        if issued.rows_affected() == 0 {
+            anyhow::bail!("Proxy {} already has an active key; revoke it first", name);
+        }
+        Ok(secret)
+    }
+
+    /// Revoke a proxy's key. Returns false when there was no active key.
+    pub async fn revoke(&self, name: &str) -> Result<bool> {
+        let revoked = sqlx::query(
+            "UPDATE proxy_keys SET revoked_at = NOW() WHERE name = $1 AND revoked_at IS NULL"
+        )
+        .bind(name)
+        .execute(&self.pool)
+        .await?;
+        Ok(revoked.rows_affected() > 0)
+    }
+
+    pub async fn list(&self) -> Result<Vec<ProxyKey>> {
+        let keys = sqlx::query_as::<_, ProxyKey>(
+            "SELECT name, created_at, revoked_at FROM proxy_keys ORDER BY name"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(keys)
+    }
+}
+
+/// PIN hash: HMAC-SHA256 keyed with the wallet's random salt
+fn pin_mac(salt: &str, pin: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(salt.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(pin.as_bytes());
+    mac
+}
+
+/// A random PIN of [`PIN_DIGITS`] digits
+fn new_pin() -> String {
+    let mut rng = rand::thread_rng();
+    (0..PIN_DIGITS).map(|_| char::from(b'0' + rng.gen_range(0..10))).collect()
+}
+
+/// Outcome of checking a miner's PIN
+#[derive(Debug, PartialEq)]
+pub enum PinCheck {
+    Valid,
+    /// A wrong PIN, or the wallet has none: the two are not told apart, so
+    /// wallets cannot be probed for a PIN
+    Wrong,
+    /// Too many wrong PINs from the caller or for the wallet; every attempt
+    /// is refused until then
+    Locked(DateTime<Utc>),
+}
+
+/// Wrong PINs of one caller
+#[derive(Debug)]
+struct CallerFailures {
+    count: u32,
+    last: Instant,
+    locked_until: Option<Instant>,
+}
+
+/// Wrong PINs per caller, whatever wallets they were for, so one caller
+/// cannot guess at many wallets nor lock a miner out of their own
+#[derive(Debug, Default)]
+struct PinFailures {
+    callers: Mutex<HashMap<IpAddr, CallerFailures>>,
+}
+
+impl PinFailures {
+    /// When a locked out caller may try again
+    fn locked_until(&self, caller: IpAddr, now: Instant) -> Option<Instant> {
+        let callers = self.callers.lock().unwrap();
+        callers.get(&caller)?.locked_until.filter(|until| *until > now)
+    }
+
+    /// Count a wrong PIN; returns when the caller is locked out until, once it is
+    fn fail(&self, caller: IpAddr, now: Instant) -> Option<Instant> {
+        let window = std::time::Duration::from_secs(PIN_LOCKOUT_SECS as u64);
+        let mut callers = self.callers.lock().unwrap();
+        callers.retain(|_, failures| now.duration_since(failures.last) < window);
+
+        let failures = callers.entry(caller).or_insert(CallerFailures { count: 0, last: now, locked_until: None });
+        failures.count += 1;
+        failures.last = now;
+        if failures.count >= MAX_PIN_ATTEMPTS {
+            failures.count = 0;
+            failures.locked_until = Some(now + window);
+        }
+        failures.locked_until
+    }
+
+    /// Forget a caller's wrong PINs after a right one
+    fn clear(&self, caller: IpAddr) {
+        self.callers.lock().unwrap().remove(&caller);
+    }
+}
+
+/// PINs miners authorize changes to their payouts with. An operator issues a
+/// wallet's PIN once its owner proved they hold it. Wrong PINs are counted
+/// per caller in memory and per wallet in the database, which holds across
+/// callers and restarts.
+pub struct MinerPinStore {
+    pool: PgPool,
+    failures: PinFailures,
+}
+
+impl MinerPinStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool, failures: PinFailures::default() }
+    }
+
+    /// Issue a wallet a new random PIN, replacing any it had, and return it
+    pub async fn issue(&self, wallet: &str) -> Result<String> {
+        let pin = new_pin();
+        let mut bytes = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let salt = hex::encode(bytes);
+        let hash = hex::encode(pin_mac(&salt, &pin).finalize().into_bytes());
+
+        sqlx::query(
+            r#"
+            INSERT INTO miner_pins (wallet_address, salt, pin_hash, created_at)
+            VALUES ($1, $2, $3, NOW())
+            ON CONFLICT (wallet_address) DO UPDATE
+            SET salt = EXCLUDED.salt, pin_hash = EXCLUDED.pin_hash, created_at = EXCLUDED.created_at
+            "#
+        )
+        .bind(wallet)
+        .bind(&salt)
+        .bind(&hash)
+        .execute(&self.pool)
+        .await?;
+        Ok(pin)
+    }
+
+    /// Check a wallet's PIN. Wrong PINs count towards locking out the caller
+    /// and the wallet, so PINs cannot be guessed.
+    pub async fn check(&self, wallet: &str, pin: &str, caller: IpAddr) -> Result<PinCheck> {
+        let now = Instant::now();
+        let locked = |until: Instant| PinCheck::Locked(Utc::now() + (until - now));
+        if let Some(until) = self.failures.locked_until(caller, now) {
+            return Ok(locked(until));
+        }
+        if let Some(until) = self.wallet_locked_until(wallet).await? {
+            return Ok(PinCheck::Locked(until));
+        }
+
+        let row: Option<(String, String)> = sqlx::query_as(
+            "SELECT salt, pin_hash FROM miner_pins WHERE wallet_address = $1"
+        )
+        .bind(wallet)
+        .fetch_optional(&self.pool)
+        .await?;
+        let valid = row.is_some_and(|(salt, hash)| {
+            hex::decode(&hash).is_ok_and(|hash| pin_mac(&salt, pin).verify_slice(&hash).is_ok())
+        });
+
+        if valid {
+            self.failures.clear(caller);
+            sqlx::query("DELETE FROM miner_pin_failures WHERE wallet_address = $1")
+                .bind(wallet)
+                .execute(&self.pool)
+                .await?;
+            return Ok(PinCheck::Valid);
+        }
+
+        self.record_wallet_failure(wallet).await?;
+        Ok(match self.failures.fail(caller, now) {
+            Some(until) => locked(until),
+            None => PinCheck::Wrong,
+        })
+    }
+
+    /// When a wallet with too many recent wrong PINs may be tried again
+    async fn wallet_locked_until(&self, wallet: &str) -> Result<Option<DateTime<Utc>>> {
+        let until = sqlx::query_scalar(
+            r#"
+            SELECT MIN(failed_at) + make_interval(secs => $2)
+            FROM (
+                SELECT failed_at FROM miner_pin_failures
+                WHERE wallet_address = $1 AND failed_at > NOW() - make_interval(secs => $2)
+                ORDER BY failed_at DESC
+                LIMIT $3
+            ) recent
+            HAVING COUNT(*) >= $3
+            "#
+        )
+        .bind(wallet)
+        .bind(PIN_LOCKOUT_SECS as f64)
+        .bind(MAX_WALLET_PIN_ATTEMPTS)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(until)
+    }
+
+    /// Remember a wrong PIN for a wallet, forgetting those past the window
+    async fn record_wallet_failure(&self, wallet: &str) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM miner_pin_failures WHERE failed_at <= NOW() - make_interval(secs => $1)")
+            .bind(PIN_LOCKOUT_SECS as f64)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO miner_pin_failures (wallet_address) VALUES ($1)")
+            .bind(wallet)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Remove a wallet's PIN, so nothing about its payouts can be changed
+    /// until it is issued a new one. Returns false when it had none.
+    pub async fn reset(&self, wallet: &str) -> Result<bool> {
+        let reset = sqlx::query("DELETE FROM miner_pins WHERE wallet_address = $1")
+            .bind(wallet)
+            .execute(&self.pool)
+            .await?;
+        Ok(reset.rows_affected() > 0)
+    }
+}
+
+/// Middleware for miner-changing routes: the request must carry the PIN of
+/// the wallet in its path. Everything else about a miner stays readable
+/// without one.
+pub async fn require_miner_pin(
+    State(state): State<AppState>,
+    ConnectInfo(remote): ConnectInfo<SocketAddr>,
+    Path(params): Path<HashMap<String, String>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, (StatusCode, Json<ApiError>)> {
+    let wallet = params.get("wallet").map(String::as_str).unwrap_or_default();
+    let Some(pin) = request.headers().get(PIN_HEADER).and_then(|v| v.to_str().ok()) else {
+        return Err(refuse(StatusCode::UNAUTHORIZED, "missing X-DefPool-Pin header"));
+    };
+
+    match state.miner_pins.check(wallet, pin, remote.ip()).await {
+        Ok(PinCheck::Valid) => {
+            debug!("Authenticated miner {} for {} {}", wallet, request.method(), request.uri().path());
+            Ok(next.run(request).await)
+        }
+        Ok(PinCheck::Wrong) => {
+            warn!("Wrong PIN for miner {} from {}", wallet, remote);
+            Err(refuse(
+                StatusCode::UNAUTHORIZED,
+                "wrong PIN; PINs are issued by the pool operator",
+            ))
+        }
+        Ok(PinCheck::Locked(until)) => {
+            info!("PINs for miner {} from {} locked out until {}", wallet, remote.ip(), until);
+            Err(refuse(
+                StatusCode::TOO_MANY_REQUESTS,
+                format!("too many wrong PINs; try again after {}", until.to_rfc3339()),
+            ))
+        }
+        Err(e) => {
+            warn!("Failed to check PIN of miner {}: {}", wallet, e);
+            Err(refuse(StatusCode::INTERNAL_SERVER_ERROR, "failed to check PIN"))
+        }
+    }
+}
+
+/// Middleware for internal routes: only requests signed by a known,
+/// unrevoked proxy get through. Keys are looked up per request, so a
+/// revocation applies to the next request.
+pub async fn require_proxy(
+    State(state): State<AppState>,
+    ConnectInfo(remote): ConnectInfo<SocketAddr>,
+    request: Request,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let (parts, body) = request.into_parts();
+    let body = to_bytes(body, MAX_BODY_BYTES).await.map_err(|_| StatusCode::PAYLOAD_TOO_LARGE)?;
+
+    let proxy = parts.headers.get(PROXY_HEADER).and_then(|v| v.to_str().ok()).unwrap_or("");
+    let secret = match state.proxy_keys.active_secret(proxy).await {
+        Ok(secret) => secret,
+        Err(e) => {
+            warn!("Failed to look up proxy key: {}", e);
+            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let verdict = match secret {
+        Some(secret) => verify(&secret, &parts, &body, Utc::now().timestamp()),
+        None if proxy.is_empty() => Err("unsigned request"),
+        None => Err("unknown or revoked proxy"),
+    };
+    if let Err(reason) = verdict {
+        warn!(
+            "Rejected {} {} from {} (proxy {:?}): {}",
+            parts.method, parts.uri.path(), remote, proxy, reason
+        );
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+
+    debug!("Authenticated proxy {} for {} {}", proxy, parts.method, parts.uri.path());
+    Ok(next.run(Request::from_parts(parts, Body::from(body))).await)
+}
+
+/// Check a request's timestamp and signature against the proxy's secret
+fn verify(secret: &str, parts: &Parts, body: &[u8], now: i64) -> Result<(), &'static str> {
+    let header = |name| parts.headers.get(name).and_then(|v| v.to_str().ok());
+
+    let timestamp: i64 = header(TIMESTAMP_HEADER)
+        .and_then(|v| v.parse().ok())
+        .ok_or("missing timestamp")?;
+    if (now - timestamp).abs() > MAX_CLOCK_SKEW_SECS {
+        return Err("timestamp outside allowed clock skew");
+    }
+
+    let signature = header(SIGNATURE_HEADER)
+        .and_then(|signature| hex::decode(signature).ok())
+        .ok_or("missing signature")?;
+    signer(secret, timestamp, parts.method.as_str(), parts.uri.path(), body)
+        .verify_slice(&signature)
+        .map_err(|_| "bad signature")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signed_parts(signature: &str, timestamp: i64) -> Parts {
+        let request = axum::http::Request::post("/api/v1/shares/batch")
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(SIGNATURE_HEADER, signature)
+            .body(())
+            .unwrap();
+        request.into_parts().0
+    }
+
+    #[test]
+    fn test_signature() {
+        // Same vector as the proxy's signing test
+        let signature = hex::encode(
+            signer("test-secret", 1_700_000_000, "POST", "/api/v1/shares/batch", b"[]").finalize().into_bytes(),
+        );
+        assert_eq!(signature, "a901f28e9089988022c50ab92f4abacd80a4241b42475217915248efe39b5121");
+
+        let parts = signed_parts(&signature, 1_700_000_000);
+        assert!(verify("test-secret", &parts, b"[]", 1_700_000_100).is_ok());
+        assert_eq!(verify("other-secret", &parts, b"[]", 1_700_000_100), Err("bad signature"));
+        assert_eq!(verify("test-secret", &parts, b"[{}]", 1_700_000_100), Err("bad signature"));
+        assert_eq!(
+            verify("test-secret", &parts, b"[]", 1_700_001_000),
+            Err("timestamp outside allowed clock skew")
+        );
+    }
+
+    #[test]
+    fn test_pin_form_and_hash() {
+        let pin = new_pin();
+        assert_eq!(pin.len(), PIN_DIGITS);
+        assert!(pin.bytes().all(|b| b.is_ascii_digit()));
+
+        let hash = pin_mac("salt", "482913").finalize().into_bytes();
+        assert!(pin_mac("salt", "482913").verify_slice(&hash).is_ok());
+        assert!(pin_mac("salt", "482914").verify_slice(&hash).is_err());
+        assert!(pin_mac("other-salt", "482913").verify_slice(&hash).is_err());
+    }
+
+    #[test]
+    fn test_wrong_pins_lock_out_the_caller() {
+        let failures = PinFailures::default();
+        let guesser: IpAddr = "203.0.113.7".parse().unwrap();
+        let miner: IpAddr = "198.51.100.2".parse().unwrap();
+        let start = Instant::now();
+
+        for _ in 1..MAX_PIN_ATTEMPTS {
+            assert_eq!(failures.fail(guesser, start), None);
+        }
+        let until = failures.fail(guesser, start).unwrap();
+
+        assert_eq!(failures.locked_until(guesser, start), Some(until));
+        assert_eq!(failures.locked_until(miner, start), None);
+        assert_eq!(failures.locked_until(guesser, until), None);
+
+        failures.fail(miner, start);
+        failures.clear(miner);
+        assert!(!failures.callers.lock().unwrap().contains_key(&miner));
+    }
+}
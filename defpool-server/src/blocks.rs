@@ -0,0 +1,344 @@
+use crate::config::{BlocksConfig, Config, MiningTarget, TargetType};
+use crate::daemon::rpc_client::DaemonRpcClient;
+use crate::db::models::{Block, BlockReport};
+use crate::ledger::{self, Account, EntryKind, Transfer};
+use crate::money::Decimal;
+use crate::payout::{BalanceCalculator, Reward, RewardSource};
+use anyhow::{Context, Result};
+use chrono::Utc;
+use sqlx::{FromRow, PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{debug, info, warn};
+
+/// A block still waiting for confirmations
+#[derive(Debug, FromRow)]
+struct PendingBlock {
+    id: i64,
+    coin: String,
+    target_name: String,
+    height: i64,
+    hash: String,
+}
+
+/// Tracks blocks found on daemon targets.
+///
+/// A candidate block's reward is distributed right away, but credited to
+/// miners' `pending_balance`. The block is then checked against its daemon
+/// until it is `confirmations` deep (the coin's maturity) and the credits move
+/// to `balance`, or it is no longer in the main chain and they are dropped.
+pub struct BlockTracker {
+    pool: PgPool,
+    calculator: Arc<BalanceCalculator>,
+    targets: Vec<MiningTarget>,
+    config: BlocksConfig,
+    /// Daemon of each daemon target, by target name
+    daemons: HashMap<String, DaemonRpcClient>,
+}
+
+impl BlockTracker {
+    pub fn new(pool: PgPool, calculator: Arc<BalanceCalculator>, config: &Config) -> Self {
+        let daemons = config
+            .targets
+            .iter()
+            .filter(|t| t.target_type == TargetType::Daemon)
+            .filter_map(|t| {
+                let url = t.daemon_rpc_url.clone()?;
+                let client = DaemonRpcClient::new(url, t.daemon_rpc_user.clone(), t.daemon_rpc_password.clone());
+                Some((t.name.clone(), client))
+            })
+            .collect();
+
+        Self {
+            pool,
+            calculator,
+            targets: config.targets.clone(),
+            config: config.blocks.clone(),
+            daemons,
+        }
+    }
+
+    /// Record a block found on a daemon target and credit its reward as
+    /// pending. Returns false if the block was already recorded.
+    pub async fn record_candidate(&self, report: &BlockReport) -> Result<bool> {
+        let target = self
+            .targets
+            .iter()
+            .find(|t| t.name == report.target_name)
+            .with_context(|| format!("Unknown target {}", report.target_name))?;
+        if !self.daemons.contains_key(&target.name) {
+            anyhow::bail!("Target {} is not a daemon target with an RPC URL", target.name);
+        }
+        if report.height < 0 || report.hash.is_empty() {
+            anyhow::bail!("Block height and hash are required");
+        }
+        if report.network_difficulty.is_nan() || report.network_difficulty <= 0.0 {
+            anyhow::bail!("Network difficulty must be positive, got {}", report.network_difficulty);
+        }
+
+        let coin = target.coin.clone();
+        let target_names: Vec<String> = self
+            .targets
+            .iter()
+            .filter(|t| t.coin == coin)
+            .map(|t| t.name.clone())
+            .collect();
+        let found_at = report.found_at.unwrap_or_else(Utc::now);
+
+        let mut tx = self.pool.begin().await?;
+
+        // The round behind this block: valid shares since the coin's previous block
+        let previous_share_id: i64 = sqlx::query_scalar(
+            "SELECT COALESCE(MAX(last_share_id), 0) FROM blocks WHERE coin = $1"
+        )
+        .bind(&coin)
+        .fetch_one(&mut *tx)
+        .await?;
+        let (round_difficulty, last_share_id): (f64, i64) = sqlx::query_as(
+            r#"
+            SELECT COALESCE(SUM(difficulty), 0)::float8, COALESCE(MAX(id), $2)
+            FROM shares
+            WHERE target_name = ANY($1)
+              AND valid = true
+              AND id > $2
+              AND created_at <= $3
+            "#,
+        )
+        .bind(&target_names)
+        .bind(previous_share_id)
+        .bind(found_at)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let block_id: Option<i64> = sqlx::query_scalar(
+            r#"
+            INSERT INTO blocks (coin, target_name, height, hash, reward, finder_id,
+                                network_difficulty, round_difficulty, last_share_id, found_at)
+            VALUES ($1, $2, $3, $4, $5, (SELECT id FROM miners WHERE wallet_address = $6), $7, $8, $9, $10)
+            ON CONFLICT (coin, hash) DO NOTHING
+            RETURNING id
+            "#,
+        )
+        .bind(&coin)
+        .bind(&target.name)
+        .bind(report.height)
+        .bind(&report.hash)
+        .bind(report.reward)
+        .bind(&report.wallet_address)
+        .bind(report.network_difficulty)
+        .bind(round_difficulty)
+        .bind(last_share_id)
+        .bind(found_at)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(block_id) = block_id else {
+            debug!("{} block {} at height {} already recorded", coin, report.hash, report.height);
+            return Ok(false);
+        };
+
+        let reward = Reward {
+            coin: coin.clone(),
+            amount: report.reward,
+            source: RewardSource::Block,
+            reference: Some(report.hash.clone()),
+            until: found_at,
+            finder: report.wallet_address.clone(),
+            pending: true,
+        };
+        let round = self.calculator.distribute_reward_in(&mut tx, &reward).await?;
+
+        sqlx::query("UPDATE blocks SET round_id = $2 WHERE id = $1")
+            .bind(block_id)
+            .bind(round.round_id)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+
+        info!(
+            "Found {} block {} at height {} (effort {:.1}%), reward {} pending",
+            coin,
+            report.hash,
+            report.height,
+            round_difficulty / report.network_difficulty * 100.0,
+            report.reward
+        );
+        Ok(true)
+    }
+
+    /// Check every pending block against its daemon
+    pub async fn check_pending(&self) -> Result<()> {
+        let pending = sqlx::query_as::<_, PendingBlock>(
+            "SELECT id, coin, target_name, height, hash FROM blocks WHERE status = 'pending' ORDER BY height"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        for block in pending {
+            let Some(daemon) = self.daemons.get(&block.target_name) else {
+                warn!("No daemon configured for target {}, block {} stays pending", block.target_name, block.hash);
+                continue;
+            };
+            if let Err(e) = self.check_block(daemon, &block).await {
+                warn!("Failed to check {} block {}: {:#}", block.coin, block.hash, e);
+            }
+        }
+        Ok(())
+    }
+
+    async fn check_block(&self, daemon: &DaemonRpcClient, block: &PendingBlock) -> Result<()> {
+        let height = u64::try_from(block.height)?;
+        let tip = daemon.get_tip_height(&block.coin).await?;
+        if tip < height {
+            return Ok(());
+        }
+
+        let main_chain_hash = daemon.get_block_hash(&block.coin, height).await?;
+        if !main_chain_hash.eq_ignore_ascii_case(&block.hash) {
+            return self.orphan(block, &main_chain_hash).await;
+        }
+
+        let confirmations = tip - height + 1;
+        if confirmations >= self.config.maturity_for(&block.coin) {
+            return self.confirm(block, confirmations).await;
+        }
+
+        sqlx::query("UPDATE blocks SET confirmations = $2 WHERE id = $1 AND status = 'pending'")
+            .bind(block.id)
+            .bind(confirmations as i64)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Mature a block: its credits move from `pending_balance` to `balance`
+    async fn confirm(&self, block: &PendingBlock, confirmations: u64) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let round_id: Option<Option<i64>> = sqlx::query_scalar(
+            r#"
+            UPDATE blocks SET status = 'confirmed', confirmations = $2, confirmed_at = NOW()
+            WHERE id = $1 AND status = 'pending'
+            RETURNING round_id
+            "#,
+        )
+        .bind(block.id)
+        .bind(confirmations as i64)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(round_id) = round_id else {
+            return Ok(());
+        };
+
+        if let Some(round_id) = round_id {
+            let transfer = maturity(&mut tx, block, round_id).await?;
+            ledger::post(&mut tx, &transfer).await?;
+        }
+
+        tx.commit().await?;
+        info!("{} block {} at height {} matured after {} confirmations", block.coin, block.hash, block.height, confirmations);
+        Ok(())
+    }
+
+    /// Orphan a block that left the main chain: its pending credits, fee and
+    /// refill of the reserve are taken back
+    async fn orphan(&self, block: &PendingBlock, main_chain_hash: &str) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+
+        let round_id: Option<Option<i64>> = sqlx::query_scalar(
+            "UPDATE blocks SET status = 'orphaned' WHERE id = $1 AND status = 'pending' RETURNING round_id"
+        )
+        .bind(block.id)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(round_id) = round_id else {
+            return Ok(());
+        };
+
+        if let Some(round_id) = round_id {
+            let transfer = orphaning(&mut tx, block, round_id).await?;
+            ledger::post(&mut tx, &transfer).await?;
+        }
+
+        sqlx::query(
+            r#"
+            INSERT INTO reserve_entries (coin, amount, round_id)
+            SELECT coin, -amount, round_id FROM reserve_entries WHERE round_id = $1
+            "#,
+        )
+        .bind(round_id)
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        warn!(
+            "{} block {} at height {} orphaned (main chain has {})",
+            block.coin, block.hash, block.height, main_chain_hash
+        );
+        Ok(())
+    }
+
+    /// Most recently found blocks, optionally of one coin
+    pub async fn list_blocks(&self, coin: Option<&str>, limit: i64) -> Result<Vec<Block>> {
+        let blocks = sqlx::query_as::<_, Block>(
+            r#"
+            SELECT id, coin, height, hash, reward,
+                   round_difficulty / network_difficulty AS effort,
+                   status, confirmations, found_at, confirmed_at
+            FROM blocks
+            WHERE $1::text IS NULL OR coin = $1
+            ORDER BY found_at DESC
+            LIMIT $2
+            "#,
+        )
+        .bind(coin)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(blocks)
+    }
+}
+
+/// Ledger transfer moving a matured block's credits from pending to available
+async fn maturity(tx: &mut Transaction<'_, Postgres>, block: &PendingBlock, round_id: i64) -> Result<Transfer> {
+    let mut transfer = Transfer::new(&block.coin)
+        .round(round_id)
+        .memo(format!("block {} matured", block.height));
+    for (miner_id, amount) in round_credits(tx, round_id).await? {
+        transfer = transfer.transfer(Account::Pending(miner_id), Account::Available(miner_id), EntryKind::Maturity, amount);
+    }
+    Ok(transfer)
+}
+
+/// Ledger transfer taking an orphaned block's credits, fee and reserve refill
+/// back to the rewards account
+async fn orphaning(tx: &mut Transaction<'_, Postgres>, block: &PendingBlock, round_id: i64) -> Result<Transfer> {
+    let (fee, reserve): (Decimal, Decimal) = sqlx::query_as(
+        r#"
+        SELECT r.fee, COALESCE((SELECT SUM(amount) FROM reserve_entries WHERE round_id = r.id), 0)
+        FROM rounds r WHERE r.id = $1
+        "#,
+    )
+    .bind(round_id)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    let mut transfer = Transfer::new(&block.coin)
+        .round(round_id)
+        .memo(format!("block {} orphaned", block.height))
+        .transfer(Account::Fees, Account::Rewards, EntryKind::Fee, fee)
+        .transfer(Account::Reserve, Account::Rewards, EntryKind::Reserve, reserve);
+    for (miner_id, amount) in round_credits(tx, round_id).await? {
+        transfer = transfer.transfer(Account::Pending(miner_id), Account::Rewards, EntryKind::Debit, amount);
+    }
+    Ok(transfer)
+}
+
+/// Each miner's credit in a round
+async fn round_credits(tx: &mut Transaction<'_, Postgres>, round_id: i64) -> Result<Vec<(i32, Decimal)>> {
+    let credits = sqlx::query_as("SELECT miner_id, amount FROM round_credits WHERE round_id = $1 ORDER BY miner_id")
+        .bind(round_id)
+        .fetch_all(&mut **tx)
+        .await?;
+    Ok(credits)
+}
@@ -1,4 +1,7 @@
+use crate::money::Decimal;
+use crate::payout::RewardScheme;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::net::SocketAddr;
 
 #[derive(Debug, Deserialize, Clone, PartialEq)]
@@ -8,27 +11,347 @@ pub enum TargetType {
     Daemon,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Deserialize, Clone)]
 pub struct MiningTarget {
     pub name: String,
     #[serde(rename = "type")]
-    #[allow(dead_code)] // Will be used for daemon vs pool logic
     pub target_type: TargetType,
     pub address: String,
+    /// Fallback endpoints, tried in order when `address` is unreachable
+    #[serde(default)]
+    pub backup_addresses: Vec<String>,
     pub coin: String,
     #[allow(dead_code)] // Will be used for multi-algorithm support
     pub algorithm: String,
-    #[allow(dead_code)] // Will be used for daemon RPC calls
+    /// JSON-RPC endpoint of a daemon target, polled for block confirmations
     pub daemon_rpc_url: Option<String>,
+    pub daemon_rpc_user: Option<String>,
+    pub daemon_rpc_password: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Clone)]
+// Keep the daemon RPC password out of logs
+impl std::fmt::Debug for MiningTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MiningTarget")
+            .field("name", &self.name)
+            .field("target_type", &self.target_type)
+            .field("address", &self.address)
+            .field("backup_addresses", &self.backup_addresses)
+            .field("coin", &self.coin)
+            .field("algorithm", &self.algorithm)
+            .field("daemon_rpc_url", &self.daemon_rpc_url)
+            .field("daemon_rpc_user", &self.daemon_rpc_user)
+            .field("daemon_rpc_password", &self.daemon_rpc_password.as_ref().map(|_| "<redacted>"))
+            .finish()
+    }
+}
+
+impl MiningTarget {
+    /// Primary address followed by the backups
+    pub fn endpoints(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.address).chain(&self.backup_addresses)
+    }
+}
+
+#[derive(Deserialize, Clone)]
 pub struct Config {
     pub listen_address: SocketAddr,
     pub database_url: String,
     pub targets: Vec<MiningTarget>,
     pub profitability_check_interval_secs: u64,
     pub switch_threshold_percent: f64,
+    #[serde(default = "default_health_check_interval")]
+    pub health_check_interval_secs: u64,
+    #[serde(default = "default_health_check_timeout")]
+    pub health_check_timeout_secs: u64,
+    /// Share of every reward kept by the pool
+    #[serde(default = "default_pool_fee_percent")]
+    pub pool_fee_percent: Decimal,
+    #[serde(default)]
+    pub pplns: PplnsConfig,
+    #[serde(default)]
+    pub reward_schemes: RewardSchemesConfig,
+    #[serde(default)]
+    pub blocks: BlocksConfig,
+    /// Hot wallet payouts of each coin are sent from; coins without one are not paid out
+    #[serde(default)]
+    pub payout_wallets: HashMap<String, WalletConfig>,
+    #[serde(default)]
+    pub payouts: PayoutsConfig,
+    #[serde(default)]
+    pub addresses: AddressesConfig,
+    /// Exchange account auto-exchange miners' coins are sold on; without one
+    /// every coin is paid out as mined
+    pub exchange: Option<ExchangeConfig>,
+}
+
+// The database URL carries its password; keep it out of logs
+impl std::fmt::Debug for Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Config")
+            .field("listen_address", &self.listen_address)
+            .field("database_url", &"<redacted>")
+            .field("targets", &self.targets)
+            .field("profitability_check_interval_secs", &self.profitability_check_interval_secs)
+            .field("switch_threshold_percent", &self.switch_threshold_percent)
+            .field("health_check_interval_secs", &self.health_check_interval_secs)
+            .field("health_check_timeout_secs", &self.health_check_timeout_secs)
+            .field("pool_fee_percent", &self.pool_fee_percent)
+            .field("pplns", &self.pplns)
+            .field("reward_schemes", &self.reward_schemes)
+            .field("blocks", &self.blocks)
+            .field("payout_wallets", &self.payout_wallets)
+            .field("payouts", &self.payouts)
+            .field("addresses", &self.addresses)
+            .field("exchange", &self.exchange)
+            .finish()
+    }
+}
+
+/// PPLNS reward distribution settings
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct PplnsConfig {
+    /// Number of most recent valid shares a reward is split over
+    pub window_shares: i64,
+}
+
+impl Default for PplnsConfig {
+    fn default() -> Self {
+        Self { window_shares: 100_000 }
+    }
+}
+
+/// Which reward scheme pays miners of each coin
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct RewardSchemesConfig {
+    /// Scheme for coins not listed in `coins`
+    pub default: RewardScheme,
+    /// Per-coin scheme, e.g. `DOGE = "pps"`
+    pub coins: HashMap<String, RewardScheme>,
+    /// Fee of PPS and FPPS, which carry the pool's variance (replaces `pool_fee_percent`)
+    pub pps_fee_percent: Decimal,
+    /// How often PPS and FPPS shares are credited
+    pub pps_interval_secs: u64,
+}
+
+impl Default for RewardSchemesConfig {
+    fn default() -> Self {
+        Self {
+            default: RewardScheme::Pplns,
+            coins: HashMap::new(),
+            pps_fee_percent: Decimal::from(3),
+            pps_interval_secs: 60,
+        }
+    }
+}
+
+impl RewardSchemesConfig {
+    /// Scheme configured for a coin
+    pub fn for_coin(&self, coin: &str) -> RewardScheme {
+        self.coins.get(coin).copied().unwrap_or(self.default)
+    }
+}
+
+/// Found block tracking
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct BlocksConfig {
+    /// How often pending blocks are checked against their daemon
+    pub poll_interval_secs: u64,
+    /// Confirmations before a block's reward can be paid out, per coin
+    pub maturity: HashMap<String, u64>,
+}
+
+impl Default for BlocksConfig {
+    fn default() -> Self {
+        Self {
+            poll_interval_secs: 60,
+            maturity: HashMap::new(),
+        }
+    }
+}
+
+impl BlocksConfig {
+    /// Maturity depth of a coin: configured, or the coin's coinbase maturity
+    pub fn maturity_for(&self, coin: &str) -> u64 {
+        self.maturity.get(coin).copied().unwrap_or(match coin {
+            "XMR" => 60,
+            "DOGE" => 240,
+            _ => 100, // Bitcoin-derived coins such as LTC
+        })
+    }
+}
+
+/// When automatic payouts of a coin are created
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PayoutSchedule {
+    /// On every scheduler run
+    #[default]
+    Continuous,
+    /// Once per UTC hour
+    Hourly,
+    /// Once per UTC day
+    Daily,
+}
+
+/// Automatic threshold-triggered payouts
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct PayoutsConfig {
+    /// How often balances are scanned for miners over their threshold
+    pub check_interval_secs: u64,
+    /// Threshold of miners without payout settings, per coin; coins not listed
+    /// only pay miners who set a threshold
+    pub default_threshold: HashMap<String, Decimal>,
+    /// Per-coin schedule, e.g. `DOGE = "daily"`; unlisted coins are continuous
+    pub schedule: HashMap<String, PayoutSchedule>,
+    /// Confirmations before a broadcast payout is confirmed
+    pub confirmations: u64,
+}
+
+impl Default for PayoutsConfig {
+    fn default() -> Self {
+        Self {
+            check_interval_secs: 300,
+            default_threshold: HashMap::new(),
+            schedule: HashMap::new(),
+            confirmations: 10,
+        }
+    }
+}
+
+impl PayoutsConfig {
+    pub fn schedule_for(&self, coin: &str) -> PayoutSchedule {
+        self.schedule.get(coin).copied().unwrap_or_default()
+    }
+}
+
+/// Wallet address validation settings
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct AddressesConfig {
+    /// Also ask each Bitcoin-style coin's daemon target to `validateaddress`
+    pub daemon_check: bool,
+}
+
+/// RPC flavor of a payout wallet
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WalletKind {
+    /// monero-wallet-rpc
+    Monero,
+    /// Bitcoin Core style wallet (LTC, DOGE)
+    Bitcoin,
+}
+
+impl WalletKind {
+    /// Destinations per transaction when not configured: monero transactions
+    /// take at most 16 outputs, one of them the change
+    pub fn max_batch(&self) -> usize {
+        match self {
+            Self::Monero => 15,
+            Self::Bitcoin => 100,
+        }
+    }
+}
+
+/// A wallet RPC endpoint payouts are sent from
+#[derive(Deserialize, Clone)]
+pub struct WalletConfig {
+    pub kind: WalletKind,
+    pub rpc_url: String,
+    pub rpc_user: Option<String>,
+    pub rpc_password: Option<String>,
+    /// Most payouts sent in one transaction
+    pub max_batch: Option<usize>,
+}
+
+// Keep the RPC password out of logs
+impl std::fmt::Debug for WalletConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WalletConfig")
+            .field("kind", &self.kind)
+            .field("rpc_url", &self.rpc_url)
+            .field("rpc_user", &self.rpc_user)
+            .field("rpc_password", &self.rpc_password.as_ref().map(|_| "<redacted>"))
+            .field("max_batch", &self.max_batch)
+            .finish()
+    }
+}
+
+/// Exchange API flavor
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExchangeKind {
+    Kraken,
+}
+
+/// An exchange account mined coins are sold on
+#[derive(Deserialize, Clone)]
+pub struct ExchangeConfig {
+    pub kind: ExchangeKind,
+    pub api_key: String,
+    /// Base64 API secret
+    pub api_secret: String,
+    /// Overrides the exchange's public API endpoint
+    pub api_url: Option<String>,
+    /// Name of the pool wallet saved on the account that proceeds are withdrawn to
+    pub withdraw_key: String,
+    /// Coin sold for and credited to miners
+    #[serde(default = "default_exchange_quote")]
+    pub quote: String,
+    #[serde(default = "default_exchange_interval")]
+    pub interval_secs: u64,
+    /// Smallest volume sold at once, per coin; only coins listed are converted
+    pub min_amount: HashMap<String, Decimal>,
+}
+
+// Keep the API credentials out of logs
+impl std::fmt::Debug for ExchangeConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ExchangeConfig")
+            .field("kind", &self.kind)
+            .field("api_key", &"<redacted>")
+            .field("api_secret", &"<redacted>")
+            .field("api_url", &self.api_url)
+            .field("withdraw_key", &self.withdraw_key)
+            .field("quote", &self.quote)
+            .field("interval_secs", &self.interval_secs)
+            .field("min_amount", &self.min_amount)
+            .finish()
+    }
+}
+
+impl ExchangeConfig {
+    /// Coins sold on the exchange
+    pub fn converted_coins(&self) -> Vec<String> {
+        let mut coins: Vec<String> = self.min_amount.keys().cloned().collect();
+        coins.sort();
+        coins
+    }
+}
+
+fn default_exchange_quote() -> String {
+    "BTC".to_string()
+}
+
+fn default_exchange_interval() -> u64 {
+    600
+}
+
+fn default_health_check_interval() -> u64 {
+    30
+}
+
+fn default_health_check_timeout() -> u64 {
+    5
+}
+
+fn default_pool_fee_percent() -> Decimal {
+    Decimal::ONE
 }
 
 impl Config {
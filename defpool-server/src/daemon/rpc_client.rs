@@ -128,6 +128,30 @@ impl DaemonRpcClient {
             .ok_or_else(|| anyhow::anyhow!("Could not extract block count from daemon response"))
     }
 
+    /// Height of the chain tip
+    pub async fn get_tip_height(&self, coin: &str) -> Result<u64> {
+        match coin {
+            "XMR" => {
+                let result: Value = self.call("get_block_count", vec![]).await?;
+                result
+                    .get("count")
+                    .and_then(|v| v.as_u64())
+                    .map(|count| count.saturating_sub(1))
+                    .ok_or_else(|| anyhow::anyhow!("Could not extract block count from daemon response"))
+            }
+            _ => self.call("getblockcount", vec![]).await,
+        }
+    }
+
+    /// Hash of the main-chain block at a height
+    pub async fn get_block_hash(&self, coin: &str, height: u64) -> Result<String> {
+        let method = match coin {
+            "XMR" => "on_get_block_hash",
+            _ => "getblockhash",
+        };
+        self.call(method, vec![serde_json::json!(height)]).await
+    }
+
     /// Validate address
     pub async fn validate_address(&self, address: &str) -> Result<bool> {
         let params = vec![serde_json::json!(address)];
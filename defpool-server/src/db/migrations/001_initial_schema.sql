@@ -0,0 +1,69 @@
+-- Core share accounting tables
+
+-- Miners, identified by payout wallet
+CREATE TABLE IF NOT EXISTS miners (
+    id SERIAL PRIMARY KEY,
+    wallet_address VARCHAR(128) NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_seen TIMESTAMPTZ,
+    total_shares BIGINT NOT NULL DEFAULT 0,
+    total_valid_shares BIGINT NOT NULL DEFAULT 0,
+    total_invalid_shares BIGINT NOT NULL DEFAULT 0
+);
+
+-- Workers (rigs) of a miner
+CREATE TABLE IF NOT EXISTS workers (
+    id SERIAL PRIMARY KEY,
+    miner_id INTEGER NOT NULL REFERENCES miners(id) ON DELETE CASCADE,
+    worker_name VARCHAR(128) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_seen TIMESTAMPTZ,
+    hashrate DOUBLE PRECISION NOT NULL DEFAULT 0,
+    total_shares BIGINT NOT NULL DEFAULT 0,
+    UNIQUE(miner_id, worker_name)
+);
+
+-- Shares submitted through the proxies
+CREATE TABLE IF NOT EXISTS shares (
+    id BIGSERIAL PRIMARY KEY,
+    miner_id INTEGER NOT NULL REFERENCES miners(id) ON DELETE CASCADE,
+    worker_id INTEGER REFERENCES workers(id) ON DELETE SET NULL,
+    target_name VARCHAR(64) NOT NULL,
+    difficulty DOUBLE PRECISION NOT NULL,
+    valid BOOLEAN NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Indexes for performance
+CREATE INDEX IF NOT EXISTS idx_workers_miner_id ON workers(miner_id);
+CREATE INDEX IF NOT EXISTS idx_shares_miner_created ON shares(miner_id, created_at);
+CREATE INDEX IF NOT EXISTS idx_shares_created_at ON shares(created_at);
+CREATE INDEX IF NOT EXISTS idx_shares_target_name ON shares(target_name);
+
+-- Trigger to keep miner and worker counters current
+CREATE OR REPLACE FUNCTION update_share_counters()
+RETURNS TRIGGER AS $$
+BEGIN
+    UPDATE miners SET
+        last_seen = NEW.created_at,
+        total_shares = total_shares + 1,
+        total_valid_shares = total_valid_shares + CASE WHEN NEW.valid THEN 1 ELSE 0 END,
+        total_invalid_shares = total_invalid_shares + CASE WHEN NEW.valid THEN 0 ELSE 1 END
+    WHERE id = NEW.miner_id;
+
+    IF NEW.worker_id IS NOT NULL THEN
+        UPDATE workers SET
+            last_seen = NEW.created_at,
+            total_shares = total_shares + 1
+        WHERE id = NEW.worker_id;
+    END IF;
+
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+DROP TRIGGER IF EXISTS share_update_counters ON shares;
+CREATE TRIGGER share_update_counters
+    AFTER INSERT ON shares
+    FOR EACH ROW
+    EXECUTE FUNCTION update_share_counters();
@@ -51,6 +51,7 @@ BEGIN
 END;
 $$ LANGUAGE plpgsql;
 
+DROP TRIGGER IF EXISTS balance_update_timestamp ON balances;
 CREATE TRIGGER balance_update_timestamp
     BEFORE UPDATE ON balances
     FOR EACH ROW
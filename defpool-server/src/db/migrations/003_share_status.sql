@@ -0,0 +1,8 @@
+-- Upstream pool verdict per share
+
+ALTER TABLE shares ADD COLUMN IF NOT EXISTS status VARCHAR(20) NOT NULL DEFAULT 'accepted'; -- accepted, rejected, stale, duplicate
+ALTER TABLE shares ADD COLUMN IF NOT EXISTS error_message TEXT;
+
+UPDATE shares SET status = 'rejected' WHERE valid = false AND status = 'accepted';
+
+CREATE INDEX IF NOT EXISTS idx_shares_status ON shares(status);
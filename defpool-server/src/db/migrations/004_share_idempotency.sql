@@ -0,0 +1,5 @@
+-- Idempotency key the proxy assigns each share, so retried deliveries are recorded once
+
+ALTER TABLE shares ADD COLUMN IF NOT EXISTS share_id VARCHAR(64);
+
+CREATE UNIQUE INDEX IF NOT EXISTS idx_shares_share_id ON shares(share_id);
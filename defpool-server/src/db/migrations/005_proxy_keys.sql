@@ -0,0 +1,9 @@
+-- Shared secrets proxies sign internal API requests with
+
+CREATE TABLE IF NOT EXISTS proxy_keys (
+    id SERIAL PRIMARY KEY,
+    name VARCHAR(64) NOT NULL UNIQUE,
+    secret VARCHAR(128) NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    revoked_at TIMESTAMPTZ
+);
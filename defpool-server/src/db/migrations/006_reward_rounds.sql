@@ -0,0 +1,29 @@
+-- Reward rounds: every reward distributed to miners, with the share window it was split over
+
+CREATE TABLE IF NOT EXISTS rounds (
+    id BIGSERIAL PRIMARY KEY,
+    coin VARCHAR(10) NOT NULL,
+    scheme VARCHAR(20) NOT NULL, -- pplns
+    source VARCHAR(20) NOT NULL, -- block, pool_payout
+    reference VARCHAR(128), -- block hash or payout transaction
+    reward DECIMAL(20, 8) NOT NULL,
+    fee DECIMAL(20, 8) NOT NULL,
+    window_first_share_id BIGINT,
+    window_last_share_id BIGINT,
+    window_shares BIGINT NOT NULL,
+    window_difficulty DOUBLE PRECISION NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE(coin, source, reference)
+);
+
+-- Each miner's part of a round
+CREATE TABLE IF NOT EXISTS round_credits (
+    round_id BIGINT NOT NULL REFERENCES rounds(id) ON DELETE CASCADE,
+    miner_id INTEGER NOT NULL REFERENCES miners(id) ON DELETE CASCADE,
+    difficulty DOUBLE PRECISION NOT NULL,
+    amount DECIMAL(20, 8) NOT NULL,
+    PRIMARY KEY (round_id, miner_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_rounds_coin_created ON rounds(coin, created_at);
+CREATE INDEX IF NOT EXISTS idx_round_credits_miner_id ON round_credits(miner_id);
@@ -0,0 +1,23 @@
+-- Reward schemes: per-miner scheme overrides and the reserve that backs PPS and FPPS
+
+-- Scheme a miner chose over their coin's scheme (pps, fpps or solo); NULL follows the coin
+ALTER TABLE payout_settings ADD COLUMN IF NOT EXISTS reward_scheme VARCHAR(10);
+
+-- Pool reserve movements: PPS and FPPS payments draw it down, the per-share
+-- miners' part of rewards refills it. Its sum per coin is the pool's exposure.
+CREATE TABLE IF NOT EXISTS reserve_entries (
+    id BIGSERIAL PRIMARY KEY,
+    coin VARCHAR(10) NOT NULL,
+    amount DECIMAL(20, 8) NOT NULL, -- negative for payments to miners
+    round_id BIGINT NOT NULL REFERENCES rounds(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Last share paid per share, per coin
+CREATE TABLE IF NOT EXISTS share_cursors (
+    coin VARCHAR(10) PRIMARY KEY,
+    last_share_id BIGINT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_reserve_entries_coin ON reserve_entries(coin);
@@ -0,0 +1,23 @@
+-- Blocks found on daemon targets, tracked until they mature or are orphaned
+
+CREATE TABLE IF NOT EXISTS blocks (
+    id BIGSERIAL PRIMARY KEY,
+    coin VARCHAR(10) NOT NULL,
+    target_name VARCHAR(64) NOT NULL,
+    height BIGINT NOT NULL,
+    hash VARCHAR(128) NOT NULL,
+    reward DECIMAL(20, 8) NOT NULL,
+    finder_id INTEGER REFERENCES miners(id) ON DELETE SET NULL,
+    network_difficulty DOUBLE PRECISION NOT NULL,
+    round_difficulty DOUBLE PRECISION NOT NULL, -- valid share difficulty since the coin's previous block
+    last_share_id BIGINT NOT NULL, -- last share counted in round_difficulty
+    status VARCHAR(20) NOT NULL DEFAULT 'pending', -- pending, confirmed, orphaned
+    confirmations BIGINT NOT NULL DEFAULT 0,
+    round_id BIGINT REFERENCES rounds(id),
+    found_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    confirmed_at TIMESTAMPTZ,
+    UNIQUE(coin, hash)
+);
+
+CREATE INDEX IF NOT EXISTS idx_blocks_status ON blocks(status);
+CREATE INDEX IF NOT EXISTS idx_blocks_coin_found ON blocks(coin, found_at);
@@ -0,0 +1,26 @@
+-- Exact amounts: room for 12 decimal places (XMR piconero) in every amount column,
+-- and zoned timestamps on the money tables so they map to UTC times
+
+ALTER TABLE balances
+    ALTER COLUMN balance TYPE NUMERIC(32, 12),
+    ALTER COLUMN pending_balance TYPE NUMERIC(32, 12),
+    ALTER COLUMN total_paid TYPE NUMERIC(32, 12),
+    ALTER COLUMN updated_at TYPE TIMESTAMPTZ USING updated_at AT TIME ZONE 'UTC';
+
+ALTER TABLE payouts
+    ALTER COLUMN amount TYPE NUMERIC(32, 12),
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ USING created_at AT TIME ZONE 'UTC',
+    ALTER COLUMN completed_at TYPE TIMESTAMPTZ USING completed_at AT TIME ZONE 'UTC';
+
+ALTER TABLE payout_settings
+    ALTER COLUMN min_payout_threshold TYPE NUMERIC(32, 12),
+    ALTER COLUMN created_at TYPE TIMESTAMPTZ USING created_at AT TIME ZONE 'UTC',
+    ALTER COLUMN updated_at TYPE TIMESTAMPTZ USING updated_at AT TIME ZONE 'UTC';
+
+ALTER TABLE rounds
+    ALTER COLUMN reward TYPE NUMERIC(32, 12),
+    ALTER COLUMN fee TYPE NUMERIC(32, 12);
+
+ALTER TABLE round_credits ALTER COLUMN amount TYPE NUMERIC(32, 12);
+ALTER TABLE reserve_entries ALTER COLUMN amount TYPE NUMERIC(32, 12);
+ALTER TABLE blocks ALTER COLUMN reward TYPE NUMERIC(32, 12);
@@ -0,0 +1,60 @@
+-- Double-entry ledger: every balance change is a transaction whose entries add up to zero.
+-- Miner accounts (available, pending, paid) mirror the columns of balances.
+
+CREATE TABLE IF NOT EXISTS ledger_transactions (
+    id BIGSERIAL PRIMARY KEY,
+    coin VARCHAR(10) NOT NULL,
+    memo TEXT,
+    round_id BIGINT REFERENCES rounds(id),
+    payout_id BIGINT REFERENCES payouts(id),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS ledger_entries (
+    id BIGSERIAL PRIMARY KEY,
+    transaction_id BIGINT NOT NULL REFERENCES ledger_transactions(id),
+    account VARCHAR(20) NOT NULL, -- available, pending, paid, rewards, fees, reserve, adjustments
+    miner_id INTEGER REFERENCES miners(id), -- set on miner accounts only
+    kind VARCHAR(20) NOT NULL, -- credit, debit, fee, reserve, maturity, payout_hold, payout_release, adjustment
+    amount NUMERIC(32, 12) NOT NULL,
+    CHECK ((miner_id IS NOT NULL) = (account IN ('available', 'pending', 'paid')))
+);
+
+CREATE INDEX IF NOT EXISTS idx_ledger_transactions_round_id ON ledger_transactions(round_id);
+CREATE INDEX IF NOT EXISTS idx_ledger_transactions_payout_id ON ledger_transactions(payout_id);
+CREATE INDEX IF NOT EXISTS idx_ledger_entries_transaction_id ON ledger_entries(transaction_id);
+CREATE INDEX IF NOT EXISTS idx_ledger_entries_miner_id ON ledger_entries(miner_id, id);
+
+-- The ledger is append-only; mistakes are corrected with new transactions
+CREATE OR REPLACE FUNCTION ledger_append_only() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'ledger rows cannot be changed or deleted';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER ledger_transactions_append_only
+    BEFORE UPDATE OR DELETE ON ledger_transactions
+    FOR EACH ROW EXECUTE FUNCTION ledger_append_only();
+
+CREATE TRIGGER ledger_entries_append_only
+    BEFORE UPDATE OR DELETE ON ledger_entries
+    FOR EACH ROW EXECUTE FUNCTION ledger_append_only();
+
+-- Opening balances: what balances held before the ledger, one transaction per coin
+INSERT INTO ledger_transactions (coin, memo)
+SELECT DISTINCT coin, 'opening balance' FROM balances;
+
+INSERT INTO ledger_entries (transaction_id, account, miner_id, kind, amount)
+SELECT t.id, a.account, b.miner_id, 'adjustment', a.amount
+FROM balances b
+JOIN ledger_transactions t ON t.coin = b.coin AND t.memo = 'opening balance'
+CROSS JOIN LATERAL (
+    VALUES ('available', b.balance), ('pending', b.pending_balance), ('paid', b.total_paid)
+) AS a(account, amount)
+WHERE a.amount <> 0;
+
+INSERT INTO ledger_entries (transaction_id, account, miner_id, kind, amount)
+SELECT transaction_id, 'adjustments', NULL, 'adjustment', -SUM(amount)
+FROM ledger_entries
+GROUP BY transaction_id
+HAVING SUM(amount) <> 0;
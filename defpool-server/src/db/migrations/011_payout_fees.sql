@@ -0,0 +1,3 @@
+-- Network fee the pool paid for each payout, as reported by its wallet.
+-- The ledger posts it from the fees account to network_fees.
+ALTER TABLE payouts ADD COLUMN IF NOT EXISTS fee NUMERIC(32, 12);
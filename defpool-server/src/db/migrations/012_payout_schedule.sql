@@ -0,0 +1,19 @@
+-- Automatic payouts: when each coin was last scheduled, and the pool-wide pause switch
+
+CREATE TABLE IF NOT EXISTS payout_schedule_runs (
+    coin VARCHAR(10) PRIMARY KEY,
+    last_run_at TIMESTAMPTZ -- NULL until the first run
+);
+
+-- Single row; while paused no payouts are created automatically or sent
+CREATE TABLE IF NOT EXISTS payout_control (
+    id BOOLEAN PRIMARY KEY DEFAULT true CHECK (id),
+    paused BOOLEAN NOT NULL DEFAULT false,
+    reason TEXT,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO payout_control (id, paused) VALUES (true, false) ON CONFLICT (id) DO NOTHING;
+
+-- Open payouts of a miner and coin, checked before creating another
+CREATE INDEX IF NOT EXISTS idx_payouts_miner_coin_status ON payouts(miner_id, coin, status);
@@ -0,0 +1,35 @@
+-- Auto-exchange: mined coins of auto_exchange miners pooled, sold for the payout coin
+-- on an exchange and credited back at the achieved rate
+
+CREATE TABLE IF NOT EXISTS conversions (
+    id BIGSERIAL PRIMARY KEY,
+    coin VARCHAR(10) NOT NULL, -- coin sold
+    quote VARCHAR(10) NOT NULL, -- coin bought and credited (BTC)
+    amount NUMERIC(32, 12) NOT NULL, -- volume pooled and sold
+    status VARCHAR(20) NOT NULL DEFAULT 'pooled', -- pooled, depositing, deposited, selling, sold, withdrawn, failed
+    deposit_address TEXT,
+    deposit_tx VARCHAR(128),
+    deposit_fee NUMERIC(32, 12), -- network fee of the deposit, paid by the pool
+    order_id VARCHAR(128),
+    proceeds NUMERIC(32, 12), -- quote coin received after the exchange fee
+    exchange_fee NUMERIC(32, 12),
+    rate NUMERIC(32, 12), -- proceeds per unit sold
+    withdrawal_id VARCHAR(128),
+    error_message TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Each miner's coins in a conversion and the quote coin credited for them
+CREATE TABLE IF NOT EXISTS conversion_contributions (
+    conversion_id BIGINT NOT NULL REFERENCES conversions(id),
+    miner_id INTEGER NOT NULL REFERENCES miners(id),
+    amount NUMERIC(32, 12) NOT NULL,
+    credited NUMERIC(32, 12),
+    PRIMARY KEY (conversion_id, miner_id)
+);
+
+ALTER TABLE ledger_transactions ADD COLUMN IF NOT EXISTS conversion_id BIGINT REFERENCES conversions(id);
+
+CREATE INDEX IF NOT EXISTS idx_conversions_status ON conversions(status);
+CREATE INDEX IF NOT EXISTS idx_conversion_contributions_miner_id ON conversion_contributions(miner_id);
@@ -0,0 +1,26 @@
+-- Payout state machine: pending -> signing -> broadcast -> confirmed, or failed.
+-- A payout is `signing` from the moment a processor claims it until its
+-- transaction is recorded; one whose send cannot be settled from the wallet
+-- goes to `review`, its amount held, for an operator.
+
+ALTER TABLE payouts
+    ADD COLUMN IF NOT EXISTS signing_at TIMESTAMPTZ,
+    ADD COLUMN IF NOT EXISTS broadcast_at TIMESTAMPTZ;
+
+-- Transactions signed for payouts and exchange deposits, recorded before they
+-- are relayed: a relay whose outcome is lost is retried with the very same
+-- transaction, which can only be mined once, instead of a newly signed one.
+CREATE TABLE IF NOT EXISTS wallet_transactions (
+    tx_hash VARCHAR(128) PRIMARY KEY,
+    coin VARCHAR(10) NOT NULL,
+    raw_tx TEXT NOT NULL, -- Monero transaction metadata or Bitcoin raw transaction
+    fee NUMERIC(32, 12), -- network fee, paid by the pool
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Payouts left processing may or may not have been sent, with nothing
+-- recorded to tell: an operator checks the wallet
+UPDATE payouts SET status = 'review', signing_at = created_at,
+    error_message = 'left processing before transactions were recorded, check the wallet'
+WHERE status = 'processing';
+UPDATE payouts SET status = 'confirmed', broadcast_at = completed_at WHERE status = 'completed';
@@ -0,0 +1,20 @@
+-- PINs miners authorize payout settings changes and payout requests with,
+-- issued by an operator once the miner proved they own the wallet.
+
+CREATE TABLE IF NOT EXISTS miner_pins (
+    wallet_address VARCHAR(128) PRIMARY KEY,
+    salt VARCHAR(64) NOT NULL,
+    pin_hash VARCHAR(128) NOT NULL, -- hex HMAC-SHA256 of the PIN keyed with the salt
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Wrong PINs per wallet, whoever sent them, so guesses spread over many
+-- callers still lock the wallet; rows older than the lockout window are pruned
+CREATE TABLE IF NOT EXISTS miner_pin_failures (
+    id BIGSERIAL PRIMARY KEY,
+    wallet_address VARCHAR(128) NOT NULL,
+    failed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_miner_pin_failures_wallet ON miner_pin_failures(wallet_address, failed_at);
+CREATE INDEX IF NOT EXISTS idx_miner_pin_failures_failed_at ON miner_pin_failures(failed_at);
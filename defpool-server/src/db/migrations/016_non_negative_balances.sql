@@ -0,0 +1,5 @@
+-- Every debit of an available balance must be covered by it: a posting that
+-- would overdraw a miner fails and its transaction rolls back.
+
+ALTER TABLE balances
+    ADD CONSTRAINT balances_balance_non_negative CHECK (balance >= 0);
@@ -0,0 +1,5 @@
+-- Address payouts in a miner's payout coin are sent to, when that coin is not
+-- the one their wallet belongs to (e.g. BTC for an auto-exchanging XMR miner).
+-- Mined coins are still paid to the wallet itself.
+
+ALTER TABLE payout_settings ADD COLUMN IF NOT EXISTS payout_address VARCHAR(128);
@@ -1,16 +1,81 @@
 pub mod models;
 pub mod repository;
 
+use sqlx::migrate::Migrator;
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
-use anyhow::Result;
+use anyhow::{Context, Result};
+use tracing::info;
 
-/// Create database connection pool
+/// Schema migrations, embedded at build time
+static MIGRATOR: Migrator = sqlx::migrate!("./src/db/migrations");
+
+/// Create database connection pool and bring the schema up to date
 pub async fn create_pool(database_url: &str) -> Result<PgPool> {
     let pool = PgPoolOptions::new()
         .max_connections(10)
         .connect(database_url)
         .await?;
-    
+
+    migrate(&pool).await?;
+
     Ok(pool)
 }
+
+/// Apply pending migrations, refusing databases migrated by a newer server
+pub async fn migrate(pool: &PgPool) -> Result<()> {
+    check_schema_version(schema_version(pool).await?, latest_version())?;
+
+    MIGRATOR.run(pool).await.context("Failed to run database migrations")?;
+    info!("Database schema at version {}", latest_version());
+    Ok(())
+}
+
+/// Highest applied migration, `None` on a database never migrated by the server
+async fn schema_version(pool: &PgPool) -> Result<Option<i64>> {
+    let table: Option<String> = sqlx::query_scalar("SELECT to_regclass('_sqlx_migrations')::text")
+        .fetch_one(pool)
+        .await?;
+    if table.is_none() {
+        return Ok(None);
+    }
+
+    let version = sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations WHERE success")
+        .fetch_one(pool)
+        .await?;
+    Ok(version)
+}
+
+fn latest_version() -> i64 {
+    MIGRATOR.iter().map(|migration| migration.version).max().unwrap_or(0)
+}
+
+fn check_schema_version(database: Option<i64>, latest: i64) -> Result<()> {
+    match database {
+        Some(version) if version > latest => anyhow::bail!(
+            "Database schema version {} is newer than this server supports ({}); upgrade defpool-server",
+            version,
+            latest
+        ),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schema_version_check() {
+        assert!(check_schema_version(None, 5).is_ok());
+        assert!(check_schema_version(Some(3), 5).is_ok());
+        assert!(check_schema_version(Some(5), 5).is_ok());
+        assert!(check_schema_version(Some(6), 5).is_err());
+    }
+
+    #[test]
+    fn test_migrations_embedded_in_order() {
+        let versions: Vec<i64> = MIGRATOR.iter().map(|migration| migration.version).collect();
+        assert_eq!(versions, (1..=latest_version()).collect::<Vec<_>>());
+    }
+}
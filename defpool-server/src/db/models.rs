@@ -1,3 +1,4 @@
+use crate::money::Decimal;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
@@ -41,9 +42,36 @@ pub struct Share {
     #[allow(dead_code)]
     pub valid: bool,
     #[allow(dead_code)]
+    pub status: String,
+    #[allow(dead_code)]
+    pub error_message: Option<String>,
+    #[allow(dead_code)]
+    pub share_id: Option<String>,
+    #[allow(dead_code)]
     pub created_at: DateTime<Utc>,
 }
 
+/// Upstream pool verdict on a share
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShareStatus {
+    Accepted,
+    Rejected,
+    Stale,
+    Duplicate,
+}
+
+impl ShareStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Accepted => "accepted",
+            Self::Rejected => "rejected",
+            Self::Stale => "stale",
+            Self::Duplicate => "duplicate",
+        }
+    }
+}
+
 /// New share submission (from proxy)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ShareSubmission {
@@ -52,6 +80,46 @@ pub struct ShareSubmission {
     pub target_name: String,
     pub difficulty: f64,
     pub valid: bool,
+    /// Pool verdict; older proxies only send `valid`
+    #[serde(default)]
+    pub status: Option<ShareStatus>,
+    /// Pool error message for shares that were not accepted
+    #[serde(default)]
+    pub error_message: Option<String>,
+    /// Idempotency key from the proxy; a share is recorded once per key
+    #[serde(default)]
+    pub share_id: Option<String>,
+}
+
+impl ShareSubmission {
+    /// Pool verdict, derived from `valid` when the proxy did not send one
+    pub fn status(&self) -> ShareStatus {
+        self.status.unwrap_or(if self.valid {
+            ShareStatus::Accepted
+        } else {
+            ShareStatus::Rejected
+        })
+    }
+}
+
+/// Outcome of one share in a batch submission
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ShareOutcome {
+    Created,
+    /// A share with the same `share_id` was already recorded
+    Duplicate,
+    /// The share was refused and will never be recorded
+    Invalid,
+}
+
+/// Per-share result of a batch submission, in request order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShareBatchResult {
+    pub share_id: Option<String>,
+    pub outcome: ShareOutcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
 }
 
 /// Miner statistics
@@ -72,9 +140,9 @@ pub struct Balance {
     pub id: i32,
     pub miner_id: i32,
     pub coin: String,
-    pub balance: f64,
-    pub pending_balance: f64,
-    pub total_paid: f64,
+    pub balance: Decimal,
+    pub pending_balance: Decimal,
+    pub total_paid: Decimal,
     pub updated_at: DateTime<Utc>,
 }
 
@@ -84,10 +152,16 @@ pub struct Payout {
     pub id: i64,
     pub miner_id: i32,
     pub coin: String,
-    pub amount: f64,
+    pub amount: Decimal,
     pub tx_hash: Option<String>,
+    /// Network fee paid by the pool
+    pub fee: Option<Decimal>,
+    /// pending, signing, review, broadcast, confirmed or failed
     pub status: String,
     pub created_at: DateTime<Utc>,
+    /// When the wallet sent it
+    pub broadcast_at: Option<DateTime<Utc>>,
+    /// When it was confirmed
     pub completed_at: Option<DateTime<Utc>>,
     pub error_message: Option<String>,
 }
@@ -97,17 +171,55 @@ pub struct Payout {
 pub struct PayoutSettings {
     pub id: i32,
     pub miner_id: i32,
-    pub min_payout_threshold: f64,
+    pub min_payout_threshold: Decimal,
     pub payout_coin: String,
+    /// Address payouts in `payout_coin` go to; the wallet itself when unset
+    pub payout_address: Option<String>,
     pub auto_exchange: bool,
+    /// Reward scheme chosen over the coin's scheme
+    pub reward_scheme: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
+/// Found block, as listed by the API
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct Block {
+    pub id: i64,
+    pub coin: String,
+    pub height: i64,
+    pub hash: String,
+    pub reward: Decimal,
+    /// Share difficulty it took to find the block over the network difficulty
+    pub effort: f64,
+    /// pending, confirmed or orphaned
+    pub status: String,
+    pub confirmations: i64,
+    pub found_at: DateTime<Utc>,
+    pub confirmed_at: Option<DateTime<Utc>>,
+}
+
+/// Block found on a daemon target (from proxy)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BlockReport {
+    pub target_name: String,
+    pub height: i64,
+    pub hash: String,
+    /// Coinbase value including fees, in coins
+    pub reward: Decimal,
+    /// Network difficulty the block was mined at
+    pub network_difficulty: f64,
+    /// Wallet of the miner whose share solved the block
+    #[serde(default)]
+    pub wallet_address: Option<String>,
+    #[serde(default)]
+    pub found_at: Option<DateTime<Utc>>,
+}
+
 /// Payout request
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PayoutRequest {
     pub wallet_address: String,
     pub coin: String,
-    pub amount: Option<f64>, // None = pay all available balance
+    pub amount: Option<Decimal>, // None = pay all available balance
 }
@@ -1,7 +1,8 @@
 use super::models::*;
 use sqlx::PgPool;
-use anyhow::Result;
+use anyhow::{Context, Result};
 use chrono::Utc;
+use std::collections::{HashMap, HashSet};
 
 /// Repository for database operations
 pub struct ShareRepository {
@@ -75,19 +76,22 @@ impl ShareRepository {
         Ok(worker)
     }
 
-    /// Record a share
-    pub async fn create_share(&self, submission: &ShareSubmission) -> Result<Share> {
+    /// Record a share. Returns `None` when a share with the same `share_id`
+    /// was already recorded.
+    pub async fn create_share(&self, submission: &ShareSubmission) -> Result<Option<Share>> {
         // Get or create miner
         let miner = self.get_or_create_miner(&submission.wallet_address).await?;
 
         // Get or create worker
         let worker = self.get_or_create_worker(miner.id, &submission.worker_name).await?;
 
-        // Insert share
+        // Insert share; only pool-accepted shares count as valid
+        let status = submission.status();
         let share = sqlx::query_as::<_, Share>(
             r#"
-            INSERT INTO shares (miner_id, worker_id, target_name, difficulty, valid, created_at)
-            VALUES ($1, $2, $3, $4, $5, $6)
+            INSERT INTO shares (miner_id, worker_id, target_name, difficulty, valid, status, error_message, share_id, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ON CONFLICT (share_id) DO NOTHING
             RETURNING *
             "#
         )
@@ -95,14 +99,139 @@ impl ShareRepository {
         .bind(worker.id)
         .bind(&submission.target_name)
         .bind(submission.difficulty)
-        .bind(submission.valid)
+        .bind(status == ShareStatus::Accepted)
+        .bind(status.as_str())
+        .bind(&submission.error_message)
+        .bind(&submission.share_id)
         .bind(Utc::now())
-        .fetch_one(&self.pool)
+        .fetch_optional(&self.pool)
         .await?;
 
         Ok(share)
     }
 
+    /// Record a batch of shares. Miners and workers are resolved as sets and the
+    /// shares written with one multi-row insert, all in a single transaction.
+    /// Returns `Created` or `Duplicate` per submission, in order.
+    pub async fn create_shares(&self, submissions: &[ShareSubmission]) -> Result<Vec<ShareOutcome>> {
+        if submissions.is_empty() {
+            return Ok(Vec::new());
+        }
+        let now = Utc::now();
+        let mut tx = self.pool.begin().await?;
+
+        let mut wallets: Vec<String> = submissions.iter().map(|s| s.wallet_address.clone()).collect();
+        wallets.sort();
+        wallets.dedup();
+
+        sqlx::query(
+            r#"
+            INSERT INTO miners (wallet_address, created_at)
+            SELECT wallet, $2 FROM UNNEST($1::text[]) AS wallet
+            ON CONFLICT (wallet_address) DO NOTHING
+            "#
+        )
+        .bind(&wallets)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+        let miner_ids: HashMap<String, i32> = sqlx::query_as::<_, (String, i32)>(
+            "SELECT wallet_address, id FROM miners WHERE wallet_address = ANY($1)"
+        )
+        .bind(&wallets)
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .collect();
+
+        let share_miners = submissions
+            .iter()
+            .map(|s| miner_ids.get(&s.wallet_address).copied().context("Miner missing after insert"))
+            .collect::<Result<Vec<i32>>>()?;
+
+        let mut worker_keys: Vec<(i32, String)> = share_miners
+            .iter()
+            .zip(submissions)
+            .map(|(miner_id, s)| (*miner_id, s.worker_name.clone()))
+            .collect();
+        worker_keys.sort();
+        worker_keys.dedup();
+        let (worker_miners, worker_names): (Vec<i32>, Vec<String>) = worker_keys.into_iter().unzip();
+
+        sqlx::query(
+            r#"
+            INSERT INTO workers (miner_id, worker_name, created_at)
+            SELECT t.miner_id, t.worker_name, $3
+            FROM UNNEST($1::int[], $2::text[]) AS t(miner_id, worker_name)
+            ON CONFLICT (miner_id, worker_name) DO NOTHING
+            "#
+        )
+        .bind(&worker_miners)
+        .bind(&worker_names)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+
+        let worker_ids: HashMap<(i32, String), i32> = sqlx::query_as::<_, (i32, String, i32)>(
+            r#"
+            SELECT w.miner_id, w.worker_name, w.id
+            FROM workers w
+            JOIN UNNEST($1::int[], $2::text[]) AS t(miner_id, worker_name)
+              ON w.miner_id = t.miner_id AND w.worker_name = t.worker_name
+            "#
+        )
+        .bind(&worker_miners)
+        .bind(&worker_names)
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|(miner_id, worker_name, id)| ((miner_id, worker_name), id))
+        .collect();
+
+        // Only pool-accepted shares count as valid
+        let statuses: Vec<ShareStatus> = submissions.iter().map(|s| s.status()).collect();
+        let share_workers: Vec<Option<i32>> = share_miners
+            .iter()
+            .zip(submissions)
+            .map(|(miner_id, s)| worker_ids.get(&(*miner_id, s.worker_name.clone())).copied())
+            .collect();
+
+        let inserted: Vec<Option<String>> = sqlx::query_scalar(
+            r#"
+            INSERT INTO shares (miner_id, worker_id, target_name, difficulty, valid, status, error_message, share_id, created_at)
+            SELECT t.miner_id, t.worker_id, t.target_name, t.difficulty, t.valid, t.status, t.error_message, t.share_id, $9
+            FROM UNNEST($1::int[], $2::int[], $3::text[], $4::float8[], $5::bool[], $6::text[], $7::text[], $8::text[])
+                AS t(miner_id, worker_id, target_name, difficulty, valid, status, error_message, share_id)
+            ON CONFLICT (share_id) DO NOTHING
+            RETURNING share_id
+            "#
+        )
+        .bind(&share_miners)
+        .bind(&share_workers)
+        .bind(submissions.iter().map(|s| s.target_name.clone()).collect::<Vec<_>>())
+        .bind(submissions.iter().map(|s| s.difficulty).collect::<Vec<_>>())
+        .bind(statuses.iter().map(|status| *status == ShareStatus::Accepted).collect::<Vec<_>>())
+        .bind(statuses.iter().map(|status| status.as_str()).collect::<Vec<_>>())
+        .bind(submissions.iter().map(|s| s.error_message.clone()).collect::<Vec<_>>())
+        .bind(submissions.iter().map(|s| s.share_id.clone()).collect::<Vec<_>>())
+        .bind(now)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        // A share id repeated within the batch is only inserted once
+        let mut created: HashSet<String> = inserted.into_iter().flatten().collect();
+        Ok(submissions
+            .iter()
+            .map(|s| match &s.share_id {
+                Some(share_id) if !created.remove(share_id) => ShareOutcome::Duplicate,
+                _ => ShareOutcome::Created,
+            })
+            .collect())
+    }
+
     /// Get miner statistics
     pub async fn get_miner_stats(&self, wallet_address: &str) -> Result<Option<MinerStats>> {
         let miner = sqlx::query_as::<_, Miner>(
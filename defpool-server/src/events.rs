@@ -0,0 +1,28 @@
+use crate::state::Target;
+use serde::{Deserialize, Serialize};
+
+/// Command pushed from the server to connected proxies
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ProxyEvent {
+    /// Move miners to a new upstream target
+    TargetChanged { target: Target },
+    /// Replace proxy runtime settings
+    ConfigUpdated { default_wallet: Option<String> },
+    /// Stop accepting new miners (existing sessions keep running)
+    Drain { reason: Option<String> },
+    /// Accept new miners again after a drain
+    Resume,
+}
+
+impl ProxyEvent {
+    /// SSE event name
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::TargetChanged { .. } => "target_changed",
+            Self::ConfigUpdated { .. } => "config_updated",
+            Self::Drain { .. } => "drain",
+            Self::Resume => "resume",
+        }
+    }
+}
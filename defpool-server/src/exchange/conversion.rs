@@ -0,0 +1,457 @@
+use super::{Exchange, Order, OrderStatus};
+use crate::config::ExchangeConfig;
+use crate::ledger::{self, Account, EntryKind, Transfer};
+use crate::money::{self, Decimal};
+use crate::payout::sender::{Destination, PayoutSender, SendError};
+use crate::payout::service::record_transaction;
+use anyhow::{Context, Result};
+use sqlx::{FromRow, PgPool};
+use std::collections::HashMap;
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+/// Decimals exchanges take order volumes in; finer remainders stay in the
+/// miners' balances
+const VOLUME_DECIMALS: u32 = 8;
+
+/// A conversion still moving through the exchange
+#[derive(Debug, FromRow)]
+struct OpenConversion {
+    id: i64,
+    coin: String,
+    amount: Decimal,
+    status: String,
+    deposit_tx: Option<String>,
+    order_id: Option<String>,
+    proceeds: Option<Decimal>,
+}
+
+/// What a filled sale credits each miner
+#[derive(Debug, PartialEq)]
+struct Settlement {
+    /// Miner and quote coin credited
+    credits: Vec<(i32, Decimal)>,
+    /// Rounding remainder, kept as a pool fee
+    remainder: Decimal,
+    rate: Decimal,
+}
+
+/// Sells the mined coins of auto-exchange miners for their payout coin.
+///
+/// Miners whose payout settings have `auto_exchange`, `payout_coin` set to the
+/// exchange's quote coin (BTC) and a payout address of it have their balances
+/// of each configured coin pooled once the pool reaches `min_amount`; without
+/// an address they are paid in the coins they mined. A conversion then moves through
+/// `pooled` → `depositing` → `deposited` → `selling` → `sold` → `withdrawn`:
+/// the coins are sent from the pool wallet to the exchange, sold at market,
+/// the proceeds credited to the miners' quote coin balances pro rata, and
+/// withdrawn to the pool's quote coin wallet. Each run advances every open
+/// conversion by at most one step; a deposit whose relay outcome is unknown
+/// stays `depositing`, its recorded transaction in `deposit_tx`, for an
+/// operator to check.
+pub struct ConversionService {
+    pool: PgPool,
+    exchange: Arc<dyn Exchange>,
+    /// Pool wallets the coins are deposited from
+    senders: HashMap<String, Arc<dyn PayoutSender>>,
+    config: ExchangeConfig,
+}
+
+impl ConversionService {
+    pub fn new(
+        pool: PgPool,
+        exchange: Arc<dyn Exchange>,
+        senders: HashMap<String, Arc<dyn PayoutSender>>,
+        config: ExchangeConfig,
+    ) -> Self {
+        Self { pool, exchange, senders, config }
+    }
+
+    /// Advance open conversions, then pool the coins that have none open
+    pub async fn run(&self) -> Result<()> {
+        let open: Vec<OpenConversion> = sqlx::query_as(
+            r#"
+            SELECT id, coin, amount, status, deposit_tx, order_id, proceeds
+            FROM conversions
+            WHERE status IN ('pooled', 'deposited', 'selling', 'sold')
+            ORDER BY id
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        for conversion in &open {
+            if let Err(e) = self.advance(conversion).await {
+                warn!("Conversion {} of {} {} stuck at {}: {:#}", conversion.id, conversion.amount, conversion.coin, conversion.status, e);
+            }
+        }
+
+        for coin in self.config.converted_coins() {
+            if let Err(e) = self.start(&coin).await {
+                warn!("Failed to pool {} for conversion: {:#}", coin, e);
+            }
+        }
+        Ok(())
+    }
+
+    async fn advance(&self, conversion: &OpenConversion) -> Result<()> {
+        match conversion.status.as_str() {
+            "pooled" => self.deposit(conversion).await,
+            "deposited" => self.sell(conversion).await,
+            "selling" => self.settle(conversion).await,
+            "sold" => self.withdraw(conversion).await,
+            status => anyhow::bail!("Unexpected conversion status {}", status),
+        }
+    }
+
+    /// Pool the coin balances of auto-exchange miners, if they add up to the
+    /// coin's minimum and no conversion of the coin is open
+    async fn start(&self, coin: &str) -> Result<()> {
+        if !self.senders.contains_key(coin) {
+            anyhow::bail!("No payout wallet to deposit {} from", coin);
+        }
+        let mut tx = self.pool.begin().await?;
+
+        let open: bool = sqlx::query_scalar(
+            "SELECT EXISTS (SELECT 1 FROM conversions WHERE coin = $1 AND status NOT IN ('withdrawn', 'failed'))"
+        )
+        .bind(coin)
+        .fetch_one(&mut *tx)
+        .await?;
+        if open {
+            return Ok(());
+        }
+
+        let balances: Vec<(i32, Decimal)> = sqlx::query_as(
+            r#"
+            SELECT b.miner_id, b.balance
+            FROM balances b
+            JOIN payout_settings ps ON ps.miner_id = b.miner_id
+            WHERE b.coin = $1 AND ps.auto_exchange AND ps.payout_coin = $2 AND ps.payout_address IS NOT NULL
+              AND b.balance > 0
+            ORDER BY b.miner_id
+            FOR UPDATE OF b
+            "#,
+        )
+        .bind(coin)
+        .bind(&self.config.quote)
+        .fetch_all(&mut *tx)
+        .await?;
+        let contributions: Vec<(i32, Decimal)> = balances
+            .into_iter()
+            .map(|(miner_id, balance)| (miner_id, money::round_down(balance, coin).trunc_with_scale(VOLUME_DECIMALS)))
+            .filter(|(_, amount)| *amount > Decimal::ZERO)
+            .collect();
+        let amount: Decimal = contributions.iter().map(|c| c.1).sum();
+        if amount.is_zero() || amount < self.config.min_amount[coin] {
+            return Ok(());
+        }
+
+        let conversion_id: i64 = sqlx::query_scalar(
+            "INSERT INTO conversions (coin, quote, amount) VALUES ($1, $2, $3) RETURNING id"
+        )
+        .bind(coin)
+        .bind(&self.config.quote)
+        .bind(amount)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let miner_ids: Vec<i32> = contributions.iter().map(|c| c.0).collect();
+        let amounts: Vec<Decimal> = contributions.iter().map(|c| c.1).collect();
+        sqlx::query(
+            r#"
+            INSERT INTO conversion_contributions (conversion_id, miner_id, amount)
+            SELECT $1, t.miner_id, t.amount FROM UNNEST($2::int[], $3::numeric[]) AS t(miner_id, amount)
+            "#,
+        )
+        .bind(conversion_id)
+        .bind(&miner_ids)
+        .bind(&amounts)
+        .execute(&mut *tx)
+        .await?;
+
+        let mut transfer = Transfer::new(coin)
+            .conversion(conversion_id)
+            .memo(format!("pooled for exchange to {}", self.config.quote));
+        for (miner_id, amount) in &contributions {
+            transfer = transfer.transfer(Account::Available(*miner_id), Account::Exchange, EntryKind::Exchange, *amount);
+        }
+        ledger::post(&mut tx, &transfer).await?;
+
+        tx.commit().await?;
+        info!("Conversion {}: pooled {} {} of {} miners for {}", conversion_id, amount, coin, contributions.len(), self.config.quote);
+        Ok(())
+    }
+
+    /// Send the pooled coins to the exchange
+    async fn deposit(&self, conversion: &OpenConversion) -> Result<()> {
+        let sender = self
+            .senders
+            .get(&conversion.coin)
+            .with_context(|| format!("No payout wallet to deposit {} from", conversion.coin))?;
+        let address = self.exchange.deposit_address(&conversion.coin).await?;
+
+        // Claim the deposit first, so a crash mid-send never sends it twice
+        let claimed = sqlx::query(
+            "UPDATE conversions SET status = 'depositing', deposit_address = $2, updated_at = NOW() WHERE id = $1 AND status = 'pooled'"
+        )
+        .bind(conversion.id)
+        .bind(&address)
+        .execute(&self.pool)
+        .await?;
+        if claimed.rows_affected() == 0 {
+            return Ok(());
+        }
+
+        let destination = Destination { address, amount: conversion.amount };
+        let prepared = match sender.prepare(&[destination]).await {
+            Ok(prepared) => prepared,
+            Err(SendError::Rejected(e)) => return self.fail(conversion, &e).await,
+            Err(e) => {
+                // Nothing was relayed: try again next run
+                warn!("Conversion {} not deposited, retrying: {}", conversion.id, e);
+                sqlx::query("UPDATE conversions SET status = 'pooled', error_message = $2, updated_at = NOW() WHERE id = $1")
+                    .bind(conversion.id)
+                    .bind(e.to_string())
+                    .execute(&self.pool)
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        // Bind the deposit to its transaction before it can reach the network
+        let mut tx = self.pool.begin().await?;
+        record_transaction(&mut tx, &conversion.coin, &prepared).await?;
+        sqlx::query("UPDATE conversions SET deposit_tx = $2, deposit_fee = $3, updated_at = NOW() WHERE id = $1")
+            .bind(conversion.id)
+            .bind(&prepared.tx_hash)
+            .bind(prepared.fee)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        match sender.relay(&prepared).await {
+            Ok(()) => {
+                let sent = prepared.sent();
+                let mut tx = self.pool.begin().await?;
+                sqlx::query("UPDATE conversions SET status = 'deposited', updated_at = NOW() WHERE id = $1")
+                    .bind(conversion.id)
+                    .execute(&mut *tx)
+                    .await?;
+
+                let fee = Transfer::new(&conversion.coin)
+                    .conversion(conversion.id)
+                    .memo(format!("exchange deposit in {}", sent.tx_hash))
+                    .transfer(Account::Fees, Account::NetworkFees, EntryKind::Fee, sent.fee.unwrap_or_default());
+                ledger::post(&mut tx, &fee).await?;
+                tx.commit().await?;
+
+                info!("Conversion {}: deposited {} {} in {}", conversion.id, conversion.amount, conversion.coin, sent.tx_hash);
+            }
+            Err(e) => {
+                error!("Conversion {} left depositing, check the {} wallet for {}: {}", conversion.id, conversion.coin, prepared.tx_hash, e);
+                sqlx::query("UPDATE conversions SET error_message = $2, updated_at = NOW() WHERE id = $1")
+                    .bind(conversion.id)
+                    .bind(e.to_string())
+                    .execute(&self.pool)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Give the pooled coins back to the miners of a conversion that never
+    /// reached the exchange
+    async fn fail(&self, conversion: &OpenConversion, reason: &str) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("UPDATE conversions SET status = 'failed', error_message = $2, updated_at = NOW() WHERE id = $1")
+            .bind(conversion.id)
+            .bind(reason)
+            .execute(&mut *tx)
+            .await?;
+
+        let contributions: Vec<(i32, Decimal)> = sqlx::query_as(
+            "SELECT miner_id, amount FROM conversion_contributions WHERE conversion_id = $1 ORDER BY miner_id"
+        )
+        .bind(conversion.id)
+        .fetch_all(&mut *tx)
+        .await?;
+        let mut transfer = Transfer::new(&conversion.coin)
+            .conversion(conversion.id)
+            .memo("exchange deposit failed");
+        for (miner_id, amount) in contributions {
+            transfer = transfer.transfer(Account::Exchange, Account::Available(miner_id), EntryKind::Exchange, amount);
+        }
+        ledger::post(&mut tx, &transfer).await?;
+        tx.commit().await?;
+
+        warn!("Conversion {} failed, {} {} returned to miners: {}", conversion.id, conversion.amount, conversion.coin, reason);
+        Ok(())
+    }
+
+    /// Sell the coins at market once the exchange credited the deposit
+    async fn sell(&self, conversion: &OpenConversion) -> Result<()> {
+        let deposit_tx = conversion.deposit_tx.as_deref().context("Deposited conversion has no deposit transaction")?;
+        if !self.exchange.deposit_credited(&conversion.coin, deposit_tx).await? {
+            return Ok(());
+        }
+
+        let client_ref = format!("defpool-conversion-{}", conversion.id);
+        let order_id = self
+            .exchange
+            .market_sell(&conversion.coin, &self.config.quote, conversion.amount, &client_ref)
+            .await?;
+        sqlx::query("UPDATE conversions SET status = 'selling', order_id = $2, updated_at = NOW() WHERE id = $1")
+            .bind(conversion.id)
+            .bind(&order_id)
+            .execute(&self.pool)
+            .await?;
+
+        info!("Conversion {}: selling {} {} for {} in order {}", conversion.id, conversion.amount, conversion.coin, self.config.quote, order_id);
+        Ok(())
+    }
+
+    /// Credit the proceeds of a filled sale to the miners at the achieved rate
+    async fn settle(&self, conversion: &OpenConversion) -> Result<()> {
+        let order_id = conversion.order_id.as_deref().context("Selling conversion has no order")?;
+        let order = self.exchange.order(order_id).await?;
+        match order.status {
+            OrderStatus::Open => return Ok(()),
+            OrderStatus::Cancelled => {
+                error!("Conversion {}: order {} closed after selling {} of {} {}", conversion.id, order_id, order.filled, conversion.amount, conversion.coin);
+                sqlx::query("UPDATE conversions SET error_message = $2, updated_at = NOW() WHERE id = $1")
+                    .bind(conversion.id)
+                    .bind(format!("order closed after selling {}", order.filled))
+                    .execute(&self.pool)
+                    .await?;
+                return Ok(());
+            }
+            OrderStatus::Filled => {}
+        }
+
+        let mut tx = self.pool.begin().await?;
+        let contributions: Vec<(i32, Decimal)> = sqlx::query_as(
+            "SELECT miner_id, amount FROM conversion_contributions WHERE conversion_id = $1 ORDER BY miner_id"
+        )
+        .bind(conversion.id)
+        .fetch_all(&mut *tx)
+        .await?;
+        let quote = &self.config.quote;
+        let settlement = settlement(&order, &contributions, quote);
+
+        let claimed = sqlx::query(
+            r#"
+            UPDATE conversions SET status = 'sold', proceeds = $2, exchange_fee = $3, rate = $4, updated_at = NOW()
+            WHERE id = $1 AND status = 'selling'
+            "#,
+        )
+        .bind(conversion.id)
+        .bind(order.proceeds)
+        .bind(order.fee)
+        .bind(settlement.rate)
+        .execute(&mut *tx)
+        .await?;
+        if claimed.rows_affected() == 0 {
+            return Ok(());
+        }
+
+        let miner_ids: Vec<i32> = settlement.credits.iter().map(|c| c.0).collect();
+        let credited: Vec<Decimal> = settlement.credits.iter().map(|c| c.1).collect();
+        sqlx::query(
+            r#"
+            UPDATE conversion_contributions cc SET credited = t.credited
+            FROM UNNEST($2::int[], $3::numeric[]) AS t(miner_id, credited)
+            WHERE cc.conversion_id = $1 AND cc.miner_id = t.miner_id
+            "#,
+        )
+        .bind(conversion.id)
+        .bind(&miner_ids)
+        .bind(&credited)
+        .execute(&mut *tx)
+        .await?;
+
+        let mut transfer = Transfer::new(quote)
+            .conversion(conversion.id)
+            .memo(format!("sold {} {} at {}", conversion.amount, conversion.coin, settlement.rate))
+            .entry(Account::Exchange, EntryKind::Exchange, -order.proceeds)
+            .entry(Account::Fees, EntryKind::Fee, settlement.remainder);
+        for (miner_id, amount) in &settlement.credits {
+            transfer = transfer.entry(Account::Available(*miner_id), EntryKind::Exchange, *amount);
+        }
+        ledger::post(&mut tx, &transfer).await?;
+        tx.commit().await?;
+
+        info!(
+            "Conversion {}: sold {} {} for {} {} (rate {}, exchange fee {})",
+            conversion.id, conversion.amount, conversion.coin, order.proceeds, quote, settlement.rate, order.fee
+        );
+        Ok(())
+    }
+
+    /// Withdraw the proceeds to the pool's quote coin wallet, which pays the
+    /// miners out
+    async fn withdraw(&self, conversion: &OpenConversion) -> Result<()> {
+        let proceeds = conversion.proceeds.context("Sold conversion has no proceeds")?;
+        let withdrawal_id = self.exchange.withdraw(&self.config.quote, proceeds).await?;
+        sqlx::query("UPDATE conversions SET status = 'withdrawn', withdrawal_id = $2, updated_at = NOW() WHERE id = $1")
+            .bind(conversion.id)
+            .bind(&withdrawal_id)
+            .execute(&self.pool)
+            .await?;
+
+        info!("Conversion {}: withdrew {} {} ({})", conversion.id, proceeds, self.config.quote, withdrawal_id);
+        Ok(())
+    }
+}
+
+/// Split a filled sale's proceeds over the miners by what each contributed,
+/// rounded down to the quote coin's atomic unit
+fn settlement(order: &Order, contributions: &[(i32, Decimal)], quote: &str) -> Settlement {
+    let sold: Decimal = contributions.iter().map(|c| c.1).sum();
+    if sold.is_zero() {
+        return Settlement { credits: Vec::new(), remainder: order.proceeds, rate: Decimal::ZERO };
+    }
+
+    let credits: Vec<(i32, Decimal)> = contributions
+        .iter()
+        .map(|(miner_id, amount)| (*miner_id, money::round_down(order.proceeds * amount / sold, quote)))
+        .collect();
+    let remainder = order.proceeds - credits.iter().map(|c| c.1).sum::<Decimal>();
+    Settlement { credits, remainder, rate: (order.proceeds / sold).round_dp(12) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::mock::MockExchange;
+
+    fn dec(amount: &str) -> Decimal {
+        amount.parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_settle_sale_pro_rata() {
+        let exchange = MockExchange::new(&[("XMR", "0.0025")], "0.26");
+        let order_id = exchange.market_sell("XMR", "BTC", dec("3"), "defpool-conversion-1").await.unwrap();
+        let order = exchange.order(&order_id).await.unwrap();
+
+        assert_eq!(order.status, OrderStatus::Filled);
+        assert_eq!(order.proceeds, dec("0.0074805"));
+
+        let settlement = settlement(&order, &[(1, dec("1")), (2, dec("2"))], "BTC");
+
+        assert_eq!(settlement.credits, vec![(1, dec("0.0024935")), (2, dec("0.004987"))]);
+        assert_eq!(settlement.remainder, Decimal::ZERO);
+        assert_eq!(settlement.rate, dec("0.0024935"));
+    }
+
+    #[test]
+    fn test_settlement_rounding_goes_to_fees() {
+        let order = Order { status: OrderStatus::Filled, filled: dec("3"), proceeds: dec("0.00000010"), fee: Decimal::ZERO };
+
+        let settlement = settlement(&order, &[(1, dec("1")), (2, dec("1")), (3, dec("1"))], "BTC");
+
+        assert_eq!(settlement.credits.iter().map(|c| c.1).collect::<Vec<_>>(), vec![dec("0.00000003"); 3]);
+        assert_eq!(settlement.remainder, dec("0.00000001"));
+    }
+}
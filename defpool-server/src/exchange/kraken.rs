@@ -0,0 +1,203 @@
+use super::{Exchange, Order, OrderStatus};
+use crate::config::ExchangeConfig;
+use crate::money::Decimal;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256, Sha512};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tracing::debug;
+
+#[derive(Deserialize)]
+struct KrakenResponse {
+    #[serde(default)]
+    error: Vec<String>,
+    result: Option<Value>,
+}
+
+/// Kraken spot account, through its private REST API.
+///
+/// Withdrawals go to the address saved on the account under `withdraw_key`.
+pub struct KrakenExchange {
+    client: reqwest::Client,
+    api_url: String,
+    api_key: String,
+    secret: Vec<u8>,
+    withdraw_key: String,
+    /// Nonces must increase for every private call with the same key
+    last_nonce: AtomicU64,
+}
+
+impl KrakenExchange {
+    pub fn new(config: &ExchangeConfig) -> Result<Self> {
+        let secret = STANDARD.decode(&config.api_secret).context("Kraken API secret is not base64")?;
+        Ok(Self {
+            client: reqwest::Client::new(),
+            api_url: config.api_url.clone().unwrap_or_else(|| "https://api.kraken.com".to_string()),
+            api_key: config.api_key.clone(),
+            secret,
+            withdraw_key: config.withdraw_key.clone(),
+            last_nonce: AtomicU64::new(0),
+        })
+    }
+
+    fn nonce(&self) -> u64 {
+        let now = chrono::Utc::now().timestamp_micros() as u64;
+        let next = |previous: u64| now.max(previous + 1);
+        let previous = self
+            .last_nonce
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |previous| Some(next(previous)))
+            .unwrap_or_else(|previous| previous);
+        next(previous)
+    }
+
+    /// Call a private endpoint, e.g. `AddOrder`
+    async fn private(&self, method: &str, params: &[(&str, String)]) -> Result<Value> {
+        let path = format!("/0/private/{}", method);
+        let nonce = self.nonce().to_string();
+        let mut form = vec![("nonce", nonce.clone())];
+        form.extend(params.iter().cloned());
+        let body = serde_urlencoded::to_string(&form)?;
+
+        debug!("Kraken call: {}", method);
+
+        let response = self
+            .client
+            .post(format!("{}{}", self.api_url, path))
+            .header("API-Key", &self.api_key)
+            .header("API-Sign", sign(&self.secret, &path, &nonce, &body))
+            .header(reqwest::header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(body)
+            .timeout(Duration::from_secs(30))
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            anyhow::bail!("Kraken {} returned {}", method, response.status());
+        }
+
+        let response: KrakenResponse = response.json().await?;
+        if !response.error.is_empty() {
+            anyhow::bail!("Kraken {} failed: {}", method, response.error.join(", "));
+        }
+        response.result.with_context(|| format!("Kraken {} returned no result", method))
+    }
+}
+
+/// `API-Sign`: HMAC-SHA512 of the path and SHA256(nonce + body), keyed with the secret
+fn sign(secret: &[u8], path: &str, nonce: &str, body: &str) -> String {
+    let digest = Sha256::digest(format!("{}{}", nonce, body).as_bytes());
+    let mut mac = Hmac::<Sha512>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(path.as_bytes());
+    mac.update(&digest);
+    STANDARD.encode(mac.finalize().into_bytes())
+}
+
+/// Kraken's name of an asset
+fn asset(coin: &str) -> &str {
+    match coin {
+        "BTC" => "XBT",
+        "DOGE" => "XDG",
+        other => other,
+    }
+}
+
+fn decimal(value: &Value) -> Result<Decimal> {
+    value
+        .as_str()
+        .with_context(|| format!("Expected a decimal string, got {}", value))?
+        .parse()
+        .with_context(|| format!("Invalid decimal {}", value))
+}
+
+#[async_trait]
+impl Exchange for KrakenExchange {
+    async fn deposit_address(&self, coin: &str) -> Result<String> {
+        let methods = self.private("DepositMethods", &[("asset", asset(coin).to_string())]).await?;
+        let method = methods[0]["method"]
+            .as_str()
+            .with_context(|| format!("Kraken has no deposit method for {}", coin))?
+            .to_string();
+
+        let addresses = self
+            .private("DepositAddresses", &[("asset", asset(coin).to_string()), ("method", method)])
+            .await?;
+        addresses[0]["address"]
+            .as_str()
+            .map(str::to_string)
+            .with_context(|| format!("Kraken returned no {} deposit address", coin))
+    }
+
+    async fn deposit_credited(&self, coin: &str, tx_hash: &str) -> Result<bool> {
+        let deposits = self.private("DepositStatus", &[("asset", asset(coin).to_string())]).await?;
+        let credited = deposits.as_array().into_iter().flatten().any(|deposit| {
+            deposit["txid"].as_str().is_some_and(|txid| txid.eq_ignore_ascii_case(tx_hash))
+                && deposit["status"] == "Success"
+        });
+        Ok(credited)
+    }
+
+    async fn market_sell(&self, coin: &str, quote: &str, volume: Decimal, client_ref: &str) -> Result<String> {
+        let params = [
+            ("pair", format!("{}{}", asset(coin), asset(quote))),
+            ("type", "sell".to_string()),
+            ("ordertype", "market".to_string()),
+            ("volume", volume.to_string()),
+            ("cl_ord_id", client_ref.to_string()),
+        ];
+        let result = self.private("AddOrder", &params).await?;
+        result["txid"][0]
+            .as_str()
+            .map(str::to_string)
+            .context("Kraken returned no order id")
+    }
+
+    async fn order(&self, order_id: &str) -> Result<Order> {
+        let result = self.private("QueryOrders", &[("txid", order_id.to_string())]).await?;
+        let order = &result[order_id];
+        let filled = decimal(&order["vol_exec"])?;
+        let cost = decimal(&order["cost"])?;
+        let fee = decimal(&order["fee"])?;
+        let status = match order["status"].as_str() {
+            Some("closed") if filled == decimal(&order["vol"])? => OrderStatus::Filled,
+            Some("closed" | "canceled" | "expired") => OrderStatus::Cancelled,
+            _ => OrderStatus::Open,
+        };
+        Ok(Order { status, filled, proceeds: cost - fee, fee })
+    }
+
+    async fn withdraw(&self, coin: &str, amount: Decimal) -> Result<String> {
+        let params = [
+            ("asset", asset(coin).to_string()),
+            ("key", self.withdraw_key.clone()),
+            ("amount", amount.to_string()),
+        ];
+        let result = self.private("Withdraw", &params).await?;
+        result["refid"]
+            .as_str()
+            .map(str::to_string)
+            .context("Kraken returned no withdrawal id")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_api_sign() {
+        // Example from Kraken's REST authentication guide
+        let secret = STANDARD
+            .decode("kQH5HW/8p1uGOVjbgWA7FunAmGO8lsSUXNsu3eow76sz84Q18fWxnyRzBHCd3pd5nE9qa99HAZtuZuj6F1huXg==")
+            .unwrap();
+        let body = "nonce=1616492376594&ordertype=limit&pair=XBTUSD&price=37500&type=buy&volume=1.25";
+
+        assert_eq!(
+            sign(&secret, "/0/private/AddOrder", "1616492376594", body),
+            "4/dpxb3iT4tp/ZCVEwSnEsLxx0bqyhLpdfOpc6fn7OR8+UClSV5n9E6aSS8MPtnRfp32bAb0nmbRn6H8ndwLUQ=="
+        );
+    }
+}
@@ -0,0 +1,63 @@
+//! In-memory exchange for testing conversions
+
+use super::{Exchange, Order, OrderStatus};
+use crate::money::Decimal;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Fills every market order at once at a fixed rate, less a fee, and
+/// credits deposits as soon as they are checked
+pub struct MockExchange {
+    /// Quote coin per unit of each coin
+    rates: HashMap<String, Decimal>,
+    fee_percent: Decimal,
+    orders: Mutex<Vec<(String, Order)>>,
+}
+
+impl MockExchange {
+    pub fn new(rates: &[(&str, &str)], fee_percent: &str) -> Self {
+        Self {
+            rates: rates.iter().map(|(coin, rate)| (coin.to_string(), rate.parse().unwrap())).collect(),
+            fee_percent: fee_percent.parse().unwrap(),
+            orders: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl Exchange for MockExchange {
+    async fn deposit_address(&self, coin: &str) -> Result<String> {
+        Ok(format!("mock-{}-deposit", coin.to_lowercase()))
+    }
+
+    async fn deposit_credited(&self, _coin: &str, _tx_hash: &str) -> Result<bool> {
+        Ok(true)
+    }
+
+    async fn market_sell(&self, coin: &str, _quote: &str, volume: Decimal, client_ref: &str) -> Result<String> {
+        let rate = self.rates.get(coin).copied().ok_or_else(|| anyhow::anyhow!("No {} market", coin))?;
+        let cost = (volume * rate).round_dp(8);
+        let fee = (cost * self.fee_percent / Decimal::ONE_HUNDRED).round_dp(8);
+        let order = Order { status: OrderStatus::Filled, filled: volume, proceeds: cost - fee, fee };
+
+        let mut orders = self.orders.lock().unwrap();
+        let order_id = format!("order-{}", client_ref);
+        orders.push((order_id.clone(), order));
+        Ok(order_id)
+    }
+
+    async fn order(&self, order_id: &str) -> Result<Order> {
+        let orders = self.orders.lock().unwrap();
+        orders
+            .iter()
+            .find(|(id, _)| id == order_id)
+            .map(|(_, order)| order.clone())
+            .ok_or_else(|| anyhow::anyhow!("Unknown order {}", order_id))
+    }
+
+    async fn withdraw(&self, coin: &str, _amount: Decimal) -> Result<String> {
+        Ok(format!("mock-{}-withdrawal", coin.to_lowercase()))
+    }
+}
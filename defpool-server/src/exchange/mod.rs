@@ -0,0 +1,61 @@
+pub mod conversion;
+pub mod kraken;
+#[cfg(test)]
+pub mod mock;
+
+pub use conversion::ConversionService;
+
+use crate::config::{ExchangeConfig, ExchangeKind};
+use crate::money::Decimal;
+use anyhow::Result;
+use async_trait::async_trait;
+use std::sync::Arc;
+
+/// Where an order stands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OrderStatus {
+    Open,
+    /// Fully executed
+    Filled,
+    /// Closed without executing in full
+    Cancelled,
+}
+
+/// A market order on an exchange
+#[derive(Debug, Clone, PartialEq)]
+pub struct Order {
+    pub status: OrderStatus,
+    /// Volume of the sold coin executed
+    pub filled: Decimal,
+    /// What the sale paid in the quote coin, after the fee
+    pub proceeds: Decimal,
+    /// Exchange fee, in the quote coin
+    pub fee: Decimal,
+}
+
+/// An exchange mined coins are sold on for the payout coin
+#[async_trait]
+pub trait Exchange: Send + Sync {
+    /// Address the pool deposits a coin to
+    async fn deposit_address(&self, coin: &str) -> Result<String>;
+
+    /// Whether a deposit transaction has been credited to the account
+    async fn deposit_credited(&self, coin: &str, tx_hash: &str) -> Result<bool>;
+
+    /// Sell a volume of a coin for the quote coin at market. `client_ref`
+    /// identifies the order on the exchange. Returns the order id.
+    async fn market_sell(&self, coin: &str, quote: &str, volume: Decimal, client_ref: &str) -> Result<String>;
+
+    /// State of an order
+    async fn order(&self, order_id: &str) -> Result<Order>;
+
+    /// Withdraw an amount of a coin to the pool's wallet. Returns the withdrawal id.
+    async fn withdraw(&self, coin: &str, amount: Decimal) -> Result<String>;
+}
+
+/// Exchange for a configured account
+pub fn from_config(config: &ExchangeConfig) -> Result<Arc<dyn Exchange>> {
+    match config.kind {
+        ExchangeKind::Kraken => Ok(Arc::new(kraken::KrakenExchange::new(config)?)),
+    }
+}
@@ -0,0 +1,392 @@
+//! Double-entry ledger behind miner balances.
+//!
+//! Every change of a balance is posted as a transfer: entries on two or more
+//! accounts that add up to zero. Miner accounts mirror the columns of
+//! `balances`, which is updated in the same transaction as a cache, and can be
+//! checked against the ledger with [`check_balances`]. Entries are never
+//! updated or deleted; mistakes are corrected with new transfers.
+
+use crate::money::Decimal;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, PgPool, Postgres, Transaction};
+use std::collections::BTreeMap;
+
+/// An account entries are posted to. A positive amount increases it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Account {
+    /// What a miner can be paid (`balances.balance`)
+    Available(i32),
+    /// Immature block rewards and held payouts (`balances.pending_balance`)
+    Pending(i32),
+    /// What a miner was sent (`balances.total_paid`)
+    Paid(i32),
+    /// Where rewards come from; negative by what the pool received
+    Rewards,
+    /// Pool fees captured
+    Fees,
+    /// PPS/FPPS reserve
+    Reserve,
+    /// Counterpart of manual adjustments
+    Adjustments,
+    /// Network fees the pool paid for payouts and exchange deposits
+    NetworkFees,
+    /// Coins on their way to or back from the exchange
+    Exchange,
+}
+
+impl Account {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Available(_) => "available",
+            Self::Pending(_) => "pending",
+            Self::Paid(_) => "paid",
+            Self::Rewards => "rewards",
+            Self::Fees => "fees",
+            Self::Reserve => "reserve",
+            Self::Adjustments => "adjustments",
+            Self::NetworkFees => "network_fees",
+            Self::Exchange => "exchange",
+        }
+    }
+
+    pub fn miner_id(&self) -> Option<i32> {
+        match self {
+            Self::Available(id) | Self::Pending(id) | Self::Paid(id) => Some(*id),
+            _ => None,
+        }
+    }
+}
+
+/// Why an entry was posted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    /// Reward or per-share payment credited
+    Credit,
+    /// Payout sent, or a credit taken back (orphaned block)
+    Debit,
+    /// Pool fee captured from a reward, or spent on a payout's network fee
+    Fee,
+    /// Reserve refilled or drawn down
+    Reserve,
+    /// Block reward matured from pending to available
+    Maturity,
+    /// Payout requested: available held as pending
+    PayoutHold,
+    /// Payout failed: held amount released back to available
+    PayoutRelease,
+    /// Manual correction by an operator
+    Adjustment,
+    /// Coins pooled for auto-exchange, released, or credited as the payout coin
+    Exchange,
+}
+
+impl EntryKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Credit => "credit",
+            Self::Debit => "debit",
+            Self::Fee => "fee",
+            Self::Reserve => "reserve",
+            Self::Maturity => "maturity",
+            Self::PayoutHold => "payout_hold",
+            Self::PayoutRelease => "payout_release",
+            Self::Adjustment => "adjustment",
+            Self::Exchange => "exchange",
+        }
+    }
+}
+
+/// One movement of a coin between accounts
+#[derive(Debug, Clone)]
+pub struct Transfer {
+    pub coin: String,
+    pub memo: Option<String>,
+    pub round_id: Option<i64>,
+    pub payout_id: Option<i64>,
+    pub conversion_id: Option<i64>,
+    pub entries: Vec<(Account, EntryKind, Decimal)>,
+}
+
+impl Transfer {
+    pub fn new(coin: &str) -> Self {
+        Self {
+            coin: coin.to_string(),
+            memo: None,
+            round_id: None,
+            payout_id: None,
+            conversion_id: None,
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn memo(mut self, memo: impl Into<String>) -> Self {
+        self.memo = Some(memo.into());
+        self
+    }
+
+    pub fn round(mut self, round_id: i64) -> Self {
+        self.round_id = Some(round_id);
+        self
+    }
+
+    pub fn payout(mut self, payout_id: i64) -> Self {
+        self.payout_id = Some(payout_id);
+        self
+    }
+
+    pub fn conversion(mut self, conversion_id: i64) -> Self {
+        self.conversion_id = Some(conversion_id);
+        self
+    }
+
+    /// Add an entry; zero amounts are left out
+    pub fn entry(mut self, account: Account, kind: EntryKind, amount: Decimal) -> Self {
+        if !amount.is_zero() {
+            self.entries.push((account, kind, amount));
+        }
+        self
+    }
+
+    /// Move an amount from one account to another
+    pub fn transfer(self, from: Account, to: Account, kind: EntryKind, amount: Decimal) -> Self {
+        self.entry(from, kind, -amount).entry(to, kind, amount)
+    }
+
+    fn is_balanced(&self) -> bool {
+        self.entries.iter().map(|e| e.2).sum::<Decimal>().is_zero()
+    }
+
+    /// Change of each miner's available, pending and paid amounts
+    fn balance_changes(&self) -> BTreeMap<i32, [Decimal; 3]> {
+        let mut changes: BTreeMap<i32, [Decimal; 3]> = BTreeMap::new();
+        for (account, _, amount) in &self.entries {
+            let column = match account {
+                Account::Available(_) => 0,
+                Account::Pending(_) => 1,
+                Account::Paid(_) => 2,
+                _ => continue,
+            };
+            if let Some(miner_id) = account.miner_id() {
+                changes.entry(miner_id).or_default()[column] += *amount;
+            }
+        }
+        changes
+    }
+}
+
+/// Post a transfer and apply it to `balances`. Returns its transaction id.
+pub async fn post(tx: &mut Transaction<'_, Postgres>, transfer: &Transfer) -> Result<i64> {
+    if !transfer.is_balanced() {
+        anyhow::bail!("Unbalanced {} ledger transfer: {:?}", transfer.coin, transfer.entries);
+    }
+
+    let transaction_id: i64 = sqlx::query_scalar(
+        r#"
+        INSERT INTO ledger_transactions (coin, memo, round_id, payout_id, conversion_id)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING id
+        "#,
+    )
+    .bind(&transfer.coin)
+    .bind(&transfer.memo)
+    .bind(transfer.round_id)
+    .bind(transfer.payout_id)
+    .bind(transfer.conversion_id)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    let accounts: Vec<&str> = transfer.entries.iter().map(|e| e.0.as_str()).collect();
+    let miner_ids: Vec<Option<i32>> = transfer.entries.iter().map(|e| e.0.miner_id()).collect();
+    let kinds: Vec<&str> = transfer.entries.iter().map(|e| e.1.as_str()).collect();
+    let amounts: Vec<Decimal> = transfer.entries.iter().map(|e| e.2).collect();
+
+    sqlx::query(
+        r#"
+        INSERT INTO ledger_entries (transaction_id, account, miner_id, kind, amount)
+        SELECT $1, t.account, t.miner_id, t.kind, t.amount
+        FROM UNNEST($2::text[], $3::int[], $4::text[], $5::numeric[]) AS t(account, miner_id, kind, amount)
+        "#,
+    )
+    .bind(transaction_id)
+    .bind(&accounts)
+    .bind(&miner_ids)
+    .bind(&kinds)
+    .bind(&amounts)
+    .execute(&mut **tx)
+    .await?;
+
+    let changes = transfer.balance_changes();
+    if !changes.is_empty() {
+        let miner_ids: Vec<i32> = changes.keys().copied().collect();
+        let available: Vec<Decimal> = changes.values().map(|c| c[0]).collect();
+        let pending: Vec<Decimal> = changes.values().map(|c| c[1]).collect();
+        let paid: Vec<Decimal> = changes.values().map(|c| c[2]).collect();
+
+        sqlx::query(
+            r#"
+            INSERT INTO balances (miner_id, coin, balance, pending_balance, total_paid, updated_at)
+            SELECT t.miner_id, $1, t.available, t.pending, t.paid, NOW()
+            FROM UNNEST($2::int[], $3::numeric[], $4::numeric[], $5::numeric[])
+                AS t(miner_id, available, pending, paid)
+            ON CONFLICT (miner_id, coin)
+            DO UPDATE SET
+                balance = balances.balance + EXCLUDED.balance,
+                pending_balance = balances.pending_balance + EXCLUDED.pending_balance,
+                total_paid = balances.total_paid + EXCLUDED.total_paid,
+                updated_at = NOW()
+            "#,
+        )
+        .bind(&transfer.coin)
+        .bind(&miner_ids)
+        .bind(&available)
+        .bind(&pending)
+        .bind(&paid)
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    Ok(transaction_id)
+}
+
+/// A ledger entry on one of a miner's accounts, as listed by the API
+#[derive(Debug, Clone, FromRow, Serialize, Deserialize)]
+pub struct MinerEntry {
+    pub transaction_id: i64,
+    pub coin: String,
+    /// available, pending or paid
+    pub account: String,
+    pub kind: String,
+    pub amount: Decimal,
+    pub memo: Option<String>,
+    pub round_id: Option<i64>,
+    pub payout_id: Option<i64>,
+    pub conversion_id: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Most recent entries on a miner's accounts, optionally of one coin
+pub async fn miner_entries(
+    pool: &PgPool,
+    wallet_address: &str,
+    coin: Option<&str>,
+    limit: i64,
+) -> Result<Vec<MinerEntry>> {
+    let entries = sqlx::query_as::<_, MinerEntry>(
+        r#"
+        SELECT e.transaction_id, t.coin, e.account, e.kind, e.amount,
+               t.memo, t.round_id, t.payout_id, t.conversion_id, t.created_at
+        FROM ledger_entries e
+        JOIN ledger_transactions t ON t.id = e.transaction_id
+        JOIN miners m ON m.id = e.miner_id
+        WHERE m.wallet_address = $1
+          AND ($2::text IS NULL OR t.coin = $2)
+        ORDER BY e.id DESC
+        LIMIT $3
+        "#,
+    )
+    .bind(wallet_address)
+    .bind(coin)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(entries)
+}
+
+/// A miner balance that differs from the sum of its ledger entries
+#[derive(Debug, FromRow)]
+pub struct BalanceMismatch {
+    pub wallet_address: String,
+    pub coin: String,
+    pub balance: Decimal,
+    pub ledger_balance: Decimal,
+    pub pending_balance: Decimal,
+    pub ledger_pending: Decimal,
+    pub total_paid: Decimal,
+    pub ledger_paid: Decimal,
+}
+
+/// Compare every row of `balances` with the ledger
+pub async fn check_balances(pool: &PgPool) -> Result<Vec<BalanceMismatch>> {
+    let mismatches = sqlx::query_as::<_, BalanceMismatch>(
+        r#"
+        WITH ledger AS (
+            SELECT e.miner_id, t.coin,
+                   COALESCE(SUM(e.amount) FILTER (WHERE e.account = 'available'), 0) AS balance,
+                   COALESCE(SUM(e.amount) FILTER (WHERE e.account = 'pending'), 0) AS pending_balance,
+                   COALESCE(SUM(e.amount) FILTER (WHERE e.account = 'paid'), 0) AS total_paid
+            FROM ledger_entries e
+            JOIN ledger_transactions t ON t.id = e.transaction_id
+            WHERE e.miner_id IS NOT NULL
+            GROUP BY e.miner_id, t.coin
+        )
+        SELECT m.wallet_address, COALESCE(b.coin, l.coin) AS coin,
+               COALESCE(b.balance, 0) AS balance, COALESCE(l.balance, 0) AS ledger_balance,
+               COALESCE(b.pending_balance, 0) AS pending_balance, COALESCE(l.pending_balance, 0) AS ledger_pending,
+               COALESCE(b.total_paid, 0) AS total_paid, COALESCE(l.total_paid, 0) AS ledger_paid
+        FROM balances b
+        FULL JOIN ledger l ON l.miner_id = b.miner_id AND l.coin = b.coin
+        JOIN miners m ON m.id = COALESCE(b.miner_id, l.miner_id)
+        WHERE COALESCE(b.balance, 0) <> COALESCE(l.balance, 0)
+           OR COALESCE(b.pending_balance, 0) <> COALESCE(l.pending_balance, 0)
+           OR COALESCE(b.total_paid, 0) <> COALESCE(l.total_paid, 0)
+        ORDER BY m.wallet_address, coin
+        "#,
+    )
+    .fetch_all(pool)
+    .await?;
+    Ok(mismatches)
+}
+
+/// Correct a miner's available balance by hand, e.g. after a support ticket
+pub async fn adjust(pool: &PgPool, wallet_address: &str, coin: &str, amount: Decimal, memo: &str) -> Result<i64> {
+    let mut tx = pool.begin().await?;
+
+    let miner_id: i32 = sqlx::query_scalar("SELECT id FROM miners WHERE wallet_address = $1")
+        .bind(wallet_address)
+        .fetch_optional(&mut *tx)
+        .await?
+        .with_context(|| format!("Unknown miner {}", wallet_address))?;
+
+    let transfer = Transfer::new(coin)
+        .memo(memo)
+        .transfer(Account::Adjustments, Account::Available(miner_id), EntryKind::Adjustment, amount);
+    let transaction_id = post(&mut tx, &transfer).await?;
+
+    tx.commit().await?;
+    Ok(transaction_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(amount: &str) -> Decimal {
+        amount.parse().unwrap()
+    }
+
+    #[test]
+    fn test_transfer_must_balance() {
+        let round = Transfer::new("XMR")
+            .entry(Account::Rewards, EntryKind::Credit, dec("-0.6"))
+            .entry(Account::Available(1), EntryKind::Credit, dec("0.594"))
+            .entry(Account::Fees, EntryKind::Fee, dec("0.006"))
+            .entry(Account::Reserve, EntryKind::Reserve, Decimal::ZERO);
+
+        assert!(round.is_balanced());
+        assert_eq!(round.entries.len(), 3);
+        assert!(!Transfer::new("XMR").entry(Account::Fees, EntryKind::Fee, dec("0.1")).is_balanced());
+    }
+
+    #[test]
+    fn test_balance_changes_per_miner() {
+        let hold = Transfer::new("XMR")
+            .transfer(Account::Available(7), Account::Pending(7), EntryKind::PayoutHold, dec("0.25"))
+            .transfer(Account::Pending(8), Account::Paid(8), EntryKind::Debit, dec("0.1"));
+        let changes = hold.balance_changes();
+
+        assert_eq!(changes[&7], [dec("-0.25"), dec("0.25"), Decimal::ZERO]);
+        assert_eq!(changes[&8], [Decimal::ZERO, dec("-0.1"), dec("0.1")]);
+    }
+}
@@ -7,21 +7,38 @@ mod db;
 mod accounting;
 mod payout;
 mod daemon;
+mod events;
+mod auth;
+mod blocks;
+mod money;
+mod ledger;
+mod exchange;
+mod address;
 
 use axum::{
+    middleware,
     routing::{get, post},
     Router,
 };
-use clap::Parser;
+use clap::{Parser, Subcommand};
 use config::Config;
 use state::AppState;
 use profitability::{ProfitabilityCalculator, providers::{CoinGeckoProvider, PoolApiProvider}};
 use tasks::profitability_monitor::start_profitability_monitor;
+use tasks::health_checker::start_health_checker;
 use tasks::payout_processor::start_payout_processor;
-use tasks::balance_updater::start_balance_updater;
+use tasks::payout_scheduler::start_payout_scheduler;
+use tasks::share_payments::start_share_payments;
+use tasks::block_maturity::start_block_maturity;
+use tasks::conversions::start_conversions;
 use db::{create_pool, repository::ShareRepository};
 use accounting::AccountingService;
-use payout::BalanceCalculator;
+use address::AddressValidator;
+use payout::{BalanceCalculator, PayoutScheduler, Reward, RewardSource};
+use auth::{MinerPinStore, ProxyKeyStore};
+use blocks::BlockTracker;
+use std::collections::HashMap;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tracing::info;
 
@@ -29,6 +46,111 @@ use tracing::info;
 struct Args {
     #[arg(short, long, default_value = "defpool-server.toml")]
     config: String,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Apply pending database migrations and exit
+    Migrate,
+    /// Distribute a reward (found block or pool payout) with the coin's reward scheme
+    Reward {
+        /// Coin the reward was paid in
+        coin: String,
+        amount: money::Decimal,
+        #[arg(long, value_enum, default_value = "pool-payout")]
+        source: RewardSource,
+        /// Block hash or payout transaction, so the reward is only distributed once
+        #[arg(long)]
+        reference: Option<String>,
+        /// Wallet that found the block; pays it the whole block if it mines solo
+        #[arg(long)]
+        finder: Option<String>,
+    },
+    /// Show the PPS/FPPS reserve balance of each coin
+    Reserve,
+    /// Check balances against the ledger or correct them
+    Ledger {
+        #[command(subcommand)]
+        action: LedgerAction,
+    },
+    /// Pause or resume payouts pool-wide
+    Payouts {
+        #[command(subcommand)]
+        action: PayoutsAction,
+    },
+    /// Manage the keys proxies sign internal API requests with
+    ProxyKey {
+        #[command(subcommand)]
+        action: ProxyKeyAction,
+    },
+    /// Manage the PINs miners authorize payout changes with
+    MinerPin {
+        #[command(subcommand)]
+        action: MinerPinAction,
+    },
+}
+
+#[derive(Subcommand)]
+enum LedgerAction {
+    /// List balances that differ from the sum of their ledger entries
+    Check,
+    /// Credit (or debit, with a negative amount) a miner's available balance
+    Adjust {
+        wallet: String,
+        coin: String,
+        #[arg(allow_hyphen_values = true)]
+        amount: money::Decimal,
+        /// Why, e.g. the support ticket
+        #[arg(long)]
+        memo: String,
+    },
+}
+
+#[derive(Subcommand)]
+enum PayoutsAction {
+    /// Stop creating automatic payouts and sending payouts
+    Pause {
+        #[arg(long)]
+        reason: Option<String>,
+    },
+    /// Resume payouts
+    Resume,
+    /// Show whether payouts are paused
+    Status,
+    /// Settle payouts held for review after checking their wallet
+    Settle {
+        #[arg(required = true)]
+        payout_ids: Vec<i64>,
+        /// Transaction the payouts were paid in
+        #[arg(long, required_unless_present = "not_sent", conflicts_with = "not_sent")]
+        sent_in: Option<String>,
+        /// The payouts were never sent: release their amounts
+        #[arg(long)]
+        not_sent: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProxyKeyAction {
+    /// Issue a key for a proxy and print its secret
+    Issue { name: String },
+    /// Revoke a proxy's key; takes effect on its next request
+    Revoke { name: String },
+    /// List proxies and their key status
+    List,
+}
+
+#[derive(Subcommand)]
+enum MinerPinAction {
+    /// Issue a miner a new PIN, replacing any it had, and print it. Only
+    /// issue one once the miner proved they own the wallet.
+    Issue { wallet: String },
+    /// Remove a miner's PIN; nothing about its payouts can be changed until
+    /// it is issued a new one
+    Reset { wallet: String },
 }
 
 #[tokio::main]
@@ -48,24 +170,75 @@ async fn main() -> anyhow::Result<()> {
     let db_pool = create_pool(&config.database_url).await?;
     info!("Database connected successfully");
 
+    info!("Initializing pool API difficulty provider");
+    let difficulty_provider = Arc::new(PoolApiProvider::new());
+    let balance_calculator = Arc::new(BalanceCalculator::new(db_pool.clone(), &config, difficulty_provider.clone()));
+
+    match args.command {
+        Some(Command::Migrate) => return Ok(()),
+        Some(Command::Reward { coin, amount, source, reference, finder }) => {
+            let reward = Reward { coin, amount, source, reference, until: chrono::Utc::now(), finder, pending: false };
+            let round = balance_calculator.distribute_reward(&reward).await?;
+            println!(
+                "Round {} ({}): credited {} to {} miners over {} shares (fee {}, reserve {:+})",
+                round.round_id, round.scheme.as_str(), round.credited, round.miners,
+                round.window_shares, round.fee, round.reserve
+            );
+            return Ok(());
+        }
+        Some(Command::Reserve) => {
+            for (coin, balance) in balance_calculator.reserve_balances().await? {
+                println!("{}\t{}", coin, balance);
+            }
+            return Ok(());
+        }
+        Some(Command::Ledger { action }) => return run_ledger_command(&db_pool, action).await,
+        Some(Command::Payouts { action }) => return run_payouts_command(&db_pool, action).await,
+        _ => {}
+    }
+
+    let proxy_keys = Arc::new(ProxyKeyStore::new(db_pool.clone()));
+    if let Some(Command::ProxyKey { action }) = args.command {
+        return run_proxy_key_command(&proxy_keys, action).await;
+    }
+
+    let miner_pins = Arc::new(MinerPinStore::new(db_pool.clone()));
+    if let Some(Command::MinerPin { action }) = args.command {
+        return run_miner_pin_command(&miner_pins, &AddressValidator::new(&config), action).await;
+    }
+
     // Initialize accounting service
     let repository = Arc::new(ShareRepository::new(db_pool.clone()));
-    let accounting_service = Arc::new(AccountingService::new(repository));
+    let addresses = Arc::new(AddressValidator::new(&config));
+    let accounting_service = Arc::new(AccountingService::new(repository, addresses.clone()));
     info!("Accounting service initialized");
 
     // Initialize payout service
-    let payout_service = Arc::new(payout::PayoutService::new(db_pool.clone()));
-    info!("Payout service initialized");
+    let senders: HashMap<String, Arc<dyn payout::sender::PayoutSender>> = config
+        .payout_wallets
+        .iter()
+        .map(|(coin, wallet)| (coin.clone(), payout::sender::from_config(coin, wallet)))
+        .collect();
+    let payout_service = Arc::new(payout::PayoutService::new(db_pool.clone(), senders.clone(), config.payouts.confirmations));
+    info!("Payout service initialized for {:?}", config.payout_wallets.keys().collect::<Vec<_>>());
+
+    // Initialize block tracking
+    let block_tracker = Arc::new(BlockTracker::new(db_pool.clone(), balance_calculator.clone(), &config));
 
     // Initialize state with services
-    let state = AppState::new(config.clone(), accounting_service, payout_service.clone());
+    let state = AppState::new(
+        config.clone(),
+        accounting_service,
+        payout_service.clone(),
+        proxy_keys,
+        miner_pins,
+        block_tracker.clone(),
+        addresses,
+    );
 
     // Initialize profitability providers
     info!("Initializing CoinGecko price provider");
     let price_provider = Arc::new(CoinGeckoProvider::new());
-    
-    info!("Initializing pool API difficulty provider");
-    let difficulty_provider = Arc::new(PoolApiProvider::new());
 
     let calculator = Arc::new(ProfitabilityCalculator::new(
         price_provider,
@@ -76,17 +249,58 @@ async fn main() -> anyhow::Result<()> {
     // Start background profitability monitor
     start_profitability_monitor(state.clone(), calculator, config.clone());
 
+    // Start background target health checker
+    start_health_checker(state.clone(), config.clone());
+
     // Start background payout processor
     start_payout_processor(payout_service.clone());
 
-    // Start background balance updater
-    let balance_calculator = Arc::new(BalanceCalculator::new(db_pool.clone()));
-    let coins: Vec<String> = config.targets.iter()
-        .map(|t| t.coin.clone())
-        .collect::<std::collections::HashSet<_>>()
-        .into_iter()
-        .collect();
-    start_balance_updater(balance_calculator, coins);
+    // Start background automatic payouts
+    let payout_scheduler = Arc::new(PayoutScheduler::new(
+        db_pool.clone(),
+        config.payouts.clone(),
+        payout_service.payout_coins(),
+        config.exchange.as_ref(),
+    ));
+    start_payout_scheduler(payout_scheduler, config.payouts.check_interval_secs);
+
+    // Start background auto-exchange conversions
+    if let Some(exchange_config) = &config.exchange {
+        let exchange = exchange::from_config(exchange_config)?;
+        let conversions = Arc::new(exchange::ConversionService::new(
+            db_pool.clone(),
+            exchange,
+            senders,
+            exchange_config.clone(),
+        ));
+        start_conversions(conversions, exchange_config.interval_secs);
+        info!("Auto-exchange to {} enabled for {:?}", exchange_config.quote, exchange_config.converted_coins());
+    }
+
+    // Start background PPS/FPPS share payments
+    start_share_payments(balance_calculator, config.reward_schemes.pps_interval_secs);
+
+    // Start background block maturity checks
+    start_block_maturity(block_tracker, config.blocks.poll_interval_secs);
+
+    // Internal routes, only for proxies with a valid key
+    let internal = Router::new()
+        // Proxy command channel
+        .route("/api/v1/proxy/events", get(api::proxy_events))
+        // Share recording
+        .route("/api/v1/shares", post(api::record_share))
+        .route("/api/v1/shares/batch", post(api::record_share_batch))
+        // Found blocks
+        .route("/api/v1/blocks/candidates", post(api::record_block))
+        // Wallet checks at miner login
+        .route("/api/v1/addresses/validate", post(api::validate_address))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::require_proxy));
+
+    // Miner-changing routes, only with the wallet's PIN
+    let miner_writes = Router::new()
+        .route("/api/v1/miners/:wallet/payout", post(api::request_payout))
+        .route("/api/v1/miners/:wallet/payout-settings", axum::routing::put(api::update_payout_settings))
+        .route_layer(middleware::from_fn_with_state(state.clone(), auth::require_miner_pin));
 
     // Build API routes with versioning
     let app = Router::new()
@@ -94,24 +308,123 @@ async fn main() -> anyhow::Result<()> {
         .route("/api/v1/target", get(api::get_current_target))
         .route("/api/v1/targets", get(api::list_targets))
         .route("/api/v1/targets/current", get(api::get_current_target_name))
+        .route("/api/v1/targets/health", get(api::get_target_health))
         .route("/api/v1/stats", get(api::get_pool_stats))
+        .route("/api/v1/blocks", get(api::list_blocks))
         // Miner endpoints
         .route("/api/v1/miners/:wallet/stats", get(api::get_miner_stats))
         .route("/api/v1/miners/:wallet/workers", get(api::get_miner_workers))
         .route("/api/v1/miners/:wallet/balances", get(api::get_miner_balances))
         .route("/api/v1/miners/:wallet/balance/:coin", get(api::get_miner_balance))
-        .route("/api/v1/miners/:wallet/payout", post(api::request_payout))
         .route("/api/v1/miners/:wallet/payouts", get(api::get_payout_history))
-        .route("/api/v1/miners/:wallet/payout-settings", axum::routing::put(api::update_payout_settings))
-        // Share recording (internal)
-        .route("/api/v1/shares", post(api::record_share))
+        .route("/api/v1/miners/:wallet/ledger", get(api::get_miner_ledger))
+        .merge(miner_writes)
+        .merge(internal)
         // Legacy routes (deprecated, for backward compatibility)
         .route("/target", get(api::get_current_target))
         .with_state(state);
 
     info!("DefPool Server listening on {}", listen_address);
     let listener = tokio::net::TcpListener::bind(listen_address).await?;
-    axum::serve(listener, app).await?;
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await?;
+
+    Ok(())
+}
+
+async fn run_ledger_command(pool: &sqlx::PgPool, action: LedgerAction) -> anyhow::Result<()> {
+    match action {
+        LedgerAction::Check => {
+            let mismatches = ledger::check_balances(pool).await?;
+            for m in &mismatches {
+                println!(
+                    "{}\t{}\tbalance {} (ledger {})\tpending {} (ledger {})\tpaid {} (ledger {})",
+                    m.wallet_address, m.coin, m.balance, m.ledger_balance,
+                    m.pending_balance, m.ledger_pending, m.total_paid, m.ledger_paid
+                );
+            }
+            if !mismatches.is_empty() {
+                anyhow::bail!("{} balances differ from the ledger", mismatches.len());
+            }
+            println!("All balances match the ledger");
+        }
+        LedgerAction::Adjust { wallet, coin, amount, memo } => {
+            if !money::is_exact(amount, &coin) {
+                anyhow::bail!("Amount {} has more decimals than {} has", amount, coin);
+            }
+            let transaction_id = ledger::adjust(pool, &wallet, &coin, amount, &memo).await?;
+            println!("Adjusted {} {} of {} in ledger transaction {}", amount, coin, wallet, transaction_id);
+        }
+    }
+    Ok(())
+}
+
+async fn run_payouts_command(pool: &sqlx::PgPool, action: PayoutsAction) -> anyhow::Result<()> {
+    match action {
+        PayoutsAction::Pause { reason } => {
+            payout::scheduler::set_paused(pool, true, reason.as_deref()).await?;
+            println!("Payouts paused");
+        }
+        PayoutsAction::Resume => {
+            payout::scheduler::set_paused(pool, false, None).await?;
+            println!("Payouts resumed");
+        }
+        PayoutsAction::Status => match payout::scheduler::paused(pool).await? {
+            Some(reason) => println!("Payouts paused: {}", reason),
+            None => println!("Payouts running"),
+        },
+        PayoutsAction::Settle { payout_ids, sent_in, .. } => {
+            payout::service::settle_review(pool, &payout_ids, sent_in.as_deref()).await?;
+            match sent_in {
+                Some(tx_hash) => println!("Payouts {:?} broadcast in {}", payout_ids, tx_hash),
+                None => println!("Payouts {:?} failed, amounts released", payout_ids),
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn run_miner_pin_command(
+    miner_pins: &MinerPinStore,
+    addresses: &AddressValidator,
+    action: MinerPinAction,
+) -> anyhow::Result<()> {
+    match action {
+        MinerPinAction::Issue { wallet } => {
+            addresses.check_miner(&wallet).await?;
+            let pin = miner_pins.issue(&wallet).await?;
+            println!("Issued PIN of miner {}: {}", wallet, pin);
+        }
+        MinerPinAction::Reset { wallet } => {
+            if !miner_pins.reset(&wallet).await? {
+                anyhow::bail!("Miner {} has no PIN", wallet);
+            }
+            println!("Reset PIN of miner {}; issue it a new one to allow payout changes", wallet);
+        }
+    }
+    Ok(())
+}
 
+async fn run_proxy_key_command(proxy_keys: &ProxyKeyStore, action: ProxyKeyAction) -> anyhow::Result<()> {
+    match action {
+        ProxyKeyAction::Issue { name } => {
+            let secret = proxy_keys.issue(&name).await?;
+            println!("Issued key for proxy {}. Add to its defpool-proxy.toml:\n", name);
+            println!("[server_auth]\nproxy_name = \"{}\"\nkey = \"{}\"", name, secret);
+        }
+        ProxyKeyAction::Revoke { name } => {
+            if !proxy_keys.revoke(&name).await? {
+                anyhow::bail!("Proxy {} has no active key", name);
+            }
+            println!("Revoked key of proxy {}", name);
+        }
+        ProxyKeyAction::List => {
+            for key in proxy_keys.list().await? {
+                match key.revoked_at {
+                    Some(revoked_at) => println!("{}\trevoked {}", key.name, revoked_at),
+                    None => println!("{}\tactive since {}", key.name, key.created_at),
+                }
+            }
+        }
+    }
     Ok(())
 }
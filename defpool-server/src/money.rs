@@ -0,0 +1,74 @@
+//! Monetary amounts.
+//!
+//! Amounts are exact decimals: `NUMERIC` in the database and strings in JSON.
+//! Credits are rounded down to the coin's atomic unit, and whatever rounding
+//! leaves over is accounted for explicitly, so splits add back up exactly.
+
+use anyhow::{Context, Result};
+use rust_decimal::RoundingStrategy;
+
+pub use rust_decimal::Decimal;
+
+/// Decimal places of a coin's atomic unit
+pub fn decimals(coin: &str) -> u32 {
+    match coin.to_ascii_uppercase().as_str() {
+        "XMR" => 12, // piconero
+        _ => 8,      // satoshi: BTC, LTC, DOGE
+    }
+}
+
+/// Round an amount down to the coin's atomic unit
+pub fn round_down(amount: Decimal, coin: &str) -> Decimal {
+    amount.round_dp_with_strategy(decimals(coin), RoundingStrategy::ToZero)
+}
+
+/// Whether an amount is a whole number of the coin's atomic units
+pub fn is_exact(amount: Decimal, coin: &str) -> bool {
+    round_down(amount, coin) == amount
+}
+
+/// Amount from an estimate such as a network block reward
+pub fn from_estimate(value: f64) -> Result<Decimal> {
+    Decimal::try_from(value).with_context(|| format!("{} is not a valid amount", value))
+}
+
+/// Amount in the coin's atomic units, as wallets such as monero-wallet-rpc take it
+pub fn to_atomic(amount: Decimal, coin: &str) -> Result<u64> {
+    if !is_exact(amount, coin) {
+        anyhow::bail!("{} has more decimals than {} has", amount, coin);
+    }
+    let atomic = amount * Decimal::from(10u64.pow(decimals(coin)));
+    u64::try_from(atomic).with_context(|| format!("{} {} is out of range", amount, coin))
+}
+
+/// Amount from the coin's atomic units
+pub fn from_atomic(atomic: u64, coin: &str) -> Decimal {
+    Decimal::from_i128_with_scale(atomic.into(), decimals(coin))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_round_down_to_atomic_units() {
+        let amount = Decimal::from_str("0.123456789012345").unwrap();
+
+        assert_eq!(round_down(amount, "XMR"), Decimal::from_str("0.123456789012").unwrap());
+        assert_eq!(round_down(amount, "DOGE"), Decimal::from_str("0.12345678").unwrap());
+        assert!(is_exact(Decimal::from_str("0.000000000001").unwrap(), "XMR"));
+        assert!(!is_exact(Decimal::from_str("0.000000001").unwrap(), "LTC"));
+    }
+
+    #[test]
+    fn test_atomic_units() {
+        let amount = Decimal::from_str("0.01").unwrap();
+
+        assert_eq!(to_atomic(amount, "XMR").unwrap(), 10_000_000_000);
+        assert_eq!(to_atomic(amount, "LTC").unwrap(), 1_000_000);
+        assert_eq!(from_atomic(10_000_000_000, "XMR"), amount);
+        assert!(to_atomic(Decimal::from_str("0.000000001").unwrap(), "DOGE").is_err());
+        assert!(to_atomic(-amount, "XMR").is_err());
+    }
+}
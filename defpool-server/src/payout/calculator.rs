@@ -1,106 +1,547 @@
-use anyhow::Result;
-use chrono::{DateTime, Utc};
-use sqlx::PgPool;
+use super::schemes::{self, Allocation, RewardScheme, WindowWeight};
+use crate::config::{Config, MiningTarget, RewardSchemesConfig};
+use crate::ledger::{self, Account, EntryKind, Transfer};
+use crate::money::{self, Decimal};
+use crate::profitability::providers::DifficultyProvider;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Duration, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::sync::Arc;
 use tracing::{debug, info};
 
-/// Calculator for miner balances based on shares
+/// Shares younger than this are left for the next per-share run, so shares
+/// still being inserted are not skipped by the cursor
+const SHARE_SETTLE_SECS: i64 = 30;
+
+/// Block rounds averaged for the transaction fees FPPS pays
+const FPPS_FEE_ROUNDS: i64 = 10;
+
+/// Where a reward came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum RewardSource {
+    /// A block the pool found itself
+    Block,
+    /// A payout from an upstream pool
+    PoolPayout,
+}
+
+impl RewardSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Block => "block",
+            Self::PoolPayout => "pool_payout",
+        }
+    }
+}
+
+/// A reward to distribute to miners
+#[derive(Debug, Clone)]
+pub struct Reward {
+    pub coin: String,
+    pub amount: Decimal,
+    pub source: RewardSource,
+    /// Block hash or payout transaction; a reference is only distributed once
+    pub reference: Option<String>,
+    /// Only shares submitted up to this time count (the block time for blocks)
+    pub until: DateTime<Utc>,
+    /// Wallet that found the block; a SOLO miner gets the whole block
+    pub finder: Option<String>,
+    /// Credit `pending_balance` until the block matures, instead of `balance`
+    pub pending: bool,
+}
+
+/// Result of distributing a reward or paying shares
+#[derive(Debug)]
+pub struct RoundSummary {
+    pub round_id: i64,
+    pub scheme: RewardScheme,
+    pub fee: Decimal,
+    pub credited: Decimal,
+    /// Change of the pool reserve
+    pub reserve: Decimal,
+    pub miners: usize,
+    pub window_shares: i64,
+}
+
+/// Difficulty per miner over a share window, with the window bounds
+struct ShareWindow {
+    weights: Vec<WindowWeight>,
+    first_share_id: Option<i64>,
+    last_share_id: Option<i64>,
+    shares: i64,
+    difficulty: f64,
+}
+
+impl ShareWindow {
+    /// Rows of (miner, scheme, difficulty, first share, last share, shares)
+    fn from_rows(rows: Vec<(i32, String, f64, i64, i64, i64)>) -> Result<Self> {
+        let mut window = Self {
+            weights: Vec::with_capacity(rows.len()),
+            first_share_id: rows.iter().map(|r| r.3).min(),
+            last_share_id: rows.iter().map(|r| r.4).max(),
+            shares: rows.iter().map(|r| r.5).sum(),
+            difficulty: rows.iter().map(|r| r.2).sum(),
+        };
+        for (miner_id, scheme, difficulty, ..) in rows {
+            window.weights.push(WindowWeight { miner_id, scheme: scheme.parse()?, difficulty });
+        }
+        Ok(window)
+    }
+}
+
+/// Credits rewards to miner balances with the coin's reward scheme.
+///
+/// PPLNS splits a reward over the last `window_shares` valid shares of the
+/// coin's targets and PROP over the shares since the previous reward, by
+/// difficulty after the pool fee. A block found by a SOLO miner goes to that
+/// miner alone. PPS and FPPS miners are paid for each share from the pool
+/// reserve by [`BalanceCalculator::credit_share_payments`], and their part of
+/// rewards goes back to the reserve. Every distribution is recorded as a round
+/// with its share window and per-miner credits.
 pub struct BalanceCalculator {
     pool: PgPool,
+    targets: Vec<MiningTarget>,
+    window_shares: i64,
+    fee_percent: Decimal,
+    schemes: RewardSchemesConfig,
+    difficulty_provider: Arc<dyn DifficultyProvider>,
 }
 
 impl BalanceCalculator {
-    pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+    pub fn new(pool: PgPool, config: &Config, difficulty_provider: Arc<dyn DifficultyProvider>) -> Self {
+        Self {
+            pool,
+            targets: config.targets.clone(),
+            window_shares: config.pplns.window_shares,
+            fee_percent: config.pool_fee_percent,
+            schemes: config.reward_schemes.clone(),
+            difficulty_provider,
+        }
     }
 
-    /// Calculate balance for a miner based on shares since last calculation
-    /// Uses PPLNS (Pay Per Last N Shares) method
-    pub async fn calculate_miner_balance(
+    /// Coin as configured and the names of the targets mining it
+    fn coin_targets(&self, coin: &str) -> Result<(String, Vec<String>)> {
+        let targets: Vec<&MiningTarget> = self
+            .targets
+            .iter()
+            .filter(|t| t.coin.eq_ignore_ascii_case(coin))
+            .collect();
+        let Some(coin) = targets.first().map(|t| t.coin.clone()) else {
+            anyhow::bail!("No configured target mines {}", coin);
+        };
+        Ok((coin, targets.iter().map(|t| t.name.clone()).collect()))
+    }
+
+    /// Distribute a reward with the coin's scheme and credit miner balances
+    pub async fn distribute_reward(&self, reward: &Reward) -> Result<RoundSummary> {
+        let mut tx = self.pool.begin().await?;
+        let summary = self.distribute_reward_in(&mut tx, reward).await?;
+        tx.commit().await?;
+        Ok(summary)
+    }
+
+    /// [`Self::distribute_reward`] as part of a caller's transaction
+    pub async fn distribute_reward_in(
         &self,
-        miner_id: i32,
-        coin: &str,
-        since: DateTime<Utc>,
-    ) -> Result<f64> {
-        // Get total valid shares for this coin since last calculation
-        let miner_shares: f64 = sqlx::query_scalar(
+        tx: &mut Transaction<'_, Postgres>,
+        reward: &Reward,
+    ) -> Result<RoundSummary> {
+        if reward.amount <= Decimal::ZERO {
+            anyhow::bail!("Reward amount must be positive, got {}", reward.amount);
+        }
+
+        let (coin, target_names) = self.coin_targets(&reward.coin)?;
+        if !money::is_exact(reward.amount, &coin) {
+            anyhow::bail!("Reward {} has more decimals than {} has", reward.amount, coin);
+        }
+        let coin_scheme = self.schemes.for_coin(&coin);
+
+        let solo_finder = match (reward.source, &reward.finder) {
+            (RewardSource::Block, Some(finder)) => self.solo_miner(tx, finder, coin_scheme).await?,
+            _ => None,
+        };
+        let scheme = match (solo_finder, coin_scheme) {
+            (Some(_), _) => RewardScheme::Solo,
+            (None, RewardScheme::Prop | RewardScheme::Pps | RewardScheme::Fpps) => coin_scheme,
+            // Rewards not found by a SOLO miner are shared like PPLNS
+            (None, RewardScheme::Pplns | RewardScheme::Solo) => RewardScheme::Pplns,
+        };
+
+        // PROP counts the shares since the previous shared round, the others the last N
+        let (after_share_id, limit) = match coin_scheme {
+            RewardScheme::Prop => {
+                let last: i64 = sqlx::query_scalar(
+                    r#"
+                    SELECT COALESCE(MAX(window_last_share_id), 0) FROM rounds
+                    WHERE coin = $1 AND scheme IN ('pplns', 'prop')
+                    "#,
+                )
+                .bind(&coin)
+                .fetch_one(&mut **tx)
+                .await?;
+                (last, None)
+            }
+            _ => (0, Some(self.window_shares)),
+        };
+
+        // A block only pays miners not mining solo, or the solo miner that found it
+        let rows = sqlx::query_as(
             r#"
-            SELECT COALESCE(SUM(difficulty), 0.0)::float8
-            FROM shares
-            WHERE miner_id = $1
-              AND target_name IN (
-                  SELECT name FROM unnest($2::text[]) AS name
-              )
-              AND valid = true
-              AND created_at > $3
+            WITH window_shares AS (
+                SELECT s.id, s.miner_id, s.difficulty, COALESCE(ps.reward_scheme, $5) AS scheme
+                FROM shares s
+                LEFT JOIN payout_settings ps ON ps.miner_id = s.miner_id
+                WHERE s.target_name = ANY($1)
+                  AND s.valid = true
+                  AND s.created_at <= $2
+                  AND s.id > $3
+                  AND ($6::int IS NULL OR s.miner_id = $6)
+                  AND NOT ($7 AND $6::int IS NULL AND COALESCE(ps.reward_scheme, $5) = 'solo')
+                ORDER BY s.id DESC
+                LIMIT $4
+            )
+            SELECT miner_id, scheme, SUM(difficulty)::float8, MIN(id), MAX(id), COUNT(*)
+            FROM window_shares
+            GROUP BY miner_id, scheme
             "#,
         )
-        .bind(miner_id)
-        .bind(&[coin]) // In production, map coin to target names
-        .bind(since)
-        .fetch_one(&self.pool)
+        .bind(&target_names)
+        .bind(reward.until)
+        .bind(after_share_id)
+        .bind(limit)
+        .bind(coin_scheme.as_str())
+        .bind(solo_finder)
+        .bind(reward.source == RewardSource::Block)
+        .fetch_all(&mut **tx)
         .await?;
+        let window = ShareWindow::from_rows(rows)?;
 
-        debug!(
-            "Miner {} has {} difficulty shares for {} since {}",
-            miner_id, miner_shares, coin, since
+        if window.weights.is_empty() {
+            anyhow::bail!("No shares for {} in the {} window, reward not distributed", coin, scheme.as_str());
+        }
+
+        let allocation = schemes::allocate_reward(reward.amount, self.fee_percent, &coin, &window.weights);
+        let round_id = record_round(
+            tx,
+            &coin,
+            scheme,
+            reward.source.as_str(),
+            reward.reference.as_deref(),
+            reward.amount,
+            &window,
+            &allocation,
+            reward.pending,
+        )
+        .await?;
+        let round_id = round_id.with_context(|| {
+            format!(
+                "{} reward {} was already distributed",
+                coin,
+                reward.reference.as_deref().unwrap_or_default()
+            )
+        })?;
+
+        let summary = summarize(round_id, scheme, &window, &allocation);
+        info!(
+            "Round {}: distributed {} {} ({}, {}) over {} shares to {} miners, fee {}, reserve {:+}",
+            round_id,
+            reward.amount,
+            coin,
+            reward.source.as_str(),
+            scheme.as_str(),
+            window.shares,
+            summary.miners,
+            summary.fee,
+            summary.reserve
         );
+        Ok(summary)
+    }
 
-        // TODO: Calculate actual earnings based on:
-        // 1. Block rewards found
-        // 2. Pool fees
-        // 3. Share of total pool hashrate
-        // For now, return a placeholder
-        Ok(miner_shares * 0.0001) // Placeholder calculation
+    /// Miner with this wallet if they mine solo
+    async fn solo_miner(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        wallet_address: &str,
+        coin_scheme: RewardScheme,
+    ) -> Result<Option<i32>> {
+        let miner_id = sqlx::query_scalar(
+            r#"
+            SELECT m.id FROM miners m
+            LEFT JOIN payout_settings ps ON ps.miner_id = m.id
+            WHERE m.wallet_address = $1
+              AND COALESCE(ps.reward_scheme, $2) = 'solo'
+            "#,
+        )
+        .bind(wallet_address)
+        .bind(coin_scheme.as_str())
+        .fetch_optional(&mut **tx)
+        .await?;
+        Ok(miner_id)
     }
 
-    /// Update all miner balances for a specific coin
-    pub async fn update_all_balances(&self, coin: &str) -> Result<()> {
-        info!("Updating balances for coin: {}", coin);
+    /// Pay PPS and FPPS miners of every coin for the shares submitted since
+    /// the previous run
+    pub async fn credit_share_payments(&self) -> Result<Vec<RoundSummary>> {
+        let mut coins: Vec<&str> = self.targets.iter().map(|t| t.coin.as_str()).collect();
+        coins.sort_unstable();
+        coins.dedup();
 
-        // Get all miners with shares for this coin
-        let miners: Vec<(i32, DateTime<Utc>)> = sqlx::query_as(
-            r#"
-            SELECT DISTINCT m.id, COALESCE(b.updated_at, m.created_at) as last_update
-            FROM miners m
-            LEFT JOIN balances b ON b.miner_id = m.id AND b.coin = $1
-            WHERE EXISTS (
-                SELECT 1 FROM shares s
-                WHERE s.miner_id = m.id
-                  AND s.valid = true
-                  AND s.created_at > COALESCE(b.updated_at, m.created_at)
+        let mut rounds = Vec::new();
+        for coin in coins {
+            let paid = self
+                .credit_coin_share_payments(coin)
+                .await
+                .with_context(|| format!("Failed to pay {} shares", coin))?;
+            rounds.extend(paid);
+        }
+        Ok(rounds)
+    }
+
+    async fn credit_coin_share_payments(&self, coin: &str) -> Result<Vec<RoundSummary>> {
+        let (coin, target_names) = self.coin_targets(coin)?;
+        let coin_scheme = self.schemes.for_coin(&coin);
+
+        let mut tx = self.pool.begin().await?;
+
+        // The cursor row also keeps concurrent runs from paying a share twice
+        let cursor: Option<i64> = sqlx::query_scalar(
+            "SELECT last_share_id FROM share_cursors WHERE coin = $1 FOR UPDATE"
+        )
+        .bind(&coin)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let upper: i64 = sqlx::query_scalar(
+            "SELECT COALESCE(MAX(id), 0) FROM shares WHERE created_at < $1"
+        )
+        .bind(Utc::now() - Duration::seconds(SHARE_SETTLE_SECS))
+        .fetch_one(&mut *tx)
+        .await?;
+
+        let Some(cursor) = cursor else {
+            // First run for this coin: shares from before it are not owed per share
+            sqlx::query(
+                "INSERT INTO share_cursors (coin, last_share_id) VALUES ($1, $2) ON CONFLICT (coin) DO NOTHING"
             )
+            .bind(&coin)
+            .bind(upper)
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await?;
+            return Ok(Vec::new());
+        };
+        if upper <= cursor {
+            return Ok(Vec::new());
+        }
+
+        let rows = sqlx::query_as(
+            r#"
+            SELECT s.miner_id, COALESCE(ps.reward_scheme, $2) AS scheme,
+                   SUM(s.difficulty)::float8, MIN(s.id), MAX(s.id), COUNT(*)
+            FROM shares s
+            LEFT JOIN payout_settings ps ON ps.miner_id = s.miner_id
+            WHERE s.target_name = ANY($1)
+              AND s.valid = true
+              AND s.id > $3
+              AND s.id <= $4
+              AND COALESCE(ps.reward_scheme, $2) IN ('pps', 'fpps')
+            GROUP BY 1, 2
             "#,
         )
-        .bind(coin)
-        .fetch_all(&self.pool)
+        .bind(&target_names)
+        .bind(coin_scheme.as_str())
+        .bind(cursor)
+        .bind(upper)
+        .fetch_all(&mut *tx)
         .await?;
+        let window = ShareWindow::from_rows(rows)?;
 
-        for (miner_id, last_update) in miners {
-            let earned = self.calculate_miner_balance(miner_id, coin, last_update).await?;
+        let mut rounds = Vec::new();
+        if !window.weights.is_empty() {
+            let network_difficulty = self.difficulty_provider.get_difficulty(&coin).await?;
+            let block_reward = money::from_estimate(self.difficulty_provider.get_block_reward(&coin).await?)?;
 
-            if earned > 0.0 {
-                // Update or insert balance
-                sqlx::query(
-                    r#"
-                    INSERT INTO balances (miner_id, coin, balance, updated_at)
-                    VALUES ($1, $2, $3, NOW())
-                    ON CONFLICT (miner_id, coin)
-                    DO UPDATE SET
-                        balance = balances.balance + $3,
-                        updated_at = NOW()
-                    "#,
-                )
-                .bind(miner_id)
-                .bind(coin)
-                .bind(earned)
-                .execute(&self.pool)
-                .await?;
+            for scheme in [RewardScheme::Pps, RewardScheme::Fpps] {
+                let weights: Vec<WindowWeight> = window
+                    .weights
+                    .iter()
+                    .filter(|w| w.scheme == scheme)
+                    .cloned()
+                    .collect();
+                if weights.is_empty() {
+                    continue;
+                }
+
+                let block_value = match scheme {
+                    RewardScheme::Fpps => block_reward + self.average_block_fees(&mut tx, &coin, block_reward).await?,
+                    _ => block_reward,
+                };
+                let value = schemes::value_per_difficulty(network_difficulty, block_value)?;
+                let allocation = schemes::pay_per_share(value, self.schemes.pps_fee_percent, &coin, &weights);
+                let part = ShareWindow {
+                    first_share_id: window.first_share_id,
+                    last_share_id: window.last_share_id,
+                    shares: window.shares,
+                    difficulty: weights.iter().map(|w| w.difficulty).sum(),
+                    weights,
+                };
+                let gross = allocation.credited() + allocation.fee;
 
-                debug!("Updated balance for miner {}: +{} {}", miner_id, earned, coin);
+                let round_id = record_round(&mut tx, &coin, scheme, "shares", None, gross, &part, &allocation, false)
+                    .await?
+                    .context("Per-share round was not recorded")?;
+                let summary = summarize(round_id, scheme, &part, &allocation);
+                info!(
+                    "Round {}: paid {} {} ({}) for shares {}..={} to {} miners, fee {}",
+                    round_id, summary.credited, coin, scheme.as_str(), cursor + 1, upper, summary.miners, summary.fee
+                );
+                rounds.push(summary);
             }
         }
 
-        info!("Balance update complete for {}", coin);
-        Ok(())
+        sqlx::query("UPDATE share_cursors SET last_share_id = $2, updated_at = NOW() WHERE coin = $1")
+            .bind(&coin)
+            .bind(upper)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        Ok(rounds)
+    }
+
+    /// Average transaction fees of the coin's recent blocks, from the rewards
+    /// distributed for them
+    async fn average_block_fees(
+        &self,
+        tx: &mut Transaction<'_, Postgres>,
+        coin: &str,
+        block_reward: Decimal,
+    ) -> Result<Decimal> {
+        let average: Option<Decimal> = sqlx::query_scalar(
+            r#"
+            SELECT AVG(reward) FROM (
+                SELECT reward FROM rounds
+                WHERE coin = $1 AND source = 'block'
+                ORDER BY id DESC
+                LIMIT $2
+            ) recent
+            "#,
+        )
+        .bind(coin)
+        .bind(FPPS_FEE_ROUNDS)
+        .fetch_one(&mut **tx)
+        .await?;
+        Ok(average.map_or(Decimal::ZERO, |a| money::round_down((a - block_reward).max(Decimal::ZERO), coin)))
+    }
+
+    /// Reserve balance per coin: what rewards put in minus what PPS and FPPS
+    /// paid out. A negative balance is the pool's exposure.
+    pub async fn reserve_balances(&self) -> Result<Vec<(String, Decimal)>> {
+        let balances = sqlx::query_as(
+            "SELECT coin, SUM(amount) FROM reserve_entries GROUP BY coin ORDER BY coin"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(balances)
+    }
+}
+
+/// Record a round with its credits and reserve change, and post them to the
+/// ledger (miners' pending accounts when `pending`). Returns None if a round
+/// with the same reference exists.
+#[allow(clippy::too_many_arguments)]
+async fn record_round(
+    tx: &mut Transaction<'_, Postgres>,
+    coin: &str,
+    scheme: RewardScheme,
+    source: &str,
+    reference: Option<&str>,
+    reward: Decimal,
+    window: &ShareWindow,
+    allocation: &Allocation,
+    pending: bool,
+) -> Result<Option<i64>> {
+    let round_id: Option<i64> = sqlx::query_scalar(
+        r#"
+        INSERT INTO rounds (coin, scheme, source, reference, reward, fee,
+                            window_first_share_id, window_last_share_id, window_shares, window_difficulty)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+        ON CONFLICT (coin, source, reference) DO NOTHING
+        RETURNING id
+        "#,
+    )
+    .bind(coin)
+    .bind(scheme.as_str())
+    .bind(source)
+    .bind(reference)
+    .bind(reward)
+    .bind(allocation.fee)
+    .bind(window.first_share_id)
+    .bind(window.last_share_id)
+    .bind(window.shares)
+    .bind(window.difficulty)
+    .fetch_optional(&mut **tx)
+    .await?;
+    let Some(round_id) = round_id else {
+        return Ok(None);
+    };
+
+    let miner_ids: Vec<i32> = allocation.credits.iter().map(|c| c.0).collect();
+    let difficulties: Vec<f64> = allocation.credits.iter().map(|c| c.1).collect();
+    let amounts: Vec<Decimal> = allocation.credits.iter().map(|c| c.2).collect();
+
+    sqlx::query(
+        r#"
+        INSERT INTO round_credits (round_id, miner_id, difficulty, amount)
+        SELECT $1, t.miner_id, t.difficulty, t.amount
+        FROM UNNEST($2::int[], $3::float8[], $4::numeric[]) AS t(miner_id, difficulty, amount)
+        "#,
+    )
+    .bind(round_id)
+    .bind(&miner_ids)
+    .bind(&difficulties)
+    .bind(&amounts)
+    .execute(&mut **tx)
+    .await?;
+
+    // Rewards pay the credits, the fee and the reserve refill; per-share
+    // payments come from the reserve and their fee is never paid out
+    let fee = if source == "shares" { Decimal::ZERO } else { allocation.fee };
+    let mut transfer = Transfer::new(coin)
+        .round(round_id)
+        .memo(format!("{} round ({})", scheme.as_str(), source))
+        .entry(Account::Rewards, EntryKind::Credit, -(allocation.credited() + fee + allocation.reserve))
+        .entry(Account::Fees, EntryKind::Fee, fee)
+        .entry(Account::Reserve, EntryKind::Reserve, allocation.reserve);
+    for (miner_id, _, amount) in &allocation.credits {
+        let account = if pending { Account::Pending(*miner_id) } else { Account::Available(*miner_id) };
+        transfer = transfer.entry(account, EntryKind::Credit, *amount);
+    }
+    ledger::post(tx, &transfer).await?;
+
+    if !allocation.reserve.is_zero() {
+        sqlx::query("INSERT INTO reserve_entries (coin, amount, round_id) VALUES ($1, $2, $3)")
+            .bind(coin)
+            .bind(allocation.reserve)
+            .bind(round_id)
+            .execute(&mut **tx)
+            .await?;
+    }
+
+    for (miner_id, _, amount) in &allocation.credits {
+        debug!("Round {}: credited miner {} with {} {}", round_id, miner_id, amount, coin);
+    }
+    Ok(Some(round_id))
+}
+
+fn summarize(round_id: i64, scheme: RewardScheme, window: &ShareWindow, allocation: &Allocation) -> RoundSummary {
+    RoundSummary {
+        round_id,
+        scheme,
+        fee: allocation.fee,
+        credited: allocation.credited(),
+        reserve: allocation.reserve,
+        miners: allocation.credits.len(),
+        window_shares: window.shares,
     }
 }
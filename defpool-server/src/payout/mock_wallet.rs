@@ -0,0 +1,66 @@
+//! JSON-RPC wallet server for testing payout senders
+
+use axum::{extract::State, http::StatusCode, routing::post, Json, Router};
+use serde_json::{json, Value};
+use std::sync::{Arc, Mutex};
+
+/// Answers a method call with a result or an RPC error code and message
+type Responder = dyn Fn(&str, &Value) -> Result<Value, (i32, String)> + Send + Sync;
+
+#[derive(Clone)]
+struct MockState {
+    responder: Arc<Responder>,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+/// Wallet on a local port that answers every call with a responder and
+/// keeps the raw request bodies. RPC errors come back with HTTP 500, as
+/// Bitcoin Core sends them.
+pub struct MockWallet {
+    url: String,
+    requests: Arc<Mutex<Vec<String>>>,
+}
+
+impl MockWallet {
+    pub async fn start(
+        responder: impl Fn(&str, &Value) -> Result<Value, (i32, String)> + Send + Sync + 'static,
+    ) -> Self {
+        let state = MockState {
+            responder: Arc::new(responder),
+            requests: Arc::new(Mutex::new(Vec::new())),
+        };
+        let requests = state.requests.clone();
+        let app = Router::new().route("/", post(handle)).with_state(state);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        Self { url, requests }
+    }
+
+    pub fn url(&self) -> String {
+        self.url.clone()
+    }
+
+    /// Raw bodies of the requests received so far
+    pub fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+async fn handle(State(state): State<MockState>, body: String) -> (StatusCode, Json<Value>) {
+    state.requests.lock().unwrap().push(body.clone());
+
+    let request: Value = serde_json::from_str(&body).unwrap_or_default();
+    let method = request["method"].as_str().unwrap_or_default();
+    match (state.responder)(method, &request["params"]) {
+        Ok(result) => (StatusCode::OK, Json(json!({ "id": request["id"], "result": result, "error": null }))),
+        Err((code, message)) => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(json!({ "id": request["id"], "result": null, "error": { "code": code, "message": message } })),
+        ),
+    }
+}
@@ -1,5 +1,12 @@
 pub mod service;
 pub mod calculator;
+pub mod schemes;
+pub mod sender;
+pub mod scheduler;
+#[cfg(test)]
+mod mock_wallet;
 
-pub use service::PayoutService;
-pub use calculator::BalanceCalculator;
+pub use service::{PayoutService, RefusedPayout};
+pub use calculator::{BalanceCalculator, Reward, RewardSource};
+pub use schemes::RewardScheme;
+pub use scheduler::PayoutScheduler;
@@ -0,0 +1,182 @@
+use super::service::create_payout;
+use crate::config::{ExchangeConfig, PayoutSchedule, PayoutsConfig};
+use crate::money::Decimal;
+use anyhow::Result;
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use sqlx::{FromRow, PgPool};
+use tracing::{debug, info};
+
+/// A miner balance over its payout threshold
+#[derive(Debug, FromRow)]
+struct DueBalance {
+    miner_id: i32,
+    balance: Decimal,
+}
+
+/// Creates payouts for miners whose balance reached their threshold.
+///
+/// A miner's `min_payout_threshold` applies when they have payout settings,
+/// otherwise the coin's `default_threshold`. Each coin is scheduled on its own
+/// cadence; miners with a payout still pending or signing are skipped, as
+/// are balances the exchange converts for auto-exchange miners.
+pub struct PayoutScheduler {
+    pool: PgPool,
+    config: PayoutsConfig,
+    /// Coins with a payout wallet
+    coins: Vec<String>,
+    /// Coins sold on the exchange and the coin they are sold for
+    converted: Vec<String>,
+    quote: Option<String>,
+}
+
+impl PayoutScheduler {
+    pub fn new(pool: PgPool, config: PayoutsConfig, mut coins: Vec<String>, exchange: Option<&ExchangeConfig>) -> Self {
+        coins.sort();
+        Self {
+            pool,
+            config,
+            coins,
+            converted: exchange.map(ExchangeConfig::converted_coins).unwrap_or_default(),
+            quote: exchange.map(|e| e.quote.clone()),
+        }
+    }
+
+    /// Create the payouts due now. Returns how many were created.
+    pub async fn run(&self) -> Result<usize> {
+        if let Some(reason) = paused(&self.pool).await? {
+            debug!("Payouts paused ({}), not scheduling", reason);
+            return Ok(0);
+        }
+
+        let now = Utc::now();
+        let mut created = 0;
+        for coin in &self.coins {
+            created += self.run_coin(coin, now).await?;
+        }
+        Ok(created)
+    }
+
+    async fn run_coin(&self, coin: &str, now: DateTime<Utc>) -> Result<usize> {
+        let mut tx = self.pool.begin().await?;
+
+        // The run row also keeps concurrent schedulers from paying a balance twice
+        sqlx::query("INSERT INTO payout_schedule_runs (coin, last_run_at) VALUES ($1, NULL) ON CONFLICT DO NOTHING")
+            .bind(coin)
+            .execute(&mut *tx)
+            .await?;
+        let last_run: Option<DateTime<Utc>> = sqlx::query_scalar(
+            "SELECT last_run_at FROM payout_schedule_runs WHERE coin = $1 FOR UPDATE"
+        )
+        .bind(coin)
+        .fetch_one(&mut *tx)
+        .await?;
+        if !is_due(self.config.schedule_for(coin), last_run, now) {
+            return Ok(0);
+        }
+
+        let due: Vec<DueBalance> = sqlx::query_as(
+            r#"
+            SELECT b.miner_id, b.balance
+            FROM balances b
+            LEFT JOIN payout_settings ps ON ps.miner_id = b.miner_id
+            WHERE b.coin = $1
+              AND b.balance > 0
+              AND b.balance >= COALESCE(ps.min_payout_threshold, $2)
+              AND NOT (b.coin = ANY($3) AND COALESCE(ps.auto_exchange AND ps.payout_coin = $4 AND ps.payout_address IS NOT NULL, false))
+              AND NOT EXISTS (
+                  SELECT 1 FROM payouts p
+                  WHERE p.miner_id = b.miner_id AND p.coin = b.coin
+                    AND p.status IN ('pending', 'signing', 'review')
+              )
+            ORDER BY b.miner_id
+            FOR UPDATE OF b
+            "#,
+        )
+        .bind(coin)
+        .bind(self.config.default_threshold.get(coin).copied())
+        .bind(&self.converted)
+        .bind(&self.quote)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for balance in &due {
+            create_payout(&mut tx, balance.miner_id, coin, balance.balance, "automatic payout").await?;
+        }
+
+        sqlx::query("UPDATE payout_schedule_runs SET last_run_at = $2 WHERE coin = $1")
+            .bind(coin)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        if !due.is_empty() {
+            let total: Decimal = due.iter().map(|b| b.balance).sum();
+            info!("Scheduled {} automatic {} payouts, {} in total", due.len(), coin, total);
+        }
+        Ok(due.len())
+    }
+}
+
+/// Whether a coin's payouts are due: continuous coins on every run, hourly and
+/// daily ones once per UTC hour or day
+fn is_due(schedule: PayoutSchedule, last_run: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+    let period = match schedule {
+        PayoutSchedule::Continuous => return true,
+        PayoutSchedule::Hourly => TimeDelta::hours(1),
+        PayoutSchedule::Daily => TimeDelta::days(1),
+    };
+    match (last_run, now.duration_trunc(period)) {
+        (Some(last_run), Ok(start)) => last_run < start,
+        _ => true,
+    }
+}
+
+/// Reason payouts are paused, None while they run
+pub async fn paused(pool: &PgPool) -> Result<Option<String>> {
+    let control: Option<(bool, Option<String>)> =
+        sqlx::query_as("SELECT paused, reason FROM payout_control")
+            .fetch_optional(pool)
+            .await?;
+    Ok(match control {
+        Some((true, reason)) => Some(reason.unwrap_or_else(|| "no reason given".to_string())),
+        _ => None,
+    })
+}
+
+/// Pause or resume automatic payouts and sending, pool-wide
+pub async fn set_paused(pool: &PgPool, paused: bool, reason: Option<&str>) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO payout_control (id, paused, reason, updated_at)
+        VALUES (true, $1, $2, NOW())
+        ON CONFLICT (id) DO UPDATE SET paused = $1, reason = $2, updated_at = NOW()
+        "#,
+    )
+    .bind(paused)
+    .bind(reason)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> DateTime<Utc> {
+        time.parse().unwrap()
+    }
+
+    #[test]
+    fn test_schedule_periods() {
+        let now = at("2025-10-01T12:30:00Z");
+
+        assert!(is_due(PayoutSchedule::Continuous, Some(now), now));
+        assert!(!is_due(PayoutSchedule::Hourly, Some(at("2025-10-01T12:05:00Z")), now));
+        assert!(is_due(PayoutSchedule::Hourly, Some(at("2025-10-01T11:59:00Z")), now));
+        assert!(!is_due(PayoutSchedule::Daily, Some(at("2025-10-01T00:00:00Z")), now));
+        assert!(is_due(PayoutSchedule::Daily, Some(at("2025-09-30T23:00:00Z")), now));
+        assert!(is_due(PayoutSchedule::Daily, None, now));
+    }
+}
@@ -0,0 +1,215 @@
+use crate::money::{self, Decimal};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// How miners are paid for their shares
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RewardScheme {
+    /// Pay Per Last N Shares: rewards are split over the last N shares
+    Pplns,
+    /// Pay Per Share: every share is paid its expected value from the pool reserve
+    Pps,
+    /// Full Pay Per Share: PPS including average transaction fees
+    Fpps,
+    /// Proportional: rewards are split over the shares since the previous reward
+    Prop,
+    /// The miner that finds a block gets all of it
+    Solo,
+}
+
+impl RewardScheme {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pplns => "pplns",
+            Self::Pps => "pps",
+            Self::Fpps => "fpps",
+            Self::Prop => "prop",
+            Self::Solo => "solo",
+        }
+    }
+
+    /// Paid per share from the reserve rather than from rewards
+    pub fn is_per_share(&self) -> bool {
+        matches!(self, Self::Pps | Self::Fpps)
+    }
+
+    /// Schemes a miner may pick over their coin's scheme. PPLNS and PROP
+    /// windows are shared by all miners of a coin, so those stay per coin.
+    pub fn is_miner_selectable(&self) -> bool {
+        matches!(self, Self::Pps | Self::Fpps | Self::Solo)
+    }
+}
+
+impl FromStr for RewardScheme {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        match s {
+            "pplns" => Ok(Self::Pplns),
+            "pps" => Ok(Self::Pps),
+            "fpps" => Ok(Self::Fpps),
+            "prop" => Ok(Self::Prop),
+            "solo" => Ok(Self::Solo),
+            _ => anyhow::bail!("Unknown reward scheme: {}", s),
+        }
+    }
+}
+
+/// A miner's difficulty in a reward window and the scheme that pays them
+#[derive(Debug, Clone)]
+pub struct WindowWeight {
+    pub miner_id: i32,
+    pub scheme: RewardScheme,
+    pub difficulty: f64,
+}
+
+/// How a reward or a run of per-share payments is divided. The credits, fee
+/// and reserve change add up exactly to what was divided.
+#[derive(Debug, Default)]
+pub struct Allocation {
+    /// Miner, difficulty and amount credited
+    pub credits: Vec<(i32, f64, Decimal)>,
+    pub fee: Decimal,
+    /// Change of the pool reserve; negative when it paid miners
+    pub reserve: Decimal,
+}
+
+impl Allocation {
+    pub fn credited(&self) -> Decimal {
+        self.credits.iter().map(|c| c.2).sum()
+    }
+}
+
+/// Split a reward over a share window by difficulty.
+///
+/// Miners paid per share already got their shares' value from the reserve, so
+/// their part of the reward refills the reserve instead. The rest is credited
+/// after the fee; parts and credits are rounded down to the coin's atomic unit
+/// and the remainder is kept with the fee.
+pub fn allocate_reward(amount: Decimal, fee_percent: Decimal, coin: &str, window: &[WindowWeight]) -> Allocation {
+    let total: f64 = window.iter().map(|w| w.difficulty).sum();
+    if total <= 0.0 {
+        return Allocation { reserve: amount, ..Default::default() };
+    }
+
+    let mut allocation = Allocation::default();
+    for weight in window {
+        let ratio = Decimal::try_from(weight.difficulty / total).unwrap_or_default();
+        let part = money::round_down(amount * ratio, coin);
+        if weight.scheme.is_per_share() {
+            allocation.reserve += part;
+        } else {
+            let credit = money::round_down(part - part * fee_percent / Decimal::ONE_HUNDRED, coin);
+            allocation.credits.push((weight.miner_id, weight.difficulty, credit));
+        }
+    }
+    allocation.fee = amount - allocation.reserve - allocation.credited();
+    allocation
+}
+
+/// Pay shares their expected value, `value_per_difficulty` per unit of
+/// share difficulty, from the reserve after the fee
+pub fn pay_per_share(value_per_difficulty: Decimal, fee_percent: Decimal, coin: &str, window: &[WindowWeight]) -> Allocation {
+    let mut allocation = Allocation::default();
+    let mut gross = Decimal::ZERO;
+    for weight in window {
+        let difficulty = Decimal::try_from(weight.difficulty).unwrap_or_default();
+        let value = money::round_down(difficulty * value_per_difficulty, coin);
+        gross += value;
+        let credit = money::round_down(value - value * fee_percent / Decimal::ONE_HUNDRED, coin);
+        allocation.credits.push((weight.miner_id, weight.difficulty, credit));
+    }
+    allocation.fee = gross - allocation.credited();
+    allocation.reserve = -allocation.credited();
+    allocation
+}
+
+/// Expected value of one unit of share difficulty: the chance of it solving a
+/// block times what the block pays
+pub fn value_per_difficulty(network_difficulty: f64, block_value: Decimal) -> anyhow::Result<Decimal> {
+    let network_difficulty = money::from_estimate(network_difficulty)?;
+    block_value
+        .checked_div(network_difficulty)
+        .ok_or_else(|| anyhow::anyhow!("Invalid network difficulty {}", network_difficulty))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn weight(miner_id: i32, scheme: RewardScheme, difficulty: f64) -> WindowWeight {
+        WindowWeight { miner_id, scheme, difficulty }
+    }
+
+    fn dec(amount: &str) -> Decimal {
+        amount.parse().unwrap()
+    }
+
+    #[test]
+    fn test_reward_split_by_difficulty() {
+        let window = [weight(1, RewardScheme::Pplns, 3000.0), weight(2, RewardScheme::Pplns, 1000.0)];
+        let allocation = allocate_reward(dec("1"), dec("1"), "XMR", &window);
+
+        assert_eq!(allocation.credits[0].2, dec("0.7425"));
+        assert_eq!(allocation.credits[1].2, dec("0.2475"));
+        assert_eq!(allocation.fee, dec("0.01"));
+        assert_eq!(allocation.reserve, Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_reward_dust_kept_with_fee() {
+        let window = [
+            weight(1, RewardScheme::Pplns, 1.0),
+            weight(2, RewardScheme::Pplns, 1.0),
+            weight(3, RewardScheme::Pplns, 1.0),
+        ];
+        let allocation = allocate_reward(dec("0.00000010"), Decimal::ZERO, "LTC", &window);
+
+        assert!(allocation.credits.iter().all(|c| c.2 == dec("0.00000003")));
+        assert_eq!(allocation.fee, dec("0.00000001"));
+    }
+
+    #[test]
+    fn test_per_share_part_refills_reserve() {
+        let window = [weight(1, RewardScheme::Pplns, 1000.0), weight(2, RewardScheme::Pps, 3000.0)];
+        let allocation = allocate_reward(dec("2"), Decimal::ZERO, "XMR", &window);
+
+        assert_eq!(allocation.credits.len(), 1);
+        assert_eq!(allocation.credits[0].2, dec("0.5"));
+        assert_eq!(allocation.reserve, dec("1.5"));
+        assert_eq!(allocation.fee, Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_allocation_reconciles_exactly() {
+        let window = [
+            weight(1, RewardScheme::Pplns, 7.0),
+            weight(2, RewardScheme::Pps, 11.0),
+            weight(3, RewardScheme::Solo, 13.0),
+        ];
+        let amount = dec("0.600000000007");
+        let allocation = allocate_reward(amount, dec("1.5"), "XMR", &window);
+
+        assert_eq!(allocation.credited() + allocation.fee + allocation.reserve, amount);
+        assert!(allocation.credits.iter().all(|c| money::is_exact(c.2, "XMR")));
+    }
+
+    #[test]
+    fn test_pay_per_share_draws_reserve() {
+        // A share at the network difficulty is worth one block
+        let value = value_per_difficulty(1_000_000.0, dec("0.6")).unwrap();
+        let allocation = pay_per_share(value, dec("3"), "XMR", &[weight(1, RewardScheme::Pps, 1_000_000.0)]);
+
+        assert_eq!(allocation.credits[0].2, dec("0.582"));
+        assert_eq!(allocation.fee, dec("0.018"));
+        assert_eq!(allocation.reserve, -allocation.credited());
+    }
+
+    #[test]
+    fn test_only_per_share_and_solo_selectable() {
+        assert!(RewardScheme::Solo.is_miner_selectable());
+        assert!(!RewardScheme::Prop.is_miner_selectable());
+        assert_eq!("fpps".parse::<RewardScheme>().unwrap(), RewardScheme::Fpps);
+    }
+}
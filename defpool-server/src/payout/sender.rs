@@ -0,0 +1,510 @@
+use crate::config::{WalletConfig, WalletKind};
+use crate::money::{self, Decimal};
+use anyhow::Context;
+use async_trait::async_trait;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::debug;
+
+/// Wallet RPC calls that take longer than this are treated as outcome unknown
+const WALLET_TIMEOUT_SECS: u64 = 120;
+
+/// One recipient of a payout transaction
+#[derive(Debug, Clone, PartialEq)]
+pub struct Destination {
+    pub address: String,
+    pub amount: Decimal,
+}
+
+/// A transaction a wallet broadcast
+#[derive(Debug, Clone, PartialEq)]
+pub struct SentTransaction {
+    pub tx_hash: String,
+    /// Network fee the pool paid, if the wallet reported it
+    pub fee: Option<Decimal>,
+}
+
+/// A signed transaction that was not relayed yet
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreparedTransaction {
+    pub tx_hash: String,
+    /// Network fee the pool pays, if the wallet reported it
+    pub fee: Option<Decimal>,
+    /// What the transaction is relayed from: the transaction metadata of
+    /// Monero wallets, the raw transaction of Bitcoin Core style ones
+    pub raw: String,
+}
+
+impl PreparedTransaction {
+    pub fn sent(&self) -> SentTransaction {
+        SentTransaction { tx_hash: self.tx_hash.clone(), fee: self.fee }
+    }
+}
+
+/// Why a payout was not sent
+#[derive(Debug, thiserror::Error)]
+pub enum SendError {
+    /// The wallet refused the transaction; nothing was sent
+    #[error("wallet refused the transaction: {0}")]
+    Rejected(String),
+    /// The wallet could not be reached; nothing was sent
+    #[error("wallet unreachable: {0}")]
+    Unreachable(String),
+    /// The request may have reached the wallet; the transaction may or may
+    /// not have been broadcast
+    #[error("wallet outcome unknown: {0:#}")]
+    Unknown(anyhow::Error),
+}
+
+/// Sends payouts on chain from the pool's hot wallet of one coin.
+///
+/// A send takes two steps: [`PayoutSender::prepare`] signs a transaction
+/// without relaying it, so the caller can record its hash first, then
+/// [`PayoutSender::relay`] puts it on the network. A relay whose outcome is
+/// lost is retried with the very same transaction, which can only be mined
+/// once.
+#[async_trait]
+pub trait PayoutSender: Send + Sync {
+    /// Sign a transaction to one or more distinct addresses, without relaying it
+    async fn prepare(&self, destinations: &[Destination]) -> Result<PreparedTransaction, SendError>;
+
+    /// Relay a prepared transaction
+    async fn relay(&self, prepared: &PreparedTransaction) -> Result<(), SendError>;
+
+    /// Whether the wallet holds a transaction, i.e. it was relayed
+    async fn is_known(&self, tx_hash: &str) -> anyhow::Result<bool>;
+
+    /// Confirmations of a sent transaction; None once the wallet gave it up
+    /// as failed or conflicted
+    async fn confirmations(&self, tx_hash: &str) -> anyhow::Result<Option<u64>>;
+
+    /// Most destinations one transaction may carry
+    fn max_batch(&self) -> usize;
+}
+
+/// Sender for a configured wallet
+pub fn from_config(coin: &str, config: &WalletConfig) -> Arc<dyn PayoutSender> {
+    let rpc = WalletRpc::new(config);
+    let max_batch = config.max_batch.unwrap_or(config.kind.max_batch()).max(1);
+    match config.kind {
+        WalletKind::Monero => Arc::new(MoneroWalletSender { rpc, coin: coin.to_string(), max_batch }),
+        WalletKind::Bitcoin => Arc::new(BitcoinWalletSender { rpc, coin: coin.to_string(), max_batch }),
+    }
+}
+
+#[derive(Deserialize)]
+struct RpcResponse {
+    result: Option<Value>,
+    error: Option<RpcError>,
+}
+
+#[derive(Deserialize)]
+struct RpcError {
+    code: i32,
+    message: String,
+}
+
+/// JSON-RPC client of a wallet
+struct WalletRpc {
+    client: reqwest::Client,
+    url: String,
+    user: Option<String>,
+    password: Option<String>,
+}
+
+impl WalletRpc {
+    fn new(config: &WalletConfig) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: config.rpc_url.clone(),
+            user: config.rpc_user.clone(),
+            password: config.rpc_password.clone(),
+        }
+    }
+
+    /// Call a wallet method. Errors the wallet answered with are rejections;
+    /// a request that may have been processed without an answer is unknown.
+    async fn call(&self, method: &str, params: &Value) -> Result<Value, SendError> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": "defpool",
+            "method": method,
+            "params": params,
+        });
+        self.call_raw(method, request.to_string()).await
+    }
+
+    async fn call_raw(&self, method: &str, body: String) -> Result<Value, SendError> {
+        debug!("Wallet RPC call: {} to {}", method, self.url);
+
+        let mut request = self
+            .client
+            .post(&self.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .timeout(Duration::from_secs(WALLET_TIMEOUT_SECS));
+        if let (Some(user), Some(password)) = (&self.user, &self.password) {
+            request = request.basic_auth(user, Some(password));
+        }
+
+        let response = match request.send().await {
+            Ok(response) => response,
+            // Nothing reached the wallet
+            Err(e) if e.is_connect() || e.is_builder() => return Err(SendError::Unreachable(e.to_string())),
+            Err(e) => return Err(SendError::Unknown(e.into())),
+        };
+        let status = response.status();
+        let body = response.text().await.map_err(|e| SendError::Unknown(e.into()))?;
+
+        // Bitcoin Core answers RPC errors with HTTP 500 and an error object
+        match serde_json::from_str::<RpcResponse>(&body) {
+            Ok(RpcResponse { error: Some(error), .. }) => {
+                Err(SendError::Rejected(format!("{} error {}: {}", method, error.code, error.message)))
+            }
+            Ok(RpcResponse { result: Some(result), .. }) if status.is_success() => Ok(result),
+            _ if status.is_client_error() => Err(SendError::Rejected(format!("{} returned {}", method, status))),
+            _ => Err(SendError::Unknown(anyhow::anyhow!("{} returned {}: {}", method, status, body))),
+        }
+    }
+
+    /// Whether the wallet answers a lookup call successfully; an error it
+    /// answers with means it does not have what was looked up
+    async fn finds(&self, method: &str, params: &Value) -> anyhow::Result<bool> {
+        match self.call(method, params).await {
+            Ok(_) => Ok(true),
+            Err(SendError::Rejected(_)) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// monero-wallet-rpc: `transfer` in atomic units (piconero) with
+/// `do_not_relay`, then `relay_tx` with the transaction's metadata.
+pub struct MoneroWalletSender {
+    rpc: WalletRpc,
+    coin: String,
+    max_batch: usize,
+}
+
+#[derive(Deserialize)]
+struct MoneroTransfer {
+    tx_hash: String,
+    fee: u64,
+    tx_metadata: String,
+}
+
+struct MoneroDestination {
+    address: String,
+    amount: u64,
+}
+
+impl MoneroWalletSender {
+    fn destinations(&self, destinations: &[Destination]) -> anyhow::Result<Vec<MoneroDestination>> {
+        destinations
+            .iter()
+            .map(|d| Ok(MoneroDestination { address: d.address.clone(), amount: money::to_atomic(d.amount, &self.coin)? }))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl PayoutSender for MoneroWalletSender {
+    async fn prepare(&self, destinations: &[Destination]) -> Result<PreparedTransaction, SendError> {
+        let destinations: Vec<Value> = self
+            .destinations(destinations)
+            .map_err(|e| SendError::Rejected(e.to_string()))?
+            .into_iter()
+            .map(|d| json!({ "amount": d.amount, "address": d.address }))
+            .collect();
+        let params = json!({
+            "destinations": destinations,
+            "priority": 0,
+            "do_not_relay": true,
+            "get_tx_metadata": true,
+        });
+
+        let result = self.rpc.call("transfer", &params).await?;
+        let transfer: MoneroTransfer =
+            serde_json::from_value(result).map_err(|e| SendError::Unknown(e.into()))?;
+        Ok(PreparedTransaction {
+            tx_hash: transfer.tx_hash,
+            fee: Some(money::from_atomic(transfer.fee, &self.coin)),
+            raw: transfer.tx_metadata,
+        })
+    }
+
+    async fn relay(&self, prepared: &PreparedTransaction) -> Result<(), SendError> {
+        self.rpc.call("relay_tx", &json!({ "hex": prepared.raw })).await?;
+        Ok(())
+    }
+
+    async fn is_known(&self, tx_hash: &str) -> anyhow::Result<bool> {
+        self.rpc.finds("get_transfer_by_txid", &json!({ "txid": tx_hash })).await
+    }
+
+    async fn confirmations(&self, tx_hash: &str) -> anyhow::Result<Option<u64>> {
+        let result = self.rpc.call("get_transfer_by_txid", &json!({ "txid": tx_hash })).await?;
+        let transfer = &result["transfer"];
+        if transfer["type"] == "failed" {
+            return Ok(None);
+        }
+        Ok(Some(transfer["confirmations"].as_u64().unwrap_or(0)))
+    }
+
+    fn max_batch(&self) -> usize {
+        self.max_batch
+    }
+}
+
+/// Bitcoin Core style wallets (LTC, DOGE): `createrawtransaction`, funded
+/// with `fundrawtransaction` (its inputs locked against other sends) and
+/// signed by the wallet, then relayed with `sendrawtransaction`.
+pub struct BitcoinWalletSender {
+    rpc: WalletRpc,
+    coin: String,
+    max_batch: usize,
+}
+
+#[derive(Deserialize)]
+struct FundedTransaction {
+    hex: String,
+    fee: Decimal,
+}
+
+#[derive(Deserialize)]
+struct SignedTransaction {
+    hex: String,
+    complete: bool,
+}
+
+impl BitcoinWalletSender {
+    /// Call a method and read its result
+    async fn call_as<T: serde::de::DeserializeOwned>(&self, method: &str, params: Value) -> Result<T, SendError> {
+        let result = self.rpc.call(method, &params).await?;
+        serde_json::from_value(result).map_err(|e| SendError::Unknown(e.into()))
+    }
+
+    /// Sign with the wallet's keys; wallets older than
+    /// `signrawtransactionwithwallet` (Dogecoin 1.14) only have `signrawtransaction`
+    async fn sign(&self, hex: &str) -> Result<SignedTransaction, SendError> {
+        match self.call_as("signrawtransactionwithwallet", json!([hex])).await {
+            Err(SendError::Rejected(e)) if e.contains("-32601") => self.call_as("signrawtransaction", json!([hex])).await,
+            signed => signed,
+        }
+    }
+}
+
+#[async_trait]
+impl PayoutSender for BitcoinWalletSender {
+    async fn prepare(&self, destinations: &[Destination]) -> Result<PreparedTransaction, SendError> {
+        // Amounts go on the wire as exact JSON numbers, not through f64
+        let outputs = destinations
+            .iter()
+            .map(|d| Ok(format!("{}:{}", serde_json::to_string(&d.address)?, d.amount)))
+            .collect::<Result<Vec<_>, serde_json::Error>>()
+            .map_err(|e| SendError::Rejected(e.to_string()))?;
+        let body = format!(
+            r#"{{"jsonrpc":"1.0","id":"defpool","method":"createrawtransaction","params":[[],{{{}}}]}}"#,
+            outputs.join(",")
+        );
+        let unfunded = self.rpc.call_raw("createrawtransaction", body).await?;
+        let unfunded = unfunded
+            .as_str()
+            .ok_or_else(|| SendError::Unknown(anyhow::anyhow!("createrawtransaction returned {}", unfunded)))?;
+
+        let funded: FundedTransaction = self.call_as("fundrawtransaction", json!([unfunded, { "lockUnspents": true }])).await?;
+        let signed = self.sign(&funded.hex).await?;
+        if !signed.complete {
+            return Err(SendError::Rejected("the wallet could not sign every input".to_string()));
+        }
+        let decoded: Value = self.call_as("decoderawtransaction", json!([signed.hex])).await?;
+        let tx_hash = decoded["txid"]
+            .as_str()
+            .ok_or_else(|| SendError::Unknown(anyhow::anyhow!("decoderawtransaction returned no txid")))?
+            .to_string();
+
+        Ok(PreparedTransaction {
+            tx_hash,
+            fee: Some(money::round_down(funded.fee, &self.coin)),
+            raw: signed.hex,
+        })
+    }
+
+    async fn relay(&self, prepared: &PreparedTransaction) -> Result<(), SendError> {
+        self.rpc.call("sendrawtransaction", &json!([prepared.raw])).await?;
+        Ok(())
+    }
+
+    async fn is_known(&self, tx_hash: &str) -> anyhow::Result<bool> {
+        self.rpc.finds("gettransaction", &json!([tx_hash])).await
+    }
+
+    async fn confirmations(&self, tx_hash: &str) -> anyhow::Result<Option<u64>> {
+        let result = self.rpc.call("gettransaction", &json!([tx_hash])).await?;
+        // Negative while the transaction conflicts with the chain
+        let confirmations = result["confirmations"].as_i64().context("gettransaction returned no confirmations")?;
+        Ok(u64::try_from(confirmations).ok())
+    }
+
+    fn max_batch(&self) -> usize {
+        self.max_batch
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::payout::mock_wallet::MockWallet;
+
+    fn wallet(kind: WalletKind, url: String) -> WalletConfig {
+        WalletConfig { kind, rpc_url: url, rpc_user: None, rpc_password: None, max_batch: None }
+    }
+
+    fn to(address: &str, amount: &str) -> Destination {
+        Destination { address: address.to_string(), amount: amount.parse().unwrap() }
+    }
+
+    fn dec(amount: &str) -> Decimal {
+        amount.parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_monero_prepares_unrelayed_transfer_in_piconero() {
+        let mock = MockWallet::start(|method, _| match method {
+            "transfer" => Ok(json!({ "tx_hash": "c0ffee", "fee": 30_000_000u64, "tx_metadata": "meta" })),
+            "relay_tx" => Ok(json!({ "tx_hash": "c0ffee" })),
+            _ => Err((-32601, "Method not found".into())),
+        })
+        .await;
+        let sender = from_config("XMR", &wallet(WalletKind::Monero, mock.url()));
+
+        let prepared = sender.prepare(&[to("4Awallet", "0.01")]).await.unwrap();
+        sender.relay(&prepared).await.unwrap();
+
+        assert_eq!(prepared, PreparedTransaction { tx_hash: "c0ffee".into(), fee: Some(dec("0.00003")), raw: "meta".into() });
+        let transfer: Value = serde_json::from_str(&mock.requests()[0]).unwrap();
+        assert_eq!(transfer["params"]["destinations"][0], json!({ "amount": 10_000_000_000u64, "address": "4Awallet" }));
+        assert_eq!(transfer["params"]["do_not_relay"], true);
+        let relay: Value = serde_json::from_str(&mock.requests()[1]).unwrap();
+        assert_eq!(relay["params"], json!({ "hex": "meta" }));
+    }
+
+    #[tokio::test]
+    async fn test_bitcoin_prepares_signed_transaction_with_exact_amounts() {
+        let mock = MockWallet::start(|method, _| match method {
+            "createrawtransaction" => Ok(json!("unfunded")),
+            "fundrawtransaction" => Ok(json!({ "hex": "funded", "fee": 0.0000226, "changepos": 1 })),
+            "signrawtransactionwithwallet" => Ok(json!({ "hex": "signed", "complete": true })),
+            "decoderawtransaction" => Ok(json!({ "txid": "beef" })),
+            "sendrawtransaction" => Ok(json!("beef")),
+            _ => Err((-32601, "Method not found".into())),
+        })
+        .await;
+        let sender = from_config("DOGE", &wallet(WalletKind::Bitcoin, mock.url()));
+
+        let prepared = sender.prepare(&[to("DOne", "12345678.12345678"), to("DTwo", "0.1")]).await.unwrap();
+        sender.relay(&prepared).await.unwrap();
+
+        assert_eq!(prepared, PreparedTransaction { tx_hash: "beef".into(), fee: Some(dec("0.0000226")), raw: "signed".into() });
+        let requests = mock.requests();
+        assert!(requests[0].contains(r#""params":[[],{"DOne":12345678.12345678,"DTwo":0.1}]"#));
+        assert!(requests[1].contains(r#""lockUnspents":true"#));
+        assert!(requests[4].contains(r#""method":"sendrawtransaction","params":["signed"]"#));
+    }
+
+    #[tokio::test]
+    async fn test_bitcoin_signs_with_older_wallets() {
+        let mock = MockWallet::start(|method, _| match method {
+            "createrawtransaction" => Ok(json!("unfunded")),
+            "fundrawtransaction" => Ok(json!({ "hex": "funded", "fee": 0.01 })),
+            "signrawtransaction" => Ok(json!({ "hex": "signed", "complete": true })),
+            "decoderawtransaction" => Ok(json!({ "txid": "beef" })),
+            _ => Err((-32601, "Method not found".into())),
+        })
+        .await;
+        let sender = from_config("DOGE", &wallet(WalletKind::Bitcoin, mock.url()));
+
+        let prepared = sender.prepare(&[to("DWallet", "5")]).await.unwrap();
+
+        assert_eq!(prepared.raw, "signed");
+    }
+
+    #[tokio::test]
+    async fn test_wallet_error_is_a_rejection() {
+        let mock = MockWallet::start(|_, _| Err((-6, "Insufficient funds".into()))).await;
+        let sender = from_config("LTC", &wallet(WalletKind::Bitcoin, mock.url()));
+
+        let err = sender.prepare(&[to("ltc1wallet", "0.5")]).await.unwrap_err();
+
+        assert!(matches!(err, SendError::Rejected(message) if message.contains("Insufficient funds")));
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_wallet() {
+        let sender = from_config("LTC", &wallet(WalletKind::Bitcoin, "http://127.0.0.1:1".to_string()));
+
+        let err = sender.prepare(&[to("ltc1wallet", "0.5")]).await.unwrap_err();
+
+        assert!(matches!(err, SendError::Unreachable(_)));
+    }
+
+    #[tokio::test]
+    async fn test_monero_batch_in_one_transfer() {
+        let mock = MockWallet::start(|_, params| {
+            let outputs = params["destinations"].as_array().map_or(0, Vec::len);
+            Ok(json!({ "tx_hash": format!("tx{}", outputs), "fee": 90_000_000u64, "tx_metadata": "meta" }))
+        })
+        .await;
+        let sender = from_config("XMR", &wallet(WalletKind::Monero, mock.url()));
+
+        let prepared = sender.prepare(&[to("4Aone", "0.1"), to("4Atwo", "0.2"), to("4Athree", "0.3")]).await.unwrap();
+
+        assert_eq!(prepared.tx_hash, "tx3");
+        assert_eq!(mock.requests().len(), 1);
+        assert_eq!(sender.max_batch(), 15);
+    }
+
+    #[tokio::test]
+    async fn test_known_transactions() {
+        let monero = MockWallet::start(|_, params| match params["txid"].as_str() {
+            Some("relayed") => Ok(json!({ "transfer": { "type": "pending" } })),
+            _ => Err((-8, "Transaction not found.".into())),
+        })
+        .await;
+        let bitcoin = MockWallet::start(|_, params| match params[0].as_str() {
+            Some("relayed") => Ok(json!({ "confirmations": 0 })),
+            _ => Err((-5, "Invalid or non-wallet transaction id".into())),
+        })
+        .await;
+        let monero = from_config("XMR", &wallet(WalletKind::Monero, monero.url()));
+        let bitcoin = from_config("LTC", &wallet(WalletKind::Bitcoin, bitcoin.url()));
+
+        assert!(monero.is_known("relayed").await.unwrap());
+        assert!(!monero.is_known("lost").await.unwrap());
+        assert!(bitcoin.is_known("relayed").await.unwrap());
+        assert!(!bitcoin.is_known("lost").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_confirmations_of_dropped_transactions() {
+        let monero = MockWallet::start(|_, params| match params["txid"].as_str() {
+            Some("failed") => Ok(json!({ "transfer": { "type": "failed", "confirmations": 0 } })),
+            _ => Ok(json!({ "transfer": { "type": "out", "confirmations": 12 } })),
+        })
+        .await;
+        let bitcoin = MockWallet::start(|_, params| match params[0].as_str() {
+            Some("conflicted") => Ok(json!({ "confirmations": -3 })),
+            _ => Ok(json!({ "confirmations": 4 })),
+        })
+        .await;
+        let monero = from_config("XMR", &wallet(WalletKind::Monero, monero.url()));
+        let bitcoin = from_config("DOGE", &wallet(WalletKind::Bitcoin, bitcoin.url()));
+
+        assert_eq!(monero.confirmations("mined").await.unwrap(), Some(12));
+        assert_eq!(monero.confirmations("failed").await.unwrap(), None);
+        assert_eq!(bitcoin.confirmations("mined").await.unwrap(), Some(4));
+        assert_eq!(bitcoin.confirmations("conflicted").await.unwrap(), None);
+    }
+}
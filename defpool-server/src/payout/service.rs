@@ -1,16 +1,56 @@
+use super::sender::{Destination, PayoutSender, PreparedTransaction, SendError, SentTransaction};
+use super::{scheduler, RewardScheme};
 use crate::db::models::*;
+use crate::ledger::{self, Account, EntryKind, MinerEntry, Transfer};
+use crate::money::{self, Decimal};
 use anyhow::Result;
-use sqlx::PgPool;
-use tracing::{info, warn};
+use sqlx::{FromRow, PgPool, Postgres, Transaction};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use tracing::{error, info, warn};
+
+/// Most payouts of a coin sent per processor run
+const MAX_PAYOUTS_PER_RUN: i64 = 1000;
+
+/// Age of a `signing` payout before recovery looks its transaction up in the
+/// wallet, well past the wallet RPC timeout
+const RECOVER_AFTER_SECS: i64 = 600;
+
+/// A payout request the miner's balance or settings do not allow, with the reason
+#[derive(Debug, thiserror::Error)]
+#[error("{0}")]
+pub struct RefusedPayout(pub String);
+
+/// A payout waiting to be sent, with the address it goes to
+#[derive(Debug, FromRow)]
+struct QueuedPayout {
+    id: i64,
+    coin: String,
+    amount: Decimal,
+    address: String,
+}
+
+/// A payout whose send may have been lost, with the transaction recorded for it
+#[derive(Debug, FromRow)]
+struct SigningPayout {
+    id: i64,
+    tx_hash: Option<String>,
+    raw_tx: Option<String>,
+    fee: Option<Decimal>,
+}
 
 /// Service for managing payouts
 pub struct PayoutService {
     pool: PgPool,
+    /// Sender of each coin with a payout wallet
+    senders: HashMap<String, Arc<dyn PayoutSender>>,
+    /// Confirmations before a broadcast payout is confirmed
+    confirmations: u64,
 }
 
 impl PayoutService {
-    pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+    pub fn new(pool: PgPool, senders: HashMap<String, Arc<dyn PayoutSender>>, confirmations: u64) -> Self {
+        Self { pool, senders, confirmations }
     }
 
     /// Get miner's balance for a specific coin
@@ -67,10 +107,13 @@ impl PayoutService {
     pub async fn update_payout_settings(
         &self,
         wallet_address: &str,
-        min_threshold: f64,
+        min_threshold: Decimal,
         payout_coin: &str,
+        payout_address: Option<&str>,
         auto_exchange: bool,
+        reward_scheme: Option<RewardScheme>,
     ) -> Result<PayoutSettings> {
+
         // Get or create miner
         let miner: Miner = sqlx::query_as(
             "SELECT * FROM miners WHERE wallet_address = $1",
@@ -81,13 +124,15 @@ impl PayoutService {
 
         let settings = sqlx::query_as::<_, PayoutSettings>(
             r#"
-            INSERT INTO payout_settings (miner_id, min_payout_threshold, payout_coin, auto_exchange)
-            VALUES ($1, $2, $3, $4)
+            INSERT INTO payout_settings (miner_id, min_payout_threshold, payout_coin, auto_exchange, reward_scheme, payout_address)
+            VALUES ($1, $2, $3, $4, $5, $6)
             ON CONFLICT (miner_id)
             DO UPDATE SET
                 min_payout_threshold = $2,
                 payout_coin = $3,
                 auto_exchange = $4,
+                reward_scheme = $5,
+                payout_address = $6,
                 updated_at = NOW()
             RETURNING *
             "#,
@@ -96,86 +141,80 @@ impl PayoutService {
         .bind(min_threshold)
         .bind(payout_coin)
         .bind(auto_exchange)
+        .bind(reward_scheme.map(|s| s.as_str()))
+        .bind(payout_address)
         .fetch_one(&self.pool)
         .await?;
 
         Ok(settings)
     }
 
-    /// Request a payout
+    /// Request a payout. A request the balance or payout threshold does not
+    /// allow fails with a [`RefusedPayout`] error.
     pub async fn request_payout(&self, request: PayoutRequest) -> Result<Payout> {
         info!(
             "Payout requested: wallet={}, coin={}, amount={:?}",
             request.wallet_address, request.coin, request.amount
         );
 
+        // The balance is locked until the payout holds its amount, so concurrent
+        // requests cannot both spend it
+        let mut tx = self.pool.begin().await?;
+
+        let refused = |reason: String| Err(RefusedPayout(reason).into());
+
         // Get miner
-        let miner: Miner = sqlx::query_as(
+        let miner: Option<Miner> = sqlx::query_as(
             "SELECT * FROM miners WHERE wallet_address = $1",
         )
         .bind(&request.wallet_address)
-        .fetch_one(&self.pool)
+        .fetch_optional(&mut *tx)
         .await?;
+        let Some(miner) = miner else {
+            return refused(format!("Unknown miner {}", request.wallet_address));
+        };
 
         // Get balance
-        let balance = self.get_balance(&request.wallet_address, &request.coin).await?
-            .ok_or_else(|| anyhow::anyhow!("No balance found for {} {}", request.wallet_address, request.coin))?;
+        let balance: Option<Decimal> = sqlx::query_scalar(
+            "SELECT balance FROM balances WHERE miner_id = $1 AND coin = $2 FOR UPDATE",
+        )
+        .bind(miner.id)
+        .bind(&request.coin)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(balance) = balance else {
+            return refused(format!("No balance found for {} {}", request.wallet_address, request.coin));
+        };
 
         // Determine payout amount
-        let amount = request.amount.unwrap_or(balance.balance);
+        let amount = request.amount.unwrap_or(balance);
 
         // Validate amount
-        if amount <= 0.0 {
-            anyhow::bail!("Payout amount must be positive");
+        if amount <= Decimal::ZERO {
+            return refused("Payout amount must be positive".to_string());
+        }
+
+        if !money::is_exact(amount, &request.coin) {
+            return refused(format!("Payout amount {} has more decimals than {} has", amount, request.coin));
         }
 
-        if amount > balance.balance {
-            anyhow::bail!("Insufficient balance: requested {}, available {}", amount, balance.balance);
+        if amount > balance {
+            return refused(format!("Insufficient balance: requested {}, available {}", amount, balance));
         }
 
         // Check minimum threshold
         let settings = self.get_payout_settings(&request.wallet_address).await?;
         if let Some(settings) = settings {
             if amount < settings.min_payout_threshold {
-                anyhow::bail!(
+                return refused(format!(
                     "Amount {} below minimum threshold {}",
                     amount,
                     settings.min_payout_threshold
-                );
+                ));
             }
         }
 
-        // Start transaction
-        let mut tx = self.pool.begin().await?;
-
-        // Move balance to pending
-        sqlx::query(
-            r#"
-            UPDATE balances
-            SET balance = balance - $1,
-                pending_balance = pending_balance + $1
-            WHERE miner_id = $2 AND coin = $3
-            "#,
-        )
-        .bind(amount)
-        .bind(miner.id)
-        .bind(&request.coin)
-        .execute(&mut *tx)
-        .await?;
-
-        // Create payout record
-        let payout = sqlx::query_as::<_, Payout>(
-            r#"
-            INSERT INTO payouts (miner_id, coin, amount, status, created_at)
-            VALUES ($1, $2, $3, 'pending', NOW())
-            RETURNING *
-            "#,
-        )
-        .bind(miner.id)
-        .bind(&request.coin)
-        .bind(amount)
-        .fetch_one(&mut *tx)
-        .await?;
+        let payout = create_payout(&mut tx, miner.id, &request.coin, amount, "payout requested").await?;
 
         tx.commit().await?;
 
@@ -183,137 +222,286 @@ impl PayoutService {
         Ok(payout)
     }
 
-    /// Process pending payouts (called by background task)
+    /// Send pending payouts (called by background task). Each coin's
+    /// payouts are claimed with `FOR UPDATE SKIP LOCKED`, so concurrent
+    /// processors never send the same payout, and sent in batches of up to
+    /// its wallet's limit, one transaction per batch. Payouts of coins without
+    /// a payout wallet stay pending.
+    ///
+    /// A payout moves from `pending` to `signing` when claimed, then to
+    /// `broadcast` once its wallet sent it (the held amount is paid then) and
+    /// `confirmed` when deep enough; a payout the wallet refused is `failed`
+    /// and its amount released. A payout whose send cannot be settled from
+    /// the wallet goes to `review`, its amount held, for an operator.
     pub async fn process_pending_payouts(&self) -> Result<()> {
-        let pending_payouts: Vec<Payout> = sqlx::query_as(
-            "SELECT * FROM payouts WHERE status = 'pending' ORDER BY created_at ASC LIMIT 10",
-        )
-        .fetch_all(&self.pool)
-        .await?;
-
-        for payout in pending_payouts {
-            info!("Processing payout: id={}, amount={} {}", payout.id, payout.amount, payout.coin);
-
-            // Update status to processing
-            sqlx::query("UPDATE payouts SET status = 'processing' WHERE id = $1")
-                .bind(payout.id)
-                .execute(&self.pool)
-                .await?;
+        if let Some(reason) = scheduler::paused(&self.pool).await? {
+            info!("Payouts paused ({}), not sending", reason);
+            return Ok(());
+        }
 
-            // TODO: Implement actual blockchain transaction
-            // For now, simulate processing
-            match self.send_transaction(&payout).await {
-                Ok(tx_hash) => {
-                    self.complete_payout(payout.id, &tx_hash).await?;
-                }
-                Err(e) => {
-                    self.fail_payout(payout.id, &e.to_string()).await?;
-                }
+        for (coin, sender) in &self.senders {
+            for batch in self.claim(coin, sender.max_batch()).await? {
+                self.send_batch(sender.as_ref(), batch).await?;
             }
         }
 
         Ok(())
     }
 
-    /// Send blockchain transaction (placeholder)
-    async fn send_transaction(&self, payout: &Payout) -> Result<String> {
-        // TODO: Implement actual blockchain transaction
-        // This would involve:
-        // 1. Connecting to coin daemon RPC
-        // 2. Creating transaction
-        // 3. Signing transaction
-        // 4. Broadcasting transaction
-        // 5. Returning transaction hash
-
-        warn!("Transaction sending not implemented yet for payout {}", payout.id);
-        
-        // Simulate transaction
-        Ok(format!("simulated_tx_hash_{}", payout.id))
-    }
-
-    /// Mark payout as completed
-    async fn complete_payout(&self, payout_id: i64, tx_hash: &str) -> Result<()> {
+    /// Claim a coin's oldest pending payouts, batched, moving them to `signing`.
+    /// Payouts in a miner's payout coin go to their payout address, if set.
+    async fn claim(&self, coin: &str, max_batch: usize) -> Result<Vec<Vec<QueuedPayout>>> {
         let mut tx = self.pool.begin().await?;
 
-        // Update payout status
-        sqlx::query(
+        let pending_payouts: Vec<QueuedPayout> = sqlx::query_as(
             r#"
-            UPDATE payouts
-            SET status = 'completed',
-                tx_hash = $1,
-                completed_at = NOW()
-            WHERE id = $2
+            SELECT p.id, p.coin, p.amount,
+                   COALESCE(CASE WHEN ps.payout_coin = p.coin THEN ps.payout_address END, m.wallet_address) AS address
+            FROM payouts p
+            JOIN miners m ON m.id = p.miner_id
+            LEFT JOIN payout_settings ps ON ps.miner_id = p.miner_id
+            WHERE p.status = 'pending' AND p.coin = $1
+            ORDER BY p.created_at ASC
+            LIMIT $2
+            FOR UPDATE OF p SKIP LOCKED
             "#,
         )
-        .bind(tx_hash)
-        .bind(payout_id)
-        .execute(&mut *tx)
+        .bind(coin)
+        .bind(MAX_PAYOUTS_PER_RUN)
+        .fetch_all(&mut *tx)
         .await?;
 
-        // Get payout details
-        let payout: Payout = sqlx::query_as("SELECT * FROM payouts WHERE id = $1")
-            .bind(payout_id)
-            .fetch_one(&mut *tx)
-            .await?;
-
-        // Update balance (remove from pending, add to total_paid)
+        let ids: Vec<i64> = pending_payouts.iter().map(|p| p.id).collect();
         sqlx::query(
-            r#"
-            UPDATE balances
-            SET pending_balance = pending_balance - $1,
-                total_paid = total_paid + $1
-            WHERE miner_id = $2 AND coin = $3
-            "#,
+            "UPDATE payouts SET status = 'signing', tx_hash = NULL, signing_at = NOW(), error_message = NULL WHERE id = ANY($1)"
         )
-        .bind(payout.amount)
-        .bind(payout.miner_id)
-        .bind(&payout.coin)
+        .bind(&ids)
         .execute(&mut *tx)
         .await?;
+        let batches = batches(pending_payouts, max_batch);
 
         tx.commit().await?;
+        Ok(batches)
+    }
+
+    /// Send a batch in one transaction. The wallet signs it without relaying
+    /// it, its hash goes on the payouts and the transaction is recorded, and
+    /// only then is it relayed: a send is bound to its payouts before it can
+    /// reach the network. A batch the wallet refuses to sign is split in
+    /// halves and retried, down to single payouts that then fail; one it could
+    /// not sign goes back to pending. Nothing was relayed for either, so no
+    /// one is paid twice.
+    async fn send_batch(&self, sender: &dyn PayoutSender, batch: Vec<QueuedPayout>) -> Result<()> {
+        let ids: Vec<i64> = batch.iter().map(|p| p.id).collect();
+        info!("Processing {} payouts of {}: ids={:?}", batch.len(), batch[0].coin, ids);
+
+        let mut queue = vec![batch];
+        while let Some(mut batch) = queue.pop() {
+            let coin = batch[0].coin.clone();
+            let ids: Vec<i64> = batch.iter().map(|p| p.id).collect();
+            let destinations: Vec<Destination> = batch
+                .iter()
+                .map(|p| Destination { address: p.address.clone(), amount: p.amount })
+                .collect();
+
+            let prepared = match sender.prepare(&destinations).await {
+                Ok(prepared) => prepared,
+                Err(SendError::Rejected(e)) if batch.len() > 1 => {
+                    warn!("Batch of {} {} payouts refused, splitting it: {}", batch.len(), coin, e);
+                    let second = batch.split_off(batch.len() / 2);
+                    queue.push(second);
+                    queue.push(batch);
+                    continue;
+                }
+                Err(SendError::Rejected(e)) => {
+                    fail_payout(&self.pool, batch[0].id, &e).await?;
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Payouts {:?} not sent, requeueing them: {}", ids, e);
+                    requeue(&self.pool, &ids, &e.to_string()).await?;
+                    continue;
+                }
+            };
+
+            let mut tx = self.pool.begin().await?;
+            record_transaction(&mut tx, &coin, &prepared).await?;
+            sqlx::query("UPDATE payouts SET tx_hash = $1 WHERE id = ANY($2) AND status = 'signing'")
+                .bind(&prepared.tx_hash)
+                .bind(&ids)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+
+            match sender.relay(&prepared).await {
+                Ok(()) => {
+                    let mut tx = self.pool.begin().await?;
+                    broadcast(&mut tx, &ids, &prepared.sent()).await?;
+                    tx.commit().await?;
+                }
+                Err(SendError::Rejected(e)) => {
+                    // The wallet may still hold the transaction: only an operator can tell
+                    error!("{} payout transaction {} refused by the wallet, payouts {:?} held for review: {}", coin, prepared.tx_hash, ids, e);
+                    hold_for_review(&self.pool, &ids, &format!("relay refused: {}", e)).await?;
+                }
+                Err(e) => {
+                    // It may have been relayed: recovery relays the same transaction again
+                    error!("Payouts {:?} left signing until {} is recovered from the {} wallet: {}", ids, prepared.tx_hash, coin, e);
+                    sqlx::query("UPDATE payouts SET error_message = $1 WHERE id = ANY($2)")
+                        .bind(e.to_string())
+                        .bind(&ids)
+                        .execute(&self.pool)
+                        .await?;
+                }
+            }
+        }
 
-        info!("Payout completed: id={}, tx_hash={}", payout_id, tx_hash);
         Ok(())
     }
 
-    /// Mark payout as failed
-    async fn fail_payout(&self, payout_id: i64, error: &str) -> Result<()> {
-        let mut tx = self.pool.begin().await?;
+    /// Settle payouts left `signing` by a crash or a lost wallet answer. A
+    /// send without a recorded transaction was never relayed and goes back to
+    /// pending. A recorded transaction the wallet holds is broadcast; one it
+    /// does not is relayed again, the same transaction, so it is paid at most
+    /// once. A transaction that cannot be relayed again is held for review.
+    /// Only sends older than [`RECOVER_AFTER_SECS`] are checked, so a send
+    /// still in flight is left alone; runs on startup and with every
+    /// processor run.
+    ///
+    /// No row lock is held while the wallet is asked: each outcome is recorded
+    /// in its own transaction, only for payouts still signing in that send.
+    pub async fn recover_payouts(&self) -> Result<()> {
+        for (coin, sender) in &self.senders {
+            for (prepared, ids) in self.claim_stuck(coin).await? {
+                let relayed = match sender.is_known(&prepared.tx_hash).await {
+                    Ok(true) => Ok(()),
+                    Ok(false) => sender.relay(&prepared).await,
+                    Err(e) => {
+                        warn!("Failed to look {} payout transaction {} up: {:#}", coin, prepared.tx_hash, e);
+                        continue;
+                    }
+                };
+
+                match relayed {
+                    Ok(()) => {
+                        let mut tx = self.pool.begin().await?;
+                        let ids = still_signing(&mut tx, &ids, &prepared.tx_hash).await?;
+                        broadcast(&mut tx, &ids, &prepared.sent()).await?;
+                        tx.commit().await?;
+                        info!("Recovered payouts {:?}: sent in {}", ids, prepared.tx_hash);
+                    }
+                    Err(SendError::Rejected(e)) => {
+                        error!("{} payout transaction {} refused again, payouts {:?} held for review: {}", coin, prepared.tx_hash, ids, e);
+                        let mut tx = self.pool.begin().await?;
+                        let ids = still_signing(&mut tx, &ids, &prepared.tx_hash).await?;
+                        hold_for_review(&mut *tx, &ids, &format!("relay refused: {}", e)).await?;
+                        tx.commit().await?;
+                    }
+                    Err(e) => warn!("Failed to relay {} payout transaction {} again: {}", coin, prepared.tx_hash, e),
+                }
+            }
+        }
 
-        // Update payout status
-        sqlx::query(
-            "UPDATE payouts SET status = 'failed', error_message = $1 WHERE id = $2",
-        )
-        .bind(error)
-        .bind(payout_id)
-        .execute(&mut *tx)
-        .await?;
+        Ok(())
+    }
 
-        // Get payout details
-        let payout: Payout = sqlx::query_as("SELECT * FROM payouts WHERE id = $1")
-            .bind(payout_id)
-            .fetch_one(&mut *tx)
-            .await?;
+    /// Take a coin's stuck signing payouts for recovery. Sends that need no
+    /// wallet are settled right away; the others are returned by transaction,
+    /// their `signing_at` renewed so a concurrent recovery leaves them alone
+    /// while the wallet is asked.
+    async fn claim_stuck(&self, coin: &str) -> Result<Vec<(PreparedTransaction, Vec<i64>)>> {
+        let mut tx = self.pool.begin().await?;
 
-        // Return balance from pending
-        sqlx::query(
+        let stuck: Vec<SigningPayout> = sqlx::query_as(
             r#"
-            UPDATE balances
-            SET balance = balance + $1,
-                pending_balance = pending_balance - $1
-            WHERE miner_id = $2 AND coin = $3
+            SELECT p.id, p.tx_hash, t.raw_tx, t.fee
+            FROM payouts p
+            LEFT JOIN wallet_transactions t ON t.tx_hash = p.tx_hash
+            WHERE p.status = 'signing' AND p.coin = $1
+              AND p.signing_at < NOW() - make_interval(secs => $2)
+            ORDER BY p.id
+            FOR UPDATE OF p SKIP LOCKED
             "#,
         )
-        .bind(payout.amount)
-        .bind(payout.miner_id)
-        .bind(&payout.coin)
-        .execute(&mut *tx)
+        .bind(coin)
+        .bind(RECOVER_AFTER_SECS as f64)
+        .fetch_all(&mut *tx)
         .await?;
 
+        let mut sends: BTreeMap<Option<String>, Vec<SigningPayout>> = BTreeMap::new();
+        for payout in stuck {
+            sends.entry(payout.tx_hash.clone()).or_default().push(payout);
+        }
+
+        let mut claimed = Vec::new();
+        for (tx_hash, payouts) in sends {
+            let ids: Vec<i64> = payouts.iter().map(|p| p.id).collect();
+            let Some(tx_hash) = tx_hash else {
+                info!("Payouts {:?} were never relayed, requeueing them", ids);
+                requeue(&mut *tx, &ids, "never relayed, requeued").await?;
+                continue;
+            };
+            let Some(raw) = payouts[0].raw_tx.clone() else {
+                error!("{} payout transaction {} was not recorded, payouts {:?} held for review", coin, tx_hash, ids);
+                hold_for_review(&mut *tx, &ids, "transaction not recorded").await?;
+                continue;
+            };
+            claimed.push((PreparedTransaction { tx_hash, fee: payouts[0].fee, raw }, ids));
+        }
+
+        let claimed_ids: Vec<i64> = claimed.iter().flat_map(|(_, ids)| ids.iter().copied()).collect();
+        sqlx::query("UPDATE payouts SET signing_at = NOW() WHERE id = ANY($1)")
+            .bind(&claimed_ids)
+            .execute(&mut *tx)
+            .await?;
+
         tx.commit().await?;
+        Ok(claimed)
+    }
+
+    /// Confirm broadcast payouts whose transaction is deep enough. A
+    /// transaction the wallet gave up on is flagged for an operator; its
+    /// amount has left the wallet's books, but not necessarily the chain.
+    pub async fn confirm_payouts(&self) -> Result<()> {
+        for (coin, sender) in &self.senders {
+            let tx_hashes: Vec<String> = sqlx::query_scalar(
+                "SELECT DISTINCT tx_hash FROM payouts WHERE status = 'broadcast' AND coin = $1 AND tx_hash IS NOT NULL"
+            )
+            .bind(coin)
+            .fetch_all(&self.pool)
+            .await?;
+
+            for tx_hash in tx_hashes {
+                match sender.confirmations(&tx_hash).await {
+                    Ok(Some(confirmations)) if confirmations >= self.confirmations => {
+                        let confirmed = sqlx::query(
+                            r#"
+                            UPDATE payouts SET status = 'confirmed', completed_at = NOW(), error_message = NULL
+                            WHERE coin = $1 AND tx_hash = $2 AND status = 'broadcast'
+                            "#,
+                        )
+                        .bind(coin)
+                        .bind(&tx_hash)
+                        .execute(&self.pool)
+                        .await?;
+                        info!("Confirmed {} {} payouts in {}", confirmed.rows_affected(), coin, tx_hash);
+                    }
+                    Ok(Some(_)) => {}
+                    Ok(None) => {
+                        error!("{} payout transaction {} failed or conflicts, check the wallet", coin, tx_hash);
+                        sqlx::query(
+                            "UPDATE payouts SET error_message = 'transaction failed or conflicts' WHERE coin = $1 AND tx_hash = $2 AND status = 'broadcast'"
+                        )
+                        .bind(coin)
+                        .bind(&tx_hash)
+                        .execute(&self.pool)
+                        .await?;
+                    }
+                    Err(e) => warn!("Failed to check {} payout transaction {}: {:#}", coin, tx_hash, e),
+                }
+            }
+        }
 
-        warn!("Payout failed: id={}, error={}", payout_id, error);
         Ok(())
     }
 
@@ -335,4 +523,270 @@ impl PayoutService {
 
         Ok(payouts)
     }
+
+    /// Coins payouts can be sent in
+    pub fn payout_coins(&self) -> Vec<String> {
+        self.senders.keys().cloned().collect()
+    }
+
+    /// Ledger entries on a miner's accounts, newest first
+    pub async fn get_ledger(&self, wallet_address: &str, coin: Option<&str>, limit: i64) -> Result<Vec<MinerEntry>> {
+        ledger::miner_entries(&self.pool, wallet_address, coin, limit).await
+    }
+}
+
+/// Create a pending payout and hold its amount: available moves to pending
+/// until the payout is sent
+pub(super) async fn create_payout(
+    tx: &mut Transaction<'_, Postgres>,
+    miner_id: i32,
+    coin: &str,
+    amount: Decimal,
+    memo: &str,
+) -> Result<Payout> {
+    let payout = sqlx::query_as::<_, Payout>(
+        r#"
+        INSERT INTO payouts (miner_id, coin, amount, status, created_at)
+        VALUES ($1, $2, $3, 'pending', NOW())
+        RETURNING *
+        "#,
+    )
+    .bind(miner_id)
+    .bind(coin)
+    .bind(amount)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    let hold = Transfer::new(coin)
+        .payout(payout.id)
+        .memo(memo)
+        .transfer(Account::Available(miner_id), Account::Pending(miner_id), EntryKind::PayoutHold, amount);
+    ledger::post(tx, &hold).await?;
+
+    Ok(payout)
+}
+
+/// Settle payouts in review once an operator checked their wallet: with the
+/// transaction they were sent in they are broadcast, without one they were
+/// never sent and fail, their amounts released
+pub async fn settle_review(pool: &PgPool, payout_ids: &[i64], sent_in: Option<&str>) -> Result<()> {
+    let in_review: Vec<i64> = sqlx::query_scalar("SELECT id FROM payouts WHERE id = ANY($1) AND status = 'review' ORDER BY id")
+        .bind(payout_ids)
+        .fetch_all(pool)
+        .await?;
+    if let Some(id) = payout_ids.iter().find(|id| !in_review.contains(id)) {
+        anyhow::bail!("Payout {} is not in review", id);
+    }
+
+    match sent_in {
+        Some(tx_hash) => {
+            let fee: Option<Decimal> = sqlx::query_scalar("SELECT fee FROM wallet_transactions WHERE tx_hash = $1")
+                .bind(tx_hash)
+                .fetch_optional(pool)
+                .await?
+                .flatten();
+            let mut tx = pool.begin().await?;
+            broadcast(&mut tx, payout_ids, &SentTransaction { tx_hash: tx_hash.to_string(), fee }).await?;
+            tx.commit().await?;
+        }
+        None => {
+            for &id in payout_ids {
+                fail_payout(pool, id, "not sent, settled by an operator").await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Record a transaction signed for a payout or deposit before it is relayed
+pub(crate) async fn record_transaction(
+    tx: &mut Transaction<'_, Postgres>,
+    coin: &str,
+    prepared: &PreparedTransaction,
+) -> Result<()> {
+    sqlx::query("INSERT INTO wallet_transactions (tx_hash, coin, raw_tx, fee) VALUES ($1, $2, $3, $4) ON CONFLICT (tx_hash) DO NOTHING")
+        .bind(&prepared.tx_hash)
+        .bind(coin)
+        .bind(&prepared.raw)
+        .bind(prepared.fee)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+/// Lock the payouts still signing in `tx_hash`, leaving out any settled
+/// since they were claimed
+async fn still_signing(tx: &mut Transaction<'_, Postgres>, payout_ids: &[i64], tx_hash: &str) -> Result<Vec<i64>> {
+    let ids = sqlx::query_scalar(
+        "SELECT id FROM payouts WHERE id = ANY($1) AND status = 'signing' AND tx_hash = $2 ORDER BY id FOR UPDATE"
+    )
+    .bind(payout_ids)
+    .bind(tx_hash)
+    .fetch_all(&mut **tx)
+    .await?;
+    Ok(ids)
+}
+
+/// Put signing payouts that were never relayed back to pending
+async fn requeue<'e>(executor: impl sqlx::PgExecutor<'e>, payout_ids: &[i64], reason: &str) -> Result<()> {
+    sqlx::query(
+        r#"
+        UPDATE payouts SET status = 'pending', tx_hash = NULL, signing_at = NULL, error_message = $1
+        WHERE id = ANY($2) AND status = 'signing'
+        "#,
+    )
+    .bind(reason)
+    .bind(payout_ids)
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+/// Hold signing payouts whose send cannot be settled from the wallet for an
+/// operator, their amounts still held
+async fn hold_for_review<'e>(executor: impl sqlx::PgExecutor<'e>, payout_ids: &[i64], reason: &str) -> Result<()> {
+    sqlx::query("UPDATE payouts SET status = 'review', error_message = $1 WHERE id = ANY($2) AND status = 'signing'")
+        .bind(reason)
+        .bind(payout_ids)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+/// Mark a signing or in review payout as failed
+async fn fail_payout(pool: &PgPool, payout_id: i64, error: &str) -> Result<()> {
+    let mut tx = pool.begin().await?;
+
+    // Update payout status
+    let payout: Option<Payout> = sqlx::query_as(
+        "UPDATE payouts SET status = 'failed', error_message = $1 WHERE id = $2 AND status IN ('signing', 'review') RETURNING *",
+    )
+    .bind(error)
+    .bind(payout_id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(payout) = payout else {
+        return Ok(());
+    };
+
+    // Release the held amount back to available
+    let release = Transfer::new(&payout.coin)
+        .payout(payout.id)
+        .memo("payout failed")
+        .transfer(Account::Pending(payout.miner_id), Account::Available(payout.miner_id), EntryKind::PayoutRelease, payout.amount);
+    ledger::post(&mut tx, &release).await?;
+
+    tx.commit().await?;
+
+    warn!("Payout failed: id={}, error={}", payout_id, error);
+    Ok(())
+}
+
+/// Move signing payouts sent in one transaction to `broadcast`, each with its
+/// share of the fee, and pay their held amounts: pending moves to paid and the
+/// pool pays the network fee. Payouts no longer signing or in review are left
+/// alone.
+async fn broadcast(tx: &mut Transaction<'_, Postgres>, payout_ids: &[i64], sent: &SentTransaction) -> Result<()> {
+    let payouts: Vec<Payout> = sqlx::query_as(
+        "SELECT * FROM payouts WHERE id = ANY($1) AND status IN ('signing', 'review') ORDER BY id FOR UPDATE"
+    )
+    .bind(payout_ids)
+    .fetch_all(&mut **tx)
+    .await?;
+    let Some(first) = payouts.first() else {
+        return Ok(());
+    };
+
+    let fees = split_fee(sent.fee, payouts.len(), &first.coin);
+    for (payout, fee) in payouts.iter().zip(fees) {
+        sqlx::query(
+            r#"
+            UPDATE payouts
+            SET status = 'broadcast',
+                tx_hash = $1,
+                fee = $2,
+                broadcast_at = NOW()
+            WHERE id = $3
+            "#,
+        )
+        .bind(&sent.tx_hash)
+        .bind(fee)
+        .bind(payout.id)
+        .execute(&mut **tx)
+        .await?;
+
+        let paid = Transfer::new(&payout.coin)
+            .payout(payout.id)
+            .memo(format!("payout sent in {}", sent.tx_hash))
+            .transfer(Account::Pending(payout.miner_id), Account::Paid(payout.miner_id), EntryKind::Debit, payout.amount)
+            .transfer(Account::Fees, Account::NetworkFees, EntryKind::Fee, fee.unwrap_or_default());
+        ledger::post(tx, &paid).await?;
+
+        info!(
+            "Payout broadcast: id={}, tx_hash={}, fee={}",
+            payout.id,
+            sent.tx_hash,
+            fee.map_or_else(|| "unknown".to_string(), |fee| fee.to_string())
+        );
+    }
+    Ok(())
+}
+
+/// Group payouts into batches of at most `max_batch`, oldest first. A batch
+/// pays an address at most once, as wallets take one amount per address.
+fn batches(payouts: Vec<QueuedPayout>, max_batch: usize) -> Vec<Vec<QueuedPayout>> {
+    let mut batches: Vec<Vec<QueuedPayout>> = Vec::new();
+    for payout in payouts {
+        let open = batches
+            .iter_mut()
+            .find(|b| b.len() < max_batch && b.iter().all(|p| p.address != payout.address));
+        match open {
+            Some(batch) => batch.push(payout),
+            None => batches.push(vec![payout]),
+        }
+    }
+    batches
+}
+
+/// Split a transaction fee evenly over its payouts, the rounding remainder on
+/// the first
+fn split_fee(fee: Option<Decimal>, payouts: usize, coin: &str) -> Vec<Option<Decimal>> {
+    let Some(fee) = fee else {
+        return vec![None; payouts];
+    };
+    let share = money::round_down(fee / Decimal::from(payouts), coin);
+    let mut fees = vec![Some(share); payouts];
+    fees[0] = Some(fee - share * Decimal::from(payouts - 1));
+    fees
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queued(id: i64, address: &str) -> QueuedPayout {
+        QueuedPayout { id, coin: "XMR".into(), amount: Decimal::ONE, address: address.into() }
+    }
+
+    fn ids(batches: &[Vec<QueuedPayout>]) -> Vec<Vec<i64>> {
+        batches.iter().map(|b| b.iter().map(|p| p.id).collect()).collect()
+    }
+
+    #[test]
+    fn test_batches_respect_limit_and_unique_addresses() {
+        let payouts = vec![queued(1, "a"), queued(2, "b"), queued(3, "a"), queued(4, "c"), queued(5, "d")];
+
+        assert_eq!(ids(&batches(payouts, 3)), vec![vec![1, 2, 4], vec![3, 5]]);
+        assert_eq!(ids(&batches(vec![queued(1, "a"), queued(2, "b")], 1)), vec![vec![1], vec![2]]);
+    }
+
+    #[test]
+    fn test_split_fee_adds_up() {
+        let fee: Decimal = "0.0001".parse().unwrap();
+        let fees = split_fee(Some(fee), 3, "LTC");
+
+        assert_eq!(fees.iter().map(|f| f.unwrap()).sum::<Decimal>(), fee);
+        assert_eq!(fees[1], Some("0.00003333".parse().unwrap()));
+        assert_eq!(split_fee(None, 2, "LTC"), vec![None, None]);
+    }
 }
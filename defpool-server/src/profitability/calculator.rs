@@ -61,13 +61,7 @@ where
         let price_btc = self.price_provider.get_price_btc(&target.coin).await?;
         let difficulty = self.difficulty_provider.get_difficulty(&target.coin).await?;
         
-        // Block rewards for different coins (should be configurable/dynamic in production)
-        let block_reward = match target.coin.as_str() {
-            "XMR" => 0.6,     // Monero block reward
-            "LTC" => 12.5,    // Litecoin block reward
-            "DOGE" => 10000.0, // Dogecoin block reward (high but low value)
-            _ => 1.0,         // Default fallback
-        };
+        let block_reward = self.difficulty_provider.get_block_reward(&target.coin).await?;
 
         // Block times for different coins (seconds)
         let block_time_seconds = match target.coin.as_str() {
@@ -114,6 +108,9 @@ mod tests {
                 coin: "XMR".to_string(),
                 algorithm: "RandomX".to_string(),
                 daemon_rpc_url: None,
+                daemon_rpc_user: None,
+                daemon_rpc_password: None,
+                backup_addresses: vec![],
             },
         ];
 
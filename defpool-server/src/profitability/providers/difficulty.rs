@@ -8,6 +8,21 @@ use tracing::{debug, warn};
 pub trait DifficultyProvider: Send + Sync {
     /// Get the current network difficulty for a coin
     async fn get_difficulty(&self, coin: &str) -> Result<f64>;
+
+    /// Get the current block reward (subsidy, without fees) for a coin
+    async fn get_block_reward(&self, coin: &str) -> Result<f64> {
+        Ok(default_block_reward(coin))
+    }
+}
+
+/// Block rewards for different coins (should be dynamic in production)
+pub fn default_block_reward(coin: &str) -> f64 {
+    match coin {
+        "XMR" => 0.6,      // Monero tail emission
+        "LTC" => 6.25,     // Litecoin after the 2023 halving
+        "DOGE" => 10000.0, // Dogecoin block reward (high but low value)
+        _ => 1.0,          // Default fallback
+    }
 }
 
 /// MoneroBlocks API difficulty provider
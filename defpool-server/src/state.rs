@@ -1,16 +1,38 @@
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
-use std::time::Instant;
+use std::time::{Instant, SystemTime};
+use tokio::sync::broadcast;
+use tracing::warn;
 use crate::config::{Config, MiningTarget};
+use crate::events::ProxyEvent;
 use crate::profitability::ProfitabilityScore;
 use crate::accounting::AccountingService;
+use crate::address::AddressValidator;
 use crate::payout::PayoutService;
+use crate::auth::{MinerPinStore, ProxyKeyStore};
+use crate::blocks::BlockTracker;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Target {
+    pub name: String,
     pub address: String,
+    pub backup_addresses: Vec<String>,
     pub pubkey: Option<String>, // Optional for SV1
     pub protocol: String, // "sv1" or "sv2"
+    pub algorithm: String, // e.g. "RandomX", "SHA256d"
+}
+
+/// Reachability of a target's endpoints, from the health checker
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TargetHealth {
+    pub target_name: String,
+    pub healthy: bool,
+    pub consecutive_failures: u32,
+    /// First endpoint that accepted a connection
+    pub reachable_endpoint: Option<String>,
+    pub last_error: Option<String>,
+    pub last_checked: SystemTime,
 }
 
 #[derive(Clone)]
@@ -18,9 +40,15 @@ pub struct AppState {
     pub current_target: Arc<RwLock<String>>,
     pub last_switch_time: Arc<RwLock<Instant>>,
     pub profitability_scores: Arc<RwLock<Vec<ProfitabilityScore>>>,
+    pub target_health: Arc<RwLock<HashMap<String, TargetHealth>>>,
     pub targets: Vec<MiningTarget>,
     pub accounting_service: Arc<AccountingService>,
     pub payout_service: Arc<PayoutService>,
+    pub proxy_events: broadcast::Sender<ProxyEvent>,
+    pub proxy_keys: Arc<ProxyKeyStore>,
+    pub miner_pins: Arc<MinerPinStore>,
+    pub blocks: Arc<BlockTracker>,
+    pub addresses: Arc<AddressValidator>,
 }
 
 impl AppState {
@@ -28,6 +56,10 @@ impl AppState {
         config: Config,
         accounting_service: Arc<AccountingService>,
         payout_service: Arc<PayoutService>,
+        proxy_keys: Arc<ProxyKeyStore>,
+        miner_pins: Arc<MinerPinStore>,
+        blocks: Arc<BlockTracker>,
+        addresses: Arc<AddressValidator>,
     ) -> Self {
         let initial_target = config.targets.first()
             .expect("At least one mining target must be configured")
@@ -37,9 +69,15 @@ impl AppState {
             current_target: Arc::new(RwLock::new(initial_target)),
             last_switch_time: Arc::new(RwLock::new(Instant::now())),
             profitability_scores: Arc::new(RwLock::new(Vec::new())),
+            target_health: Arc::new(RwLock::new(HashMap::new())),
             targets: config.targets,
             accounting_service,
             payout_service,
+            proxy_events: broadcast::channel(64).0,
+            proxy_keys,
+            miner_pins,
+            blocks,
+            addresses,
         }
     }
 
@@ -50,19 +88,49 @@ impl AppState {
             .expect("Current target not found in configuration");
 
         Target {
+            name: mining_target.name.clone(),
             address: mining_target.address.clone(),
+            backup_addresses: mining_target.backup_addresses.clone(),
             pubkey: None, // V1 doesn't need pubkey
             protocol: "sv1".to_string(),
+            algorithm: mining_target.algorithm.clone(),
         }
     }
 
+    /// Switch to a configured target and tell the proxies; other names are ignored
     pub fn switch_target(&self, new_target: String) {
-        let mut current = self.current_target.write().unwrap();
-        *current = new_target;
+        if !self.targets.iter().any(|t| t.name == new_target) {
+            warn!("Not switching to {}: no such target is configured", new_target);
+            return;
+        }
+
+        *self.current_target.write().unwrap() = new_target;
         *self.last_switch_time.write().unwrap() = Instant::now();
+
+        self.publish(ProxyEvent::TargetChanged {
+            target: self.get_current_target(),
+        });
+    }
+
+    /// Broadcast an event to all subscribed proxies
+    pub fn publish(&self, event: ProxyEvent) {
+        // Sending only fails when no proxy is subscribed
+        let _ = self.proxy_events.send(event);
     }
 
     pub fn update_scores(&self, scores: Vec<ProfitabilityScore>) {
         *self.profitability_scores.write().unwrap() = scores;
     }
+
+    pub fn update_health(&self, health: TargetHealth) {
+        self.target_health.write().unwrap().insert(health.target_name.clone(), health);
+    }
+
+    /// Targets are assumed healthy until the first failed check
+    pub fn is_target_healthy(&self, target_name: &str) -> bool {
+        self.target_health.read().unwrap()
+            .get(target_name)
+            .map(|health| health.healthy)
+            .unwrap_or(true)
+    }
 }
@@ -0,0 +1,21 @@
+use crate::blocks::BlockTracker;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info};
+
+/// Start the background task confirming or orphaning found blocks
+pub fn start_block_maturity(tracker: Arc<BlockTracker>, interval_secs: u64) {
+    tokio::spawn(async move {
+        let interval = Duration::from_secs(interval_secs);
+
+        info!("Starting block maturity checks (interval: {}s)", interval_secs);
+
+        loop {
+            tokio::time::sleep(interval).await;
+
+            if let Err(e) = tracker.check_pending().await {
+                error!("Failed to check pending blocks: {:#}", e);
+            }
+        }
+    });
+}
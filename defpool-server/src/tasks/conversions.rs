@@ -0,0 +1,21 @@
+use crate::exchange::ConversionService;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info};
+
+/// Start the background task selling auto-exchange miners' coins on the exchange
+pub fn start_conversions(service: Arc<ConversionService>, interval_secs: u64) {
+    tokio::spawn(async move {
+        let interval = Duration::from_secs(interval_secs);
+
+        info!("Starting conversions (interval: {}s)", interval_secs);
+
+        loop {
+            tokio::time::sleep(interval).await;
+
+            if let Err(e) = service.run().await {
+                error!("Failed to run conversions: {:#}", e);
+            }
+        }
+    });
+}
@@ -0,0 +1,82 @@
+use crate::state::{AppState, TargetHealth};
+use crate::tasks::profitability_monitor::select_target;
+use crate::config::{Config, MiningTarget};
+use std::time::{Duration, SystemTime};
+use tokio::net::TcpStream;
+use tracing::{info, warn};
+
+/// Failed checks in a row before a target is marked unhealthy
+const FAILURES_BEFORE_UNHEALTHY: u32 = 2;
+
+/// Start the background target health checker
+pub fn start_health_checker(state: AppState, config: Config) {
+    tokio::spawn(async move {
+        let interval = Duration::from_secs(config.health_check_interval_secs);
+        let timeout = Duration::from_secs(config.health_check_timeout_secs);
+
+        info!("Starting target health checker (interval: {}s, timeout: {}s)",
+            config.health_check_interval_secs, config.health_check_timeout_secs);
+
+        loop {
+            let mut current_became_unhealthy = false;
+
+            for target in &state.targets {
+                let previous_failures = state.target_health.read().unwrap()
+                    .get(&target.name)
+                    .map(|h| h.consecutive_failures)
+                    .unwrap_or(0);
+                let health = check_target(target, timeout, previous_failures).await;
+
+                if state.is_target_healthy(&target.name) != health.healthy {
+                    if health.healthy {
+                        info!("Target {} is reachable again via {}", target.name,
+                            health.reachable_endpoint.as_deref().unwrap_or_default());
+                    } else {
+                        warn!("Target {} is unhealthy: {}", target.name,
+                            health.last_error.as_deref().unwrap_or_default());
+                        current_became_unhealthy |= *state.current_target.read().unwrap() == target.name;
+                    }
+                }
+                state.update_health(health);
+            }
+
+            if current_became_unhealthy {
+                select_target(&state, config.switch_threshold_percent);
+            }
+
+            tokio::time::sleep(interval).await;
+        }
+    });
+}
+
+/// Try each endpoint in order until one accepts a TCP connection
+async fn check_target(target: &MiningTarget, timeout: Duration, previous_failures: u32) -> TargetHealth {
+    let mut last_error = None;
+
+    for endpoint in target.endpoints() {
+        match tokio::time::timeout(timeout, TcpStream::connect(endpoint)).await {
+            Ok(Ok(_)) => {
+                return TargetHealth {
+                    target_name: target.name.clone(),
+                    healthy: true,
+                    consecutive_failures: 0,
+                    reachable_endpoint: Some(endpoint.clone()),
+                    last_error: None,
+                    last_checked: SystemTime::now(),
+                };
+            }
+            Ok(Err(e)) => last_error = Some(format!("{}: {}", endpoint, e)),
+            Err(_) => last_error = Some(format!("{}: connect timed out", endpoint)),
+        }
+    }
+
+    let consecutive_failures = previous_failures + 1;
+    TargetHealth {
+        target_name: target.name.clone(),
+        healthy: consecutive_failures < FAILURES_BEFORE_UNHEALTHY,
+        consecutive_failures,
+        reachable_endpoint: None,
+        last_error,
+        last_checked: SystemTime::now(),
+    }
+}
@@ -1,3 +1,7 @@
 pub mod profitability_monitor;
+pub mod health_checker;
 pub mod payout_processor;
-pub mod balance_updater;
+pub mod share_payments;
+pub mod block_maturity;
+pub mod payout_scheduler;
+pub mod conversions;
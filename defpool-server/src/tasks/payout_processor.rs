@@ -3,16 +3,29 @@ use std::sync::Arc;
 use std::time::Duration;
 use tracing::{info, error};
 
-/// Start the background payout processing task
+/// Start the background payout processing task. Payouts a previous run left
+/// signing are recovered on startup, before anything is sent.
 pub fn start_payout_processor(payout_service: Arc<PayoutService>) {
     tokio::spawn(async move {
         let interval = Duration::from_secs(60); // Check every minute
 
         info!("Starting payout processor (interval: 60s)");
 
+        if let Err(e) = payout_service.recover_payouts().await {
+            error!("Failed to recover payouts: {:#}", e);
+        }
+
         loop {
             tokio::time::sleep(interval).await;
 
+            if let Err(e) = payout_service.recover_payouts().await {
+                error!("Failed to recover payouts: {:#}", e);
+            }
+
+            if let Err(e) = payout_service.confirm_payouts().await {
+                error!("Failed to confirm payouts: {:#}", e);
+            }
+
             match payout_service.process_pending_payouts().await {
                 Ok(_) => {
                     // Success - no need to log unless there were payouts
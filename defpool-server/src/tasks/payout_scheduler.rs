@@ -0,0 +1,21 @@
+use crate::payout::PayoutScheduler;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info};
+
+/// Start the background task creating payouts for miners over their threshold
+pub fn start_payout_scheduler(scheduler: Arc<PayoutScheduler>, interval_secs: u64) {
+    tokio::spawn(async move {
+        let interval = Duration::from_secs(interval_secs);
+
+        info!("Starting payout scheduler (interval: {}s)", interval_secs);
+
+        loop {
+            tokio::time::sleep(interval).await;
+
+            if let Err(e) = scheduler.run().await {
+                error!("Failed to schedule payouts: {:#}", e);
+            }
+        }
+    });
+}
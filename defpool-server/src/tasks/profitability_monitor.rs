@@ -4,7 +4,7 @@ use crate::profitability::providers::{PriceProvider, DifficultyProvider};
 use crate::config::Config;
 use std::sync::Arc;
 use std::time::Duration;
-use tracing::{info, error};
+use tracing::{info, error, warn};
 
 /// Start the background profitability monitoring task
 pub fn start_profitability_monitor<P, D>(
@@ -27,36 +27,8 @@ pub fn start_profitability_monitor<P, D>(
 
             match calculator.calculate_all().await {
                 Ok(scores) => {
-                    state.update_scores(scores.clone());
-
-                    if let Some(best) = scores.iter()
-                        .max_by(|a, b| a.score.partial_cmp(&b.score).unwrap_or(std::cmp::Ordering::Equal))
-                    {
-                        let current_target = state.current_target.read().unwrap().clone();
-                        
-                        // Check if we should switch
-                        if best.target_name != current_target {
-                            let current_score = scores.iter()
-                                .find(|s| s.target_name == current_target)
-                                .map(|s| s.score)
-                                .unwrap_or(0.0);
-
-                            let improvement_percent = ((best.score - current_score) / current_score) * 100.0;
-
-                            if improvement_percent >= threshold_percent {
-                                info!(
-                                    "Switching from {} to {} (improvement: {:.2}%)",
-                                    current_target, best.target_name, improvement_percent
-                                );
-                                state.switch_target(best.target_name.clone());
-                            } else {
-                                info!(
-                                    "Target {} is better but below threshold ({:.2}% < {}%)",
-                                    best.target_name, improvement_percent, threshold_percent
-                                );
-                            }
-                        }
-                    }
+                    state.update_scores(scores);
+                    select_target(&state, threshold_percent);
                 }
                 Err(e) => {
                     error!("Failed to calculate profitability: {}", e);
@@ -65,3 +37,63 @@ pub fn start_profitability_monitor<P, D>(
         }
     });
 }
+
+/// Switch to the most profitable healthy target.
+///
+/// Unhealthy targets are never chosen, and leaving an unhealthy current
+/// target ignores the switch threshold.
+pub fn select_target(state: &AppState, threshold_percent: f64) {
+    let scores = state.profitability_scores.read().unwrap().clone();
+    let current_target = state.current_target.read().unwrap().clone();
+    let current_healthy = state.is_target_healthy(&current_target);
+
+    let best = scores.iter()
+        .filter(|s| state.is_target_healthy(&s.target_name))
+        .max_by(|a, b| a.score.partial_cmp(&b.score).unwrap_or(std::cmp::Ordering::Equal))
+        .map(|s| (s.target_name.clone(), s.score));
+
+    let Some((best_name, best_score)) = best.or_else(|| {
+        // No scores yet: any healthy target beats an unreachable one
+        if current_healthy {
+            return None;
+        }
+        state.targets.iter()
+            .find(|t| state.is_target_healthy(&t.name))
+            .map(|t| (t.name.clone(), 0.0))
+    }) else {
+        if !current_healthy {
+            warn!("Current target {} is unhealthy and no healthy target is available", current_target);
+        }
+        return;
+    };
+
+    if best_name == current_target {
+        return;
+    }
+
+    if !current_healthy {
+        warn!("Current target {} is unhealthy, switching to {}", current_target, best_name);
+        state.switch_target(best_name);
+        return;
+    }
+
+    let current_score = scores.iter()
+        .find(|s| s.target_name == current_target)
+        .map(|s| s.score)
+        .unwrap_or(0.0);
+
+    let improvement_percent = ((best_score - current_score) / current_score) * 100.0;
+
+    if improvement_percent >= threshold_percent {
+        info!(
+            "Switching from {} to {} (improvement: {:.2}%)",
+            current_target, best_name, improvement_percent
+        );
+        state.switch_target(best_name);
+    } else {
+        info!(
+            "Target {} is better but below threshold ({:.2}% < {}%)",
+            best_name, improvement_percent, threshold_percent
+        );
+    }
+}
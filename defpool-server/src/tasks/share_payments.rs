@@ -0,0 +1,21 @@
+use crate::payout::BalanceCalculator;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info};
+
+/// Start the background task paying PPS and FPPS miners for their shares
+pub fn start_share_payments(calculator: Arc<BalanceCalculator>, interval_secs: u64) {
+    tokio::spawn(async move {
+        let interval = Duration::from_secs(interval_secs);
+
+        info!("Starting per-share payments (interval: {}s)", interval_secs);
+
+        loop {
+            tokio::time::sleep(interval).await;
+
+            if let Err(e) = calculator.credit_share_payments().await {
+                error!("Failed to credit share payments: {:#}", e);
+            }
+        }
+    });
+}